@@ -31,7 +31,21 @@ impl AABB {
             _max: big,
         }
     }
+    pub fn centroid(&self) -> Point3 {
+        (self._min + self._max) / 2.0
+    }
+    pub fn area(&self) -> f64 {
+        let d = self._max - self._min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
     pub fn hit(&self, r: &Ray, tmin: f64, tmax: f64) -> bool {
+        self.clip(r, tmin, tmax).is_some()
+    }
+    // the part of [tmin, tmax] for which the ray is inside the box
+    pub fn clip(&self, r: &Ray, tmin: f64, tmax: f64) -> Option<(f64, f64)> {
+        // the interval shrinks with every slab, so it has to be carried across the axes
+        let mut tmin = tmin;
+        let mut tmax = tmax;
         for a in 0..3 {
             let inv_d = 1.0 / r.dir[a];
             let mut t0 = (self._min[a] - r.orig[a]) * inv_d;
@@ -39,12 +53,12 @@ impl AABB {
             if inv_d < 0.0 {
                 swap(&mut t0, &mut t1);
             }
-            t0 = if t0 > tmin { t0 } else { tmin };
-            t1 = if t1 < tmax { t1 } else { tmax };
-            if t1 <= t0 {
-                return false;
+            tmin = if t0 > tmin { t0 } else { tmin };
+            tmax = if t1 < tmax { t1 } else { tmax };
+            if tmax <= tmin {
+                return None;
             }
         }
-        true
+        Some((tmin, tmax))
     }
 }
@@ -1,11 +1,121 @@
 use crate::{
     aabb::AABB,
-    hittable::HitTable,
+    hittable::{HitRecord, HitTable},
     material::Material,
+    ray::Ray,
+    rtweekend::clamp,
+    sampler::Sampler,
+    triangle::closest_point_on_triangle,
     vec3::{Point3, Vec3},
 };
 use std::sync::Arc;
 
+// An axis-aligned rectangle spanning [a0, a1] x [b0, b1] on axes `a` and `b` at `k` on the third
+// axis. The three rectangle types share their geometry through it.
+struct Rect {
+    axes: (usize, usize, usize),
+    a0: f64,
+    a1: f64,
+    b0: f64,
+    b1: f64,
+    k: f64,
+}
+
+impl Rect {
+    fn point(&self, a: f64, b: f64, c: f64) -> Point3 {
+        let mut p = [0.0; 3];
+        p[self.axes.0] = a;
+        p[self.axes.1] = b;
+        p[self.axes.2] = c;
+        Point3::new(p[0], p[1], p[2])
+    }
+
+    // the ray parameter and the in-plane coordinates where `r` crosses the rectangle
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let (a, b, c) = self.axes;
+        let t = (self.k - r.orig[c]) / r.dir[c];
+        if t.is_nan() || t < t_min || t > t_max {
+            return None;
+        }
+        let x = r.orig[a] + t * r.dir[a];
+        let y = r.orig[b] + t * r.dir[b];
+        if x < self.a0 || x > self.a1 || y < self.b0 || y > self.b1 {
+            return None;
+        }
+        Some((t, x, y))
+    }
+
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        mp: &Arc<dyn Material>,
+    ) -> bool {
+        let (t, x, y) = match self.intersect(r, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        rec.u = (x - self.a0) / (self.a1 - self.a0);
+        rec.v = (y - self.b0) / (self.b1 - self.b0);
+        rec.t = t;
+        let outward_normal = self.point(0.0, 0.0, 1.0);
+        rec.set_face_normal(r, &outward_normal);
+        rec.mat_ptr = mp.clone();
+        rec.p = r.at(t);
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        AABB::new(
+            self.point(self.a0, self.b0, self.k - 0.0001),
+            self.point(self.a1, self.b1, self.k + 0.0001),
+        )
+    }
+
+    fn distance(&self, other_center: &Point3) -> f64 {
+        let (a, b, _) = self.axes;
+        let closest = self.point(
+            clamp(other_center[a], self.a0, self.a1),
+            clamp(other_center[b], self.b0, self.b1),
+            self.k,
+        );
+        other_center.distance(closest)
+    }
+
+    // uniform over the area, converted to solid angle from `o`
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        let (t, _, _) = match self.intersect(
+            &Ray {
+                orig: *o,
+                dir: *v,
+                time: 0.0,
+                wavelength: None,
+            },
+            0.001,
+            f64::INFINITY,
+        ) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+        let area = (self.a1 - self.a0) * (self.b1 - self.b0);
+        let distance_squared = t * t * v.squared_length();
+        let cosine = v[self.axes.2].abs() / v.length();
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, o: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (s, t) = sampler.get_2d();
+        let p = self.point(
+            self.a0 + s * (self.a1 - self.a0),
+            self.b0 + t * (self.b1 - self.b0),
+            self.k,
+        );
+        p - *o
+    }
+}
+
 pub struct XYRect {
     pub mp: Arc<dyn Material>,
     pub x0: f64,
@@ -26,42 +136,328 @@ impl XYRect {
             k: _k,
         }
     }
+
+    fn rect(&self) -> Rect {
+        Rect {
+            axes: (0, 1, 2),
+            a0: self.x0,
+            a1: self.x1,
+            b0: self.y0,
+            b1: self.y1,
+            k: self.k,
+        }
+    }
 }
 
 impl HitTable for XYRect {
-    fn hit(
-        &self,
-        r: &crate::ray::Ray,
-        t0: f64,
-        t1: f64,
-        rec: &mut crate::hittable::HitRecord,
-    ) -> bool {
-        let t = (self.k - r.orig.z) / r.dir.z;
-        if t < t0 || t > t1 {
-            return false;
+    fn hit(&self, r: &Ray, t0: f64, t1: f64, rec: &mut HitRecord) -> bool {
+        self.rect().hit(r, t0, t1, rec, &self.mp)
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        *output_box = self.rect().bounding_box();
+        true
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        self.rect().distance(other_center)
+    }
+    fn is_light(&self) -> bool {
+        self.mp.is_emissive()
+    }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        self.rect().pdf_value(o, v)
+    }
+    fn random(&self, o: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.rect().random(o, sampler)
+    }
+}
+
+pub struct XZRect {
+    pub mp: Arc<dyn Material>,
+    pub x0: f64,
+    pub x1: f64,
+    pub z0: f64,
+    pub z1: f64,
+    pub k: f64,
+}
+
+impl XZRect {
+    pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, mat: Arc<dyn Material>) -> Self {
+        Self {
+            mp: mat,
+            x0,
+            x1,
+            z0,
+            z1,
+            k,
         }
-        let x = r.orig.x + t * r.dir.x;
-        let y = r.orig.y + t * r.dir.y;
-        if x < self.x0 || x > self.x1 || y < self.y0 || y > self.y1 {
-            return false;
+    }
+
+    fn rect(&self) -> Rect {
+        Rect {
+            axes: (0, 2, 1),
+            a0: self.x0,
+            a1: self.x1,
+            b0: self.z0,
+            b1: self.z1,
+            k: self.k,
         }
-        rec.u = (x - self.x0) / (self.x1 - self.x0);
-        rec.v = (y - self.y0) / (self.y1 - self.y0);
+    }
+}
+
+impl HitTable for XZRect {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64, rec: &mut HitRecord) -> bool {
+        self.rect().hit(r, t0, t1, rec, &self.mp)
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        *output_box = self.rect().bounding_box();
+        true
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        self.rect().distance(other_center)
+    }
+    fn is_light(&self) -> bool {
+        self.mp.is_emissive()
+    }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        self.rect().pdf_value(o, v)
+    }
+    fn random(&self, o: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.rect().random(o, sampler)
+    }
+}
+
+pub struct YZRect {
+    pub mp: Arc<dyn Material>,
+    pub y0: f64,
+    pub y1: f64,
+    pub z0: f64,
+    pub z1: f64,
+    pub k: f64,
+}
+
+impl YZRect {
+    pub fn new(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, mat: Arc<dyn Material>) -> Self {
+        Self {
+            mp: mat,
+            y0,
+            y1,
+            z0,
+            z1,
+            k,
+        }
+    }
+
+    fn rect(&self) -> Rect {
+        Rect {
+            axes: (1, 2, 0),
+            a0: self.y0,
+            a1: self.y1,
+            b0: self.z0,
+            b1: self.z1,
+            k: self.k,
+        }
+    }
+}
+
+impl HitTable for YZRect {
+    fn hit(&self, r: &Ray, t0: f64, t1: f64, rec: &mut HitRecord) -> bool {
+        self.rect().hit(r, t0, t1, rec, &self.mp)
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        *output_box = self.rect().bounding_box();
+        true
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        self.rect().distance(other_center)
+    }
+    fn is_light(&self) -> bool {
+        self.mp.is_emissive()
+    }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        self.rect().pdf_value(o, v)
+    }
+    fn random(&self, o: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.rect().random(o, sampler)
+    }
+}
+
+// Parallelogram with corner `q` and edges `u` and `v`, facing along u x v.
+pub struct Quad {
+    pub q: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub mp: Arc<dyn Material>,
+    normal: Vec3,
+    d: f64,
+    // n / |n|^2 for n = u x v, to get the plane coordinates of a point
+    w: Vec3,
+    area: f64,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Self {
+        let n = u.cross(v);
+        let normal = n.unit();
+        Self {
+            q,
+            u,
+            v,
+            mp: mat,
+            normal,
+            d: normal * q,
+            w: n / (n * n),
+            area: n.length(),
+        }
+    }
+
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let denom = self.normal * r.dir;
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = (self.d - self.normal * r.orig) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let planar = r.at(t) - self.q;
+        let alpha = self.w * planar.cross(self.v);
+        let beta = self.w * self.u.cross(planar);
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some((t, alpha, beta))
+    }
+}
+
+impl HitTable for Quad {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (t, alpha, beta) = match self.intersect(r, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
         rec.t = t;
-        let outward_normal = Vec3::new(0.0, 0.0, 0.1);
-        rec.set_face_normal(r, &outward_normal);
-        rec.mat_ptr = self.mp.clone();
         rec.p = r.at(t);
+        rec.u = alpha;
+        rec.v = beta;
+        rec.set_face_normal(r, &self.normal);
+        rec.mat_ptr = self.mp.clone();
         true
     }
-    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut crate::aabb::AABB) -> bool {
-        *output_box = AABB::new(
-            Point3::new(self.x0, self.y0, self.k - 0.0001),
-            Point3::new(self.x1, self.y1, self.k + 0.0001),
-        );
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        let pad = Vec3::new(0.0001, 0.0001, 0.0001);
+        let corners = [self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let mut bbox = AABB::new(self.q - pad, self.q + pad);
+        for corner in &corners {
+            bbox = AABB::surrounding_box(&bbox, &AABB::new(*corner - pad, *corner + pad));
+        }
+        *output_box = bbox;
         true
     }
-    fn distance(&self, _other_center: &Point3) -> f64 {
-        0.0
+    fn distance(&self, other_center: &Point3) -> f64 {
+        let (a, b, c, d) = (
+            self.q,
+            self.q + self.u,
+            self.q + self.u + self.v,
+            self.q + self.v,
+        );
+        let first = closest_point_on_triangle(other_center, &a, &b, &c);
+        let second = closest_point_on_triangle(other_center, &a, &c, &d);
+        other_center
+            .distance(first)
+            .min(other_center.distance(second))
+    }
+    fn is_light(&self) -> bool {
+        self.mp.is_emissive()
+    }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        let (t, _, _) = match self.intersect(
+            &Ray {
+                orig: *o,
+                dir: *v,
+                time: 0.0,
+                wavelength: None,
+            },
+            0.001,
+            f64::INFINITY,
+        ) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+        let distance_squared = t * t * v.squared_length();
+        let cosine = (*v * self.normal).abs() / v.length();
+        distance_squared / (cosine * self.area)
+    }
+    fn random(&self, o: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (s, t) = sampler.get_2d();
+        self.q + self.u * s + self.v * t - *o
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, sampler::IndependentSampler, vec3::Color};
+
+    #[test]
+    fn test_rects_match_quads() {
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::new(Color::ones()));
+        let pairs: Vec<(Box<dyn HitTable>, Quad)> = vec![
+            (
+                Box::new(XYRect::new(-1.0, 2.0, 0.0, 1.0, 3.0, mat.clone())),
+                Quad::new(
+                    Point3::new(-1.0, 0.0, 3.0),
+                    Vec3::new(3.0, 0.0, 0.0),
+                    Vec3::new(0.0, 1.0, 0.0),
+                    mat.clone(),
+                ),
+            ),
+            (
+                Box::new(XZRect::new(-1.0, 2.0, 0.0, 1.0, 3.0, mat.clone())),
+                Quad::new(
+                    Point3::new(-1.0, 3.0, 0.0),
+                    Vec3::new(3.0, 0.0, 0.0),
+                    Vec3::new(0.0, 0.0, 1.0),
+                    mat.clone(),
+                ),
+            ),
+            (
+                Box::new(YZRect::new(-1.0, 2.0, 0.0, 1.0, 3.0, mat.clone())),
+                Quad::new(
+                    Point3::new(3.0, -1.0, 0.0),
+                    Vec3::new(0.0, 3.0, 0.0),
+                    Vec3::new(0.0, 0.0, 1.0),
+                    mat.clone(),
+                ),
+            ),
+        ];
+        let o = Point3::new(0.3, 0.4, 0.5);
+        let mut sampler = IndependentSampler::new(5);
+        for (rect, quad) in &pairs {
+            for i in 0..20 {
+                sampler.start_sample(0, 0, i);
+                let dir = rect.random(&o, &mut sampler);
+                let r = Ray {
+                    orig: o,
+                    dir,
+                    time: 0.0,
+                    wavelength: None,
+                };
+                let mut rec_rect = HitRecord::new(mat.clone());
+                let mut rec_quad = HitRecord::new(mat.clone());
+                assert!(rect.hit(&r, 0.001, f64::INFINITY, &mut rec_rect));
+                assert!(quad.hit(&r, 0.001, f64::INFINITY, &mut rec_quad));
+                assert!((rec_rect.t - rec_quad.t).abs() < 1e-9);
+                assert!((rec_rect.u - rec_quad.u).abs() < 1e-9);
+                assert!((rec_rect.v - rec_quad.v).abs() < 1e-9);
+                assert!((rec_rect.normal - rec_quad.normal).length() < 1e-9);
+                assert!((rec_rect.normal.length() - 1.0).abs() < 1e-9);
+                let pdf = rect.pdf_value(&o, &dir);
+                assert!(pdf > 0.0);
+                assert!((pdf - quad.pdf_value(&o, &dir)).abs() < 1e-9 * pdf);
+            }
+            let far = Point3::new(10.0, 10.0, 10.0);
+            assert!((rect.distance(&far) - quad.distance(&far)).abs() < 1e-9);
+            assert_eq!(rect.pdf_value(&o, &Vec3::new(-1.0, -1.0, -1.0)), 0.0);
+        }
     }
 }
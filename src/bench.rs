@@ -0,0 +1,88 @@
+use crate::{
+    bvh::{BVHNode, FlatBVH},
+    camera::Camera,
+    hittable::{HitRecord, HitTable},
+    hittablelist::HitTableList,
+    material::Lambertian,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{sample_unit_vector, Color},
+};
+use std::{f64::INFINITY, sync::Arc, time::Instant};
+
+// trace every ray once, returning the number of hits and the elapsed seconds
+fn trace(world: &dyn HitTable, rays: &[Ray]) -> (usize, f64) {
+    let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+    let start = Instant::now();
+    let mut hits = 0;
+    for r in rays {
+        if world.hit(r, 0.001, INFINITY, &mut rec) {
+            hits += 1;
+        }
+    }
+    (hits, start.elapsed().as_secs_f64())
+}
+
+// Compare the pointer tree and the flattened BVH on one scene, with one camera ray per pixel
+// and one diffuse bounce from every camera ray that hits something.
+pub fn bench_bvh(
+    name: &str,
+    world: &HitTableList,
+    cam: &Camera,
+    image_width: u32,
+    image_height: u32,
+    max_leaf_size: usize,
+    sampler: &mut dyn Sampler,
+) {
+    let start = Instant::now();
+    let tree = BVHNode::new(&world.objects, 0.0, 0.1, max_leaf_size);
+    let tree_build = start.elapsed().as_secs_f64();
+    let start = Instant::now();
+    let flat = FlatBVH::new(&world.objects, 0.0, 0.1, max_leaf_size);
+    let flat_build = start.elapsed().as_secs_f64();
+
+    let mut rays = Vec::with_capacity(2 * (image_width * image_height) as usize);
+    let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+    for y in 0..image_height {
+        for x in 0..image_width {
+            let u = x as f64 / (image_width - 1) as f64;
+            let v = (image_height - y) as f64 / (image_height - 1) as f64;
+            sampler.start_sample(x, y, 0);
+            // the built-in scenes are static, so the shutter sample does not matter
+            let r = cam.get_ray(u, v, sampler.get_2d(), 0.0);
+            if tree.hit(&r, 0.001, INFINITY, &mut rec) {
+                rays.push(Ray {
+                    orig: rec.p,
+                    dir: rec.normal + sample_unit_vector(sampler.get_2d()),
+                    time: r.time,
+                    wavelength: r.wavelength,
+                });
+            }
+            rays.push(r);
+        }
+    }
+
+    println!(
+        "{}: {} objects, {} rays",
+        name,
+        world.objects.len(),
+        rays.len()
+    );
+    for (kind, bvh, build, stats) in [
+        ("tree", &tree as &dyn HitTable, tree_build, tree.stats()),
+        ("flat", &flat as &dyn HitTable, flat_build, flat.stats()),
+    ]
+    .iter()
+    {
+        let (hits, secs) = trace(*bvh, &rays);
+        println!(
+            "  {}: build {:.3}s, trace {:.3}s ({:.2} Mrays/s), {} hits",
+            kind,
+            build,
+            secs,
+            rays.len() as f64 / secs / 1e6,
+            hits
+        );
+        println!("    {}", stats);
+    }
+}
@@ -2,117 +2,510 @@ use crate::{
     aabb::AABB,
     hittable::{HitRecord, HitTable},
     ray::Ray,
-    rtweekend::random_int,
     vec3::Point3,
 };
-use std::{cmp::Ordering, sync::Arc};
+use std::{fmt, sync::Arc};
 
-#[derive(Clone)]
-pub struct BVHNode {
-    left: Arc<dyn HitTable>,
-    right: Arc<dyn HitTable>,
-    bvhbox: AABB,
+// number of buckets the centroid bounds are divided into when searching for a split
+const SAH_BINS: usize = 12;
+// cost of visiting a node relative to intersecting a primitive
+const TRAVERSAL_COST: f64 = 0.125;
+
+// a primitive as seen by the builder, `index` points back into the caller's list
+pub struct BuildPrim {
+    pub index: usize,
+    pub bbox: AABB,
+    pub centroid: Point3,
 }
 
-impl BVHNode {
-    pub fn new(
-        objects: &mut Vec<Arc<dyn HitTable>>,
+impl BuildPrim {
+    pub fn new(index: usize, bbox: AABB) -> Self {
+        Self {
+            index,
+            centroid: bbox.centroid(),
+            bbox,
+        }
+    }
+}
+
+// Tree produced by `build_sah`. Leaves cover prims[start..start + count] of the reordered slice.
+pub enum BuildNode {
+    Leaf {
+        bbox: AABB,
         start: usize,
-        end: usize,
-        time0: f64,
-        time1: f64,
-    ) -> Self {
-        let axis = random_int(0, 3);
-        let mut tmp: BVHNode;
-        let comparator = if axis == 0 {
-            box_x_compare
-        } else if axis == 1 {
-            box_y_compare
-        } else {
-            box_z_compare
+        count: usize,
+    },
+    Interior {
+        bbox: AABB,
+        // the axis the children were separated along
+        axis: usize,
+        children: Box<[BuildNode; 2]>,
+    },
+}
+
+impl BuildNode {
+    pub fn bbox(&self) -> &AABB {
+        match self {
+            BuildNode::Leaf { bbox, .. } => bbox,
+            BuildNode::Interior { bbox, .. } => bbox,
+        }
+    }
+}
+
+fn union(a: &Option<AABB>, b: &AABB) -> Option<AABB> {
+    match a {
+        Some(a) => Some(AABB::surrounding_box(a, b)),
+        None => Some(b.clone()),
+    }
+}
+
+// Binned surface area heuristic builder. `prims` must not be empty and is reordered in place.
+pub fn build_sah(prims: &mut [BuildPrim], max_leaf_size: usize) -> BuildNode {
+    build_range(prims, 0, max_leaf_size.max(1))
+}
+
+fn build_range(prims: &mut [BuildPrim], offset: usize, max_leaf_size: usize) -> BuildNode {
+    let n = prims.len();
+    let mut bbox = prims[0].bbox.clone();
+    let mut centroid_box = AABB::new(prims[0].centroid, prims[0].centroid);
+    for prim in prims[1..].iter() {
+        bbox = AABB::surrounding_box(&bbox, &prim.bbox);
+        centroid_box =
+            AABB::surrounding_box(&centroid_box, &AABB::new(prim.centroid, prim.centroid));
+    }
+    let leaf = BuildNode::Leaf {
+        bbox: bbox.clone(),
+        start: offset,
+        count: n,
+    };
+    if n == 1 {
+        return leaf;
+    }
+    // find the cheapest bucket boundary over all three axes
+    let extent = centroid_box._max - centroid_box._min;
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        if extent[axis] <= 0.0 {
+            continue;
+        }
+        let bucket_of = |c: &Point3| {
+            let b = ((c[axis] - centroid_box._min[axis]) / extent[axis] * SAH_BINS as f64) as usize;
+            b.min(SAH_BINS - 1)
         };
-        let object_span = end - start;
-        if object_span == 1 {
-            tmp = BVHNode {
-                left: objects[start].clone(),
-                right: objects[start].clone(),
-                bvhbox: AABB::new(Point3::zero(), Point3::zero()),
+        let mut counts = [0_usize; SAH_BINS];
+        let mut boxes: Vec<Option<AABB>> = vec![None; SAH_BINS];
+        for prim in prims.iter() {
+            let b = bucket_of(&prim.centroid);
+            counts[b] += 1;
+            boxes[b] = union(&boxes[b], &prim.bbox);
+        }
+        // sweep from the right to know the area and count right of every boundary
+        let mut right_area = [0.0; SAH_BINS];
+        let mut right_count = [0_usize; SAH_BINS];
+        let mut acc: Option<AABB> = None;
+        let mut count = 0;
+        for b in (1..SAH_BINS).rev() {
+            if let Some(bb) = &boxes[b] {
+                acc = union(&acc, bb);
+            }
+            count += counts[b];
+            right_area[b] = acc.as_ref().map_or(0.0, |a| a.area());
+            right_count[b] = count;
+        }
+        let mut acc: Option<AABB> = None;
+        let mut count = 0;
+        for b in 1..SAH_BINS {
+            if let Some(bb) = &boxes[b - 1] {
+                acc = union(&acc, bb);
+            }
+            count += counts[b - 1];
+            if count == 0 || right_count[b] == 0 {
+                continue;
+            }
+            let left_area = acc.as_ref().map_or(0.0, |a| a.area());
+            let cost = left_area * count as f64 + right_area[b] * right_count[b] as f64;
+            let better = match best {
+                Some((best_cost, _, _)) => cost < best_cost,
+                None => true,
             };
-        } else if object_span == 2 {
-            if comparator(&objects[start], &objects[start + 1]) == Ordering::Less {
-                tmp = BVHNode {
-                    left: objects[start].clone(),
-                    right: objects[start + 1].clone(),
-                    bvhbox: AABB::new(Point3::zero(), Point3::zero()),
-                };
+            if better {
+                best = Some((cost, axis, b));
+            }
+        }
+    }
+    let area = bbox.area();
+    let split = match best {
+        Some((cost, axis, bucket)) => {
+            let split_cost = if area > 0.0 {
+                TRAVERSAL_COST + cost / area
             } else {
-                tmp = BVHNode {
-                    left: objects[start + 1].clone(),
-                    right: objects[start].clone(),
-                    bvhbox: AABB::new(Point3::zero(), Point3::zero()),
-                };
+                TRAVERSAL_COST + n as f64
+            };
+            if n <= max_leaf_size && n as f64 <= split_cost {
+                return leaf;
             }
+            let boundary = centroid_box._min[axis] + extent[axis] * bucket as f64 / SAH_BINS as f64;
+            Some((axis, boundary))
+        }
+        None => None,
+    };
+    if split.is_none() && n <= max_leaf_size {
+        return leaf;
+    }
+    let (axis, mid) = match split {
+        Some((axis, boundary)) => {
+            // partition in place, everything left of the boundary goes first
+            let mut mid = 0;
+            for i in 0..n {
+                if prims[i].centroid[axis] < boundary {
+                    prims.swap(i, mid);
+                    mid += 1;
+                }
+            }
+            (axis, mid)
+        }
+        None => (0, 0),
+    };
+    let (axis, mid) = if mid == 0 || mid == n {
+        // all centroids coincide, or the boundary did not separate them: split at the median
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
         } else {
-            objects.as_mut_slice()[start..end].sort_by(comparator);
-            let mid = start + object_span / 2;
-            tmp = BVHNode {
-                left: Arc::new(BVHNode::new(objects, start, mid, time0, time1)),
-                right: Arc::new(BVHNode::new(objects, mid, end, time0, time1)),
-                bvhbox: AABB::new(Point3::zero(), Point3::zero()),
-            };
+            2
+        };
+        prims.sort_by(|a, b| a.centroid[axis].partial_cmp(&b.centroid[axis]).unwrap());
+        (axis, n / 2)
+    } else {
+        (axis, mid)
+    };
+    let (left, right) = prims.split_at_mut(mid);
+    let left = build_range(left, offset, max_leaf_size);
+    let right = build_range(right, offset + mid, max_leaf_size);
+    BuildNode::Interior {
+        bbox,
+        axis,
+        children: Box::new([left, right]),
+    }
+}
+
+// Node of a flattened BVH. The left child of an interior node directly follows it.
+#[derive(Clone)]
+pub struct LinearNode {
+    pub bbox: AABB,
+    // first primitive of a leaf, or index of the right child of an interior node
+    pub offset: u32,
+    // number of primitives, zero for interior nodes
+    pub count: u16,
+    pub axis: u8,
+}
+
+// Depth first array of nodes over primitive indices, traversed without recursion.
+#[derive(Clone, Default)]
+pub struct LinearBVH {
+    pub nodes: Vec<LinearNode>,
+    // primitive indices, every leaf covers a contiguous range
+    pub prims: Vec<u32>,
+    // levels of nodes, which bounds the traversal stack
+    pub depth: usize,
+}
+
+impl LinearBVH {
+    pub fn new(boxes: Vec<AABB>, max_leaf_size: usize) -> Self {
+        let mut prims: Vec<BuildPrim> = boxes
+            .into_iter()
+            .enumerate()
+            .map(|(index, bbox)| BuildPrim::new(index, bbox))
+            .collect();
+        if prims.is_empty() {
+            return Self::default();
+        }
+        // leaf sizes are stored as u16
+        let root = build_sah(&mut prims, max_leaf_size.min(u16::MAX as usize));
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * prims.len()),
+            prims: prims.iter().map(|prim| prim.index as u32).collect(),
+            depth: 0,
+        };
+        bvh.flatten(&root, 1);
+        bvh
+    }
+
+    fn flatten(&mut self, node: &BuildNode, depth: usize) {
+        self.depth = self.depth.max(depth);
+        let this = self.nodes.len();
+        self.nodes.push(LinearNode {
+            bbox: node.bbox().clone(),
+            offset: 0,
+            count: 0,
+            axis: 0,
+        });
+        match node {
+            BuildNode::Leaf { start, count, .. } => {
+                self.nodes[this].offset = *start as u32;
+                self.nodes[this].count = *count as u16;
+            }
+            BuildNode::Interior { axis, children, .. } => {
+                self.nodes[this].axis = *axis as u8;
+                self.flatten(&children[0], depth + 1);
+                self.nodes[this].offset = self.nodes.len() as u32;
+                self.flatten(&children[1], depth + 1);
+            }
+        }
+    }
+
+    pub fn bbox(&self) -> Option<&AABB> {
+        self.nodes.first().map(|root| &root.bbox)
+    }
+
+    // Visit the primitives whose boxes the ray passes through, nearer children first.
+    // `hit_prim(index, t_max)` returns the distance of a hit closer than `t_max`.
+    pub fn traverse<F: FnMut(usize, f64) -> Option<f64>>(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut hit_prim: F,
+    ) -> bool {
+        if self.nodes.is_empty() {
+            return false;
         }
-        let mut box_left = AABB::new(Point3::zero(), Point3::zero());
-        let mut box_right = AABB::new(Point3::zero(), Point3::zero());
-        if !tmp.left.bounding_box(time0, time1, &mut box_left)
-            || !tmp.right.bounding_box(time0, time1, &mut box_right)
-        {
-            println!("No bounding box in bvh_node constructor.\n");
+        let dir_is_neg = [r.dir.x < 0.0, r.dir.y < 0.0, r.dir.z < 0.0];
+        let mut closest_so_far = t_max;
+        let mut hit_anything = false;
+        // every level leaves at most one sibling behind, only degenerate trees need the heap
+        let mut small = [0_u32; 64];
+        let mut large;
+        let stack: &mut [u32] = if self.depth < small.len() {
+            &mut small
+        } else {
+            large = vec![0_u32; self.depth + 1];
+            &mut large
+        };
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let idx = stack[stack_len] as usize;
+            let node = &self.nodes[idx];
+            if !node.bbox.hit(r, t_min, closest_so_far) {
+                continue;
+            }
+            if node.count > 0 {
+                let start = node.offset as usize;
+                for &prim in &self.prims[start..start + node.count as usize] {
+                    if let Some(t) = hit_prim(prim as usize, closest_so_far) {
+                        hit_anything = true;
+                        closest_so_far = t;
+                    }
+                }
+            } else if dir_is_neg[node.axis as usize] {
+                // the second child lies further along the axis, so it is nearer
+                stack[stack_len] = idx as u32 + 1;
+                stack[stack_len + 1] = node.offset;
+                stack_len += 2;
+            } else {
+                stack[stack_len] = node.offset;
+                stack[stack_len + 1] = idx as u32 + 1;
+                stack_len += 2;
+            }
+        }
+        hit_anything
+    }
+
+    pub fn stats(&self) -> BVHStats {
+        let mut stats = BVHStats::default();
+        if !self.nodes.is_empty() {
+            self.collect_stats(0, 1, &mut stats);
+        }
+        stats
+    }
+
+    fn collect_stats(&self, idx: usize, depth: usize, stats: &mut BVHStats) {
+        let node = &self.nodes[idx];
+        if node.count > 0 {
+            stats.add_leaf(depth, node.count as usize);
+        } else {
+            stats.nodes += 1;
+            stats.depth = stats.depth.max(depth);
+            self.collect_stats(idx + 1, depth + 1, stats);
+            self.collect_stats(node.offset as usize, depth + 1, stats);
         }
-        tmp.bvhbox = AABB::surrounding_box(&box_left, &box_right);
-        tmp
     }
 }
 
-pub fn box_x_compare(a: &Arc<dyn HitTable>, b: &Arc<dyn HitTable>) -> Ordering {
-    let mut box_a = AABB::new(Point3::zero(), Point3::zero());
-    let mut box_b = AABB::new(Point3::zero(), Point3::zero());
-    if !a.bounding_box(0.0, 0.0, &mut box_a) || !b.bounding_box(0.0, 0.0, &mut box_b) {
-        println!("No bounding box in bvh_node constructor.\n");
+// BVH over arbitrary objects, stored as a `LinearBVH`
+pub struct FlatBVH {
+    objects: Vec<Arc<dyn HitTable>>,
+    bvh: LinearBVH,
+}
+
+impl FlatBVH {
+    pub fn new(
+        objects: &[Arc<dyn HitTable>],
+        time0: f64,
+        time1: f64,
+        max_leaf_size: usize,
+    ) -> Self {
+        let boxes = objects
+            .iter()
+            .map(|object| {
+                let mut bbox = AABB::new(Point3::zero(), Point3::zero());
+                if !object.bounding_box(time0, time1, &mut bbox) {
+                    println!("No bounding box in bvh_node constructor.\n");
+                }
+                bbox
+            })
+            .collect();
+        Self {
+            objects: objects.to_vec(),
+            bvh: LinearBVH::new(boxes, max_leaf_size),
+        }
+    }
+
+    pub fn stats(&self) -> BVHStats {
+        self.bvh.stats()
+    }
+}
+
+impl HitTable for FlatBVH {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let objects = &self.objects;
+        self.bvh.traverse(r, t_min, t_max, |idx, closest_so_far| {
+            if objects[idx].hit(r, t_min, closest_so_far, rec) {
+                rec.is_light = objects[idx].is_light();
+                Some(rec.t)
+            } else {
+                None
+            }
+        })
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        match self.bvh.bbox() {
+            Some(bbox) => {
+                *output_box = bbox.clone();
+                true
+            }
+            None => false,
+        }
     }
-    if box_a._min.x < box_b._min.x {
-        return Ordering::Less;
-    } else if box_a._min.x > box_b._min.x {
-        return Ordering::Greater;
+    fn distance(&self, _other_center: &Point3) -> f64 {
+        0.0
     }
-    Ordering::Equal
 }
 
-pub fn box_y_compare(a: &Arc<dyn HitTable>, b: &Arc<dyn HitTable>) -> Ordering {
-    let mut box_a = AABB::new(Point3::zero(), Point3::zero());
-    let mut box_b = AABB::new(Point3::zero(), Point3::zero());
-    if !a.bounding_box(0.0, 0.0, &mut box_a) || !b.bounding_box(0.0, 0.0, &mut box_b) {
-        println!("No bounding box in bvh_node constructor.\n");
+#[derive(Clone)]
+enum BVHContent {
+    Interior(Arc<BVHNode>, Arc<BVHNode>),
+    Leaf(Vec<Arc<dyn HitTable>>),
+}
+
+#[derive(Clone)]
+pub struct BVHNode {
+    content: BVHContent,
+    bvhbox: AABB,
+}
+
+#[derive(Debug, Default)]
+pub struct BVHStats {
+    pub depth: usize,
+    pub nodes: usize,
+    pub leaves: usize,
+    // leaf_sizes[k] is the number of leaves holding k objects
+    pub leaf_sizes: Vec<usize>,
+}
+
+impl BVHStats {
+    fn add_leaf(&mut self, depth: usize, size: usize) {
+        self.nodes += 1;
+        self.leaves += 1;
+        self.depth = self.depth.max(depth);
+        if self.leaf_sizes.len() <= size {
+            self.leaf_sizes.resize(size + 1, 0);
+        }
+        self.leaf_sizes[size] += 1;
     }
-    if box_a._min.y < box_b._min.y {
-        return Ordering::Less;
-    } else if box_a._min.y > box_b._min.y {
-        return Ordering::Greater;
+}
+
+impl fmt::Display for BVHStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "BVH: {} nodes, {} leaves, depth {}, leaf sizes",
+            self.nodes, self.leaves, self.depth
+        )?;
+        for (size, count) in self.leaf_sizes.iter().enumerate() {
+            if *count > 0 {
+                write!(f, " {}:{}", size, count)?;
+            }
+        }
+        Ok(())
     }
-    Ordering::Equal
 }
 
-pub fn box_z_compare(a: &Arc<dyn HitTable>, b: &Arc<dyn HitTable>) -> Ordering {
-    let mut box_a = AABB::new(Point3::zero(), Point3::zero());
-    let mut box_b = AABB::new(Point3::zero(), Point3::zero());
-    if !a.bounding_box(0.0, 0.0, &mut box_a) || !b.bounding_box(0.0, 0.0, &mut box_b) {
-        println!("No bounding box in bvh_node constructor.\n");
+impl BVHNode {
+    pub fn new(
+        objects: &[Arc<dyn HitTable>],
+        time0: f64,
+        time1: f64,
+        max_leaf_size: usize,
+    ) -> Self {
+        let mut prims = Vec::with_capacity(objects.len());
+        for (index, object) in objects.iter().enumerate() {
+            let mut bbox = AABB::new(Point3::zero(), Point3::zero());
+            if !object.bounding_box(time0, time1, &mut bbox) {
+                println!("No bounding box in bvh_node constructor.\n");
+            }
+            prims.push(BuildPrim::new(index, bbox));
+        }
+        if prims.is_empty() {
+            return BVHNode {
+                content: BVHContent::Leaf(Vec::new()),
+                bvhbox: AABB::new(Point3::zero(), Point3::zero()),
+            };
+        }
+        let root = build_sah(&mut prims, max_leaf_size);
+        BVHNode::from_build(&root, &prims, objects)
+    }
+
+    fn from_build(node: &BuildNode, prims: &[BuildPrim], objects: &[Arc<dyn HitTable>]) -> Self {
+        match node {
+            BuildNode::Leaf { bbox, start, count } => BVHNode {
+                content: BVHContent::Leaf(
+                    prims[*start..*start + *count]
+                        .iter()
+                        .map(|prim| objects[prim.index].clone())
+                        .collect(),
+                ),
+                bvhbox: bbox.clone(),
+            },
+            BuildNode::Interior { bbox, children, .. } => BVHNode {
+                content: BVHContent::Interior(
+                    Arc::new(BVHNode::from_build(&children[0], prims, objects)),
+                    Arc::new(BVHNode::from_build(&children[1], prims, objects)),
+                ),
+                bvhbox: bbox.clone(),
+            },
+        }
     }
-    if box_a._min.z < box_b._min.z {
-        return Ordering::Less;
-    } else if box_a._min.z > box_b._min.z {
-        return Ordering::Greater;
+
+    pub fn stats(&self) -> BVHStats {
+        let mut stats = BVHStats::default();
+        self.collect_stats(1, &mut stats);
+        stats
+    }
+
+    fn collect_stats(&self, depth: usize, stats: &mut BVHStats) {
+        match &self.content {
+            BVHContent::Interior(left, right) => {
+                stats.nodes += 1;
+                stats.depth = stats.depth.max(depth);
+                left.collect_stats(depth + 1, stats);
+                right.collect_stats(depth + 1, stats);
+            }
+            BVHContent::Leaf(objects) => stats.add_leaf(depth, objects.len()),
+        }
     }
-    Ordering::Equal
 }
 
 impl HitTable for BVHNode {
@@ -120,11 +513,25 @@ impl HitTable for BVHNode {
         if !self.bvhbox.hit(r, t_min, t_max) {
             return false;
         }
-        let hit_left = self.left.hit(r, t_min, t_max, rec);
-        let hit_right = self
-            .right
-            .hit(r, t_min, if hit_left { rec.t } else { t_max }, rec);
-        hit_left || hit_right
+        match &self.content {
+            BVHContent::Interior(left, right) => {
+                let hit_left = left.hit(r, t_min, t_max, rec);
+                let hit_right = right.hit(r, t_min, if hit_left { rec.t } else { t_max }, rec);
+                hit_left || hit_right
+            }
+            BVHContent::Leaf(objects) => {
+                let mut hit_anything = false;
+                let mut closest_so_far = t_max;
+                for object in objects {
+                    if object.hit(r, t_min, closest_so_far, rec) {
+                        hit_anything = true;
+                        closest_so_far = rec.t;
+                        rec.is_light = object.is_light();
+                    }
+                }
+                hit_anything
+            }
+        }
     }
     fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
         *output_box = self.bvhbox.clone();
@@ -134,3 +541,110 @@ impl HitTable for BVHNode {
         0.0
     }
 }
+
+#[allow(clippy::float_cmp)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::Sphere,
+        hittablelist::HitTableList,
+        material::Lambertian,
+        vec3::{Color, Vec3},
+    };
+
+    fn spheres() -> HitTableList {
+        let mut list = HitTableList::new();
+        let mat = Arc::new(Lambertian::new(Color::ones()));
+        for i in 0..10 {
+            for j in 0..10 {
+                let center = Point3::new(i as f64, (i * j % 7) as f64 * 0.3, j as f64);
+                list.add(Arc::new(Sphere::new(center, 0.3, mat.clone())));
+            }
+        }
+        list
+    }
+
+    #[test]
+    fn test_matches_list() {
+        let list = spheres();
+        let bvh = BVHNode::new(&list.objects, 0.0, 1.0, 4);
+        let flat = FlatBVH::new(&list.objects, 0.0, 1.0, 4);
+        let mat = Arc::new(Lambertian::new(Color::zero()));
+        for k in 0..200 {
+            let r = Ray {
+                orig: Point3::new(-2.0, 5.0, -2.0),
+                dir: Vec3::new(
+                    1.0 + (k % 20) as f64 * 0.1,
+                    -1.0,
+                    1.0 + (k / 20) as f64 * 0.1,
+                ),
+                time: 0.0,
+                wavelength: None,
+            };
+            let mut rec_list = HitRecord::new(mat.clone());
+            let mut rec_bvh = HitRecord::new(mat.clone());
+            let mut rec_flat = HitRecord::new(mat.clone());
+            let hit = list.hit(&r, 0.001, f64::INFINITY, &mut rec_list);
+            assert_eq!(hit, bvh.hit(&r, 0.001, f64::INFINITY, &mut rec_bvh));
+            assert_eq!(hit, flat.hit(&r, 0.001, f64::INFINITY, &mut rec_flat));
+            if hit {
+                assert_eq!(rec_list.t, rec_bvh.t);
+                assert_eq!(rec_list.t, rec_flat.t);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let list = spheres();
+        for &leaf_size in &[1, 4, 16] {
+            let stats = BVHNode::new(&list.objects, 0.0, 1.0, leaf_size).stats();
+            let flat_stats = FlatBVH::new(&list.objects, 0.0, 1.0, leaf_size).stats();
+            assert_eq!(flat_stats.nodes, stats.nodes);
+            assert_eq!(flat_stats.leaf_sizes, stats.leaf_sizes);
+            let objects: usize = stats
+                .leaf_sizes
+                .iter()
+                .enumerate()
+                .map(|(size, count)| size * count)
+                .sum();
+            assert_eq!(objects, 100);
+            assert_eq!(stats.nodes, 2 * stats.leaves - 1);
+            assert!(stats.leaf_sizes.len() <= leaf_size + 1);
+        }
+        assert_eq!(BVHNode::new(&[], 0.0, 1.0, 4).stats().leaves, 1);
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        // boxes spreading out geometrically make SAH peel off one at a time
+        let boxes: Vec<AABB> = (0..2000)
+            .map(|i| {
+                let x = 1.3_f64.powi(i);
+                AABB::new(Point3::new(x, 0.0, 0.0), Point3::new(x * 1.1, 1.0, 1.0))
+            })
+            .collect();
+        let bvh = LinearBVH::new(boxes.clone(), 1);
+        assert!(bvh.depth > 128);
+        assert_eq!(bvh.stats().depth, bvh.depth);
+        let r = Ray {
+            orig: Point3::new(0.0, 0.5, 0.5),
+            dir: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        // every box lies on the ray, the last one is only reached at the bottom of the tree
+        let mut visited = 0;
+        let hit = bvh.traverse(&r, 0.0, f64::INFINITY, |index, _| {
+            visited += 1;
+            if index == boxes.len() - 1 {
+                Some(boxes[index]._min.x)
+            } else {
+                None
+            }
+        });
+        assert!(hit);
+        assert_eq!(visited, boxes.len());
+    }
+}
@@ -1,9 +1,55 @@
 use crate::ray::Ray;
 use crate::{
+    onb::Onb,
     rtweekend::degrees_to_radians,
-    vec3::{random_in_unit_disk, Point3, Vec3},
+    vec3::{sample_in_unit_disk, Point3, Vec3},
 };
 
+// user-facing description of a camera, turned into a `Camera` once the aspect ratio is known
+#[derive(Clone, Copy, Debug)]
+pub struct CameraSettings {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    // shutter interval, rays are spread over it for motion blur
+    pub time0: f64,
+    pub time1: f64,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            lookfrom: Point3::new(13.0, 2.0, 3.0),
+            lookat: Point3::zero(),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 20.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            time0: 0.0,
+            time1: 0.0,
+        }
+    }
+}
+
+impl CameraSettings {
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        Camera::new(
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+            self.time0,
+            self.time1,
+        )
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Camera {
     origin: Point3,
@@ -14,9 +60,12 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: f64,
+    time0: f64,
+    time1: f64,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -25,14 +74,18 @@ impl Camera {
         aspect_ratio: f64,
         aperture: f64,
         focus_dist: f64,
+        time0: f64,
+        time1: f64,
     ) -> Self {
         let theta = degrees_to_radians(vfov);
         let h = (theta / 2.0).tan();
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
-        let ww = (lookfrom - lookat).unit();
-        let uu = vup.cross(ww).unit();
-        let vv = ww.cross(uu);
+        let Onb {
+            u: uu,
+            v: vv,
+            w: ww,
+        } = Onb::look_at(&lookfrom, &lookat, &vup);
         Self {
             origin: lookfrom,
             horizontal: uu * viewport_width * focus_dist,
@@ -45,17 +98,27 @@ impl Camera {
             v: vv,
             w: ww,
             lens_radius: aperture / 2.0,
+            time0,
+            time1,
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = random_in_unit_disk() * self.lens_radius;
+    // whether rays need a time sample
+    pub fn has_motion(&self) -> bool {
+        self.time1 > self.time0
+    }
+
+    // `lens` is a 2D sample for the point on the lens, `shutter` one for the time
+    pub fn get_ray(&self, s: f64, t: f64, lens: (f64, f64), shutter: f64) -> Ray {
+        let rd = sample_in_unit_disk(lens) * self.lens_radius;
         let offset = self.u * rd.x + self.v * rd.y;
         Ray {
             orig: self.origin + offset,
             dir: self.lower_left_corner + self.horizontal * s + self.vertical * t
                 - self.origin
                 - offset,
+            time: self.time0 + shutter * (self.time1 - self.time0),
+            wavelength: None,
         }
     }
 }
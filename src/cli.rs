@@ -0,0 +1,337 @@
+use crate::{
+    sampler::SamplerKind,
+    vec3::{Point3, Vec3},
+};
+use std::fmt;
+
+pub const USAGE: &str = "\
+Usage: raytracer [OPTIONS]
+
+Options:
+    -w, --width <N>          image width in pixels [default: 1600]
+    -H, --height <N>         image height in pixels [default: width * 9 / 16]
+    -s, --spp <N>            samples per pixel [default: 200]
+    -d, --depth <N>          maximum ray bounce depth [default: 50]
+        --rr-depth <N>       bounces before paths are cut by Russian roulette [default: 3]
+    -t, --threads <N>        number of worker threads [default: 4, CI: 2]
+    -j, --tiles <N>          number of row tiles the image is split into [default: 16, CI: 32]
+        --leaf-size <N>      maximum number of objects in a BVH leaf [default: 4]
+        --bvh <LAYOUT>       BVH layout: tree (pointer tree) or flat (linear array)
+                             [default: flat]
+        --sampler <NAME>     sample generator: independent, stratified, halton or sobol
+                             [default: sobol]
+        --bench-bvh          time both BVH layouts on the built-in scenes and exit
+        --spectral           trace wavelengths instead of RGB, converting to sRGB at the film
+        --seed <N>           seed for the scene layout and the samples [default: random]
+    -o, --output <PATH>      output image path [default: output/test.png]
+        --scene <NAME>       built-in scene: random_scene, read_image, simple_light
+                             [default: read_image]
+        --scene-file <PATH>  load the scene from a text scene file instead
+        --lookfrom <X,Y,Z>   camera position [default: depends on scene]
+        --lookat <X,Y,Z>     camera target [default: depends on scene]
+        --vfov <DEG>         vertical field of view in degrees [default: depends on scene]
+        --aperture <F>       lens aperture [default: depends on scene]
+        --focus-dist <F>     focus distance [default: depends on scene]
+        --shutter <T0,T1>    shutter open and close times for motion blur
+                             [default: depends on scene]
+    -h, --help               print this message";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SceneKind {
+    RandomScene,
+    ReadImage,
+    SimpleLight,
+}
+
+impl SceneKind {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "random_scene" => Some(SceneKind::RandomScene),
+            "read_image" => Some(SceneKind::ReadImage),
+            "simple_light" => Some(SceneKind::SimpleLight),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BVHLayout {
+    Tree,
+    Flat,
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: i64,
+    pub max_depth: i64,
+    pub rr_depth: i64,
+    pub n_workers: usize,
+    pub n_jobs: usize,
+    pub max_leaf_size: usize,
+    pub bvh: BVHLayout,
+    pub bench_bvh: bool,
+    pub spectral: bool,
+    pub sampler: SamplerKind,
+    pub seed: Option<u64>,
+    pub output: String,
+    pub scene: SceneKind,
+    pub scene_file: Option<String>,
+    pub lookfrom: Option<Point3>,
+    pub lookat: Option<Point3>,
+    pub vfov: Option<f64>,
+    pub aperture: Option<f64>,
+    pub focus_dist: Option<f64>,
+    pub shutter: Option<(f64, f64)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    Help,
+    Invalid(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Help => write!(f, "{}", USAGE),
+            CliError::Invalid(msg) => write!(f, "error: {}\n\n{}", msg, USAGE),
+        }
+    }
+}
+
+fn invalid<T>(msg: String) -> Result<T, CliError> {
+    Err(CliError::Invalid(msg))
+}
+
+fn parse_num<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, CliError> {
+    match value.parse() {
+        Ok(v) => Ok(v),
+        Err(_) => invalid(format!("invalid value '{}' for '{}'", value, flag)),
+    }
+}
+
+fn parse_positive<T: std::str::FromStr + PartialOrd + Default>(
+    flag: &str,
+    value: &str,
+) -> Result<T, CliError> {
+    let v: T = parse_num(flag, value)?;
+    if v <= T::default() {
+        return invalid(format!("'{}' must be positive, got '{}'", flag, value));
+    }
+    Ok(v)
+}
+
+fn parse_vec3(flag: &str, value: &str) -> Result<Vec3, CliError> {
+    let parts: Vec<&str> = value.split(',').collect();
+    if parts.len() != 3 {
+        return invalid(format!(
+            "'{}' expects three comma separated numbers, got '{}'",
+            flag, value
+        ));
+    }
+    Ok(Vec3::new(
+        parse_num(flag, parts[0])?,
+        parse_num(flag, parts[1])?,
+        parse_num(flag, parts[2])?,
+    ))
+}
+
+impl Options {
+    pub fn default_for(is_ci: bool) -> Self {
+        // jobs: split image into how many parts
+        // workers: maximum allowed concurrent running threads
+        let (n_jobs, n_workers) = if is_ci { (32, 2) } else { (16, 4) };
+        Self {
+            image_width: 1600,
+            image_height: 900,
+            samples_per_pixel: 200,
+            max_depth: 50,
+            rr_depth: 3,
+            n_workers,
+            n_jobs,
+            max_leaf_size: 4,
+            bvh: BVHLayout::Flat,
+            bench_bvh: false,
+            spectral: false,
+            sampler: SamplerKind::Sobol,
+            seed: None,
+            output: String::from("output/test.png"),
+            scene: SceneKind::ReadImage,
+            scene_file: None,
+            lookfrom: None,
+            lookat: None,
+            vfov: None,
+            aperture: None,
+            focus_dist: None,
+            shutter: None,
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        self.image_width as f64 / self.image_height as f64
+    }
+
+    /// Parse command line arguments (without the program name) on top of the defaults.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I, is_ci: bool) -> Result<Self, CliError> {
+        let mut opts = Self::default_for(is_ci);
+        let mut height = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // accept both "--flag value" and "--flag=value"
+            let (flag, inline) = match arg.find('=') {
+                Some(pos) if arg.starts_with("--") => {
+                    (arg[..pos].to_string(), Some(arg[pos + 1..].to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            if flag == "-h" || flag == "--help" {
+                return Err(CliError::Help);
+            }
+            if flag == "--bench-bvh" {
+                opts.bench_bvh = true;
+                continue;
+            }
+            if flag == "--spectral" {
+                opts.spectral = true;
+                continue;
+            }
+            let value = match inline.or_else(|| args.next()) {
+                Some(v) => v,
+                None => return invalid(format!("missing value for '{}'", flag)),
+            };
+            match flag.as_str() {
+                "-w" | "--width" => opts.image_width = parse_positive(&flag, &value)?,
+                "-H" | "--height" => height = Some(parse_positive(&flag, &value)?),
+                "-s" | "--spp" => opts.samples_per_pixel = parse_positive(&flag, &value)?,
+                "-d" | "--depth" => opts.max_depth = parse_positive(&flag, &value)?,
+                "--rr-depth" => opts.rr_depth = parse_positive(&flag, &value)?,
+                "-t" | "--threads" => opts.n_workers = parse_positive(&flag, &value)?,
+                "-j" | "--tiles" => opts.n_jobs = parse_positive(&flag, &value)?,
+                "--leaf-size" => opts.max_leaf_size = parse_positive(&flag, &value)?,
+                "--bvh" => {
+                    opts.bvh = match value.as_str() {
+                        "tree" => BVHLayout::Tree,
+                        "flat" => BVHLayout::Flat,
+                        _ => return invalid(format!("unknown BVH layout '{}'", value)),
+                    }
+                }
+                "--sampler" => {
+                    opts.sampler = match SamplerKind::parse(&value) {
+                        Some(sampler) => sampler,
+                        None => return invalid(format!("unknown sampler '{}'", value)),
+                    }
+                }
+                "--seed" => opts.seed = Some(parse_num(&flag, &value)?),
+                "-o" | "--output" => opts.output = value,
+                "--scene" => {
+                    opts.scene = match SceneKind::parse(&value) {
+                        Some(scene) => scene,
+                        None => return invalid(format!("unknown scene '{}'", value)),
+                    }
+                }
+                "--scene-file" => opts.scene_file = Some(value),
+                "--lookfrom" => opts.lookfrom = Some(parse_vec3(&flag, &value)?),
+                "--lookat" => opts.lookat = Some(parse_vec3(&flag, &value)?),
+                "--vfov" => {
+                    let vfov: f64 = parse_positive(&flag, &value)?;
+                    if vfov >= 180.0 {
+                        return invalid(format!("'{}' must be below 180 degrees", flag));
+                    }
+                    opts.vfov = Some(vfov);
+                }
+                "--aperture" => {
+                    let aperture: f64 = parse_num(&flag, &value)?;
+                    if aperture < 0.0 {
+                        return invalid(format!("'{}' must not be negative", flag));
+                    }
+                    opts.aperture = Some(aperture);
+                }
+                "--focus-dist" => opts.focus_dist = Some(parse_positive(&flag, &value)?),
+                "--shutter" => {
+                    let parts: Vec<&str> = value.split(',').collect();
+                    if parts.len() != 2 {
+                        return invalid(format!(
+                            "'{}' expects two comma separated times, got '{}'",
+                            flag, value
+                        ));
+                    }
+                    let open: f64 = parse_num(&flag, parts[0])?;
+                    let close: f64 = parse_num(&flag, parts[1])?;
+                    if close < open {
+                        return invalid(format!("'{}' must not close before it opens", flag));
+                    }
+                    opts.shutter = Some((open, close));
+                }
+                _ => return invalid(format!("unknown option '{}'", flag)),
+            }
+        }
+        opts.image_height = match height {
+            Some(h) => h,
+            None => ((opts.image_width as f64 * 9.0 / 16.0) as u32).max(1),
+        };
+        if opts.image_width < 2 || opts.image_height < 2 {
+            return invalid(String::from("image must be at least 2x2 pixels"));
+        }
+        if opts.n_jobs > opts.image_height as usize {
+            return invalid(format!(
+                "cannot split {} rows into {} tiles",
+                opts.image_height, opts.n_jobs
+            ));
+        }
+        Ok(opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, CliError> {
+        Options::parse(args.iter().map(|s| s.to_string()), false)
+    }
+
+    #[test]
+    fn test_defaults() {
+        let opts = parse(&[]).unwrap();
+        assert_eq!((opts.image_width, opts.image_height), (1600, 900));
+        assert_eq!(opts.samples_per_pixel, 200);
+        assert_eq!(opts.scene, SceneKind::ReadImage);
+        assert!(!opts.spectral);
+    }
+
+    #[test]
+    fn test_values() {
+        let opts = parse(&[
+            "--width=320",
+            "-s",
+            "8",
+            "--scene",
+            "simple_light",
+            "--lookfrom",
+            "1,2,3",
+            "--shutter",
+            "0,0.5",
+            "--spectral",
+        ])
+        .unwrap();
+        assert_eq!((opts.image_width, opts.image_height), (320, 180));
+        assert_eq!(opts.samples_per_pixel, 8);
+        assert_eq!(opts.scene, SceneKind::SimpleLight);
+        assert_eq!(opts.lookfrom, Some(Vec3::new(1.0, 2.0, 3.0)));
+        assert_eq!(opts.shutter, Some((0.0, 0.5)));
+        assert!(opts.spectral);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(parse(&["--help"]).unwrap_err(), CliError::Help);
+        assert!(parse(&["--spp", "0"]).is_err());
+        assert!(parse(&["--scene", "cornell"]).is_err());
+        assert!(parse(&["--lookat", "1,2"]).is_err());
+        assert!(parse(&["--shutter", "1,0"]).is_err());
+        assert!(parse(&["--width"]).is_err());
+        assert!(parse(&["--bogus", "1"]).is_err());
+    }
+}
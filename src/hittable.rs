@@ -2,7 +2,10 @@ use crate::ray::Ray;
 use crate::{
     aabb::AABB,
     material::Material,
-    vec3::{Point3, Vec3},
+    onb::Onb,
+    rtweekend::{fmax, fmin},
+    sampler::Sampler,
+    vec3::{sample_unit_vector, Point3, Vec3},
 };
 use std::{f64::consts::PI, sync::Arc};
 
@@ -15,6 +18,8 @@ pub struct HitRecord {
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    // whether the object hit is one of the lights, set by the collection holding it
+    pub is_light: bool,
 }
 
 impl HitRecord {
@@ -31,6 +36,7 @@ impl HitRecord {
             u: 0.0,
             v: 0.0,
             front_face: false,
+            is_light: false,
         }
     }
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3) {
@@ -47,6 +53,49 @@ pub trait HitTable: Send + Sync {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
     fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut AABB) -> bool;
     fn distance(&self, other_center: &Point3) -> f64;
+    // Light sampling: `random` picks a direction from `o` towards the object and `pdf_value` is
+    // the solid angle density of picking `v` that way. Only objects that are lights implement
+    // them.
+    fn is_light(&self) -> bool {
+        false
+    }
+    fn pdf_value(&self, _o: &Point3, _v: &Vec3) -> f64 {
+        0.0
+    }
+    fn random(&self, _o: &Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+}
+
+fn hit_sphere(
+    center: &Point3,
+    radius: f64,
+    mat_ptr: &Arc<dyn Material>,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+    rec: &mut HitRecord,
+) -> bool {
+    let oc = r.orig - *center;
+    let a = r.dir * r.dir;
+    let b = oc * r.dir;
+    let c = oc * oc - radius * radius;
+    let discriminant = b * b - a * c;
+    if discriminant > 0.0 {
+        let root = discriminant.sqrt();
+        for &tmp in &[(-b - root) / a, (-b + root) / a] {
+            if tmp < t_max && tmp > t_min {
+                rec.t = tmp;
+                rec.p = r.at(rec.t);
+                let outward_normal = (rec.p - *center) / radius;
+                rec.set_face_normal(r, &outward_normal);
+                Sphere::get_sphere_uv(&outward_normal, &mut rec.u, &mut rec.v);
+                rec.mat_ptr = mat_ptr.clone();
+                return true;
+            }
+        }
+    }
+    false
 }
 
 #[derive(Clone)]
@@ -74,43 +123,15 @@ impl Sphere {
 
 impl HitTable for Sphere {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let oc = r.orig - self.center;
-        let a = r.dir * r.dir;
-        let b = oc * r.dir;
-        let c = oc * oc - self.radius * self.radius;
-        let discriminant = b * b - a * c;
-        if discriminant > 0.0 {
-            let root = discriminant.sqrt();
-            let tmp = (-b - root) / a;
-            if tmp < t_max && tmp > t_min {
-                rec.t = tmp;
-                rec.p = r.at(rec.t);
-                let outward_normal = (rec.p - self.center) / self.radius;
-                rec.set_face_normal(r, &outward_normal);
-                Sphere::get_sphere_uv(
-                    &((rec.p - self.center) / self.radius),
-                    &mut rec.u,
-                    &mut rec.v,
-                );
-                rec.mat_ptr = self.mat_ptr.clone();
-                return true;
-            }
-            let tmp = (-b + root) / a;
-            if tmp < t_max && tmp > t_min {
-                rec.t = tmp;
-                rec.p = r.at(rec.t);
-                let outward_normal = (rec.p - self.center) / self.radius;
-                rec.set_face_normal(r, &outward_normal);
-                Sphere::get_sphere_uv(
-                    &((rec.p - self.center) / self.radius),
-                    &mut rec.u,
-                    &mut rec.v,
-                );
-                rec.mat_ptr = self.mat_ptr.clone();
-                return true;
-            }
-        }
-        false
+        hit_sphere(
+            &self.center,
+            self.radius,
+            &self.mat_ptr,
+            r,
+            t_min,
+            t_max,
+            rec,
+        )
     }
     fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
         *output_box = AABB::new(
@@ -122,4 +143,160 @@ impl HitTable for Sphere {
     fn distance(&self, other_center: &Point3) -> f64 {
         other_center.distance(self.center) - self.radius
     }
+    fn is_light(&self) -> bool {
+        self.mat_ptr.is_emissive()
+    }
+    // uniform over the cone of directions the sphere covers, or over all directions from inside
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        let oc = self.center - *o;
+        let distance_squared = oc.squared_length();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        if oc * *v < cos_theta_max * oc.length() * v.length() {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+    fn random(&self, o: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let oc = self.center - *o;
+        let distance_squared = oc.squared_length();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return sample_unit_vector(sampler.get_2d());
+        }
+        let (r1, r2) = sampler.get_2d();
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+        Onb::build_from_w(&oc).local(&Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+}
+
+// Sphere whose center moves in a straight line, from `center0` at `time0` to `center1` at
+// `time1`. Moving emitters are not sampled as lights.
+#[derive(Clone)]
+pub struct MovingSphere {
+    pub center0: Point3,
+    pub center1: Point3,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+    pub mat_ptr: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Point3,
+        center1: Point3,
+        time0: f64,
+        time1: f64,
+        r: f64,
+        m: Arc<dyn Material>,
+    ) -> Self {
+        assert!(time1 > time0);
+        Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius: r,
+            mat_ptr: m,
+        }
+    }
+    pub fn center(&self, time: f64) -> Point3 {
+        self.center0
+            + (self.center1 - self.center0) * ((time - self.time0) / (self.time1 - self.time0))
+    }
+}
+
+impl HitTable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        hit_sphere(
+            &self.center(r.time),
+            self.radius,
+            &self.mat_ptr,
+            r,
+            t_min,
+            t_max,
+            rec,
+        )
+    }
+    // the motion is linear, so the boxes at both ends cover everything in between
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut AABB) -> bool {
+        let extent = Vec3::new(self.radius, self.radius, self.radius);
+        let (c0, c1) = (self.center(t0), self.center(t1));
+        *output_box = AABB::surrounding_box(
+            &AABB::new(c0 - extent, c0 + extent),
+            &AABB::new(c1 - extent, c1 + extent),
+        );
+        true
+    }
+    // distance to the whole swept volume
+    fn distance(&self, other_center: &Point3) -> f64 {
+        let path = self.center1 - self.center0;
+        let length_squared = path.squared_length();
+        let s = if length_squared > 0.0 {
+            fmin(
+                fmax((*other_center - self.center0) * path / length_squared, 0.0),
+                1.0,
+            )
+        } else {
+            0.0
+        };
+        other_center.distance(self.center0 + path * s) - self.radius
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, vec3::Color};
+
+    #[test]
+    fn test_moving_sphere() {
+        let sphere = MovingSphere::new(
+            Point3::zero(),
+            Point3::new(4.0, 0.0, 0.0),
+            1.0,
+            3.0,
+            1.0,
+            Arc::new(Lambertian::new(Color::ones())),
+        );
+        // rays down onto where the sphere is at each end of the motion and halfway
+        for &(time, x) in &[(1.0, 0.0), (3.0, 4.0), (2.0, 2.0)] {
+            let mut rec = HitRecord::new(sphere.mat_ptr.clone());
+            for &(x_ray, hits) in &[(x, true), (4.0 - x, x == 2.0)] {
+                let r = Ray {
+                    orig: Point3::new(x_ray, 0.0, 5.0),
+                    dir: Vec3::new(0.0, 0.0, -1.0),
+                    time,
+                    wavelength: None,
+                };
+                assert_eq!(sphere.hit(&r, 0.001, f64::INFINITY, &mut rec), hits);
+            }
+            assert!((rec.t - 4.0).abs() < 1e-9);
+            assert!((rec.p - Point3::new(x, 0.0, 1.0)).length() < 1e-9);
+        }
+        let mut output_box = AABB::new(Point3::zero(), Point3::zero());
+        assert!(sphere.bounding_box(1.0, 3.0, &mut output_box));
+        assert!((output_box._min - Point3::new(-1.0, -1.0, -1.0)).length() < 1e-12);
+        assert!((output_box._max - Point3::new(5.0, 1.0, 1.0)).length() < 1e-12);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_moving_sphere_without_interval() {
+        MovingSphere::new(
+            Point3::zero(),
+            Point3::new(4.0, 0.0, 0.0),
+            1.0,
+            1.0,
+            1.0,
+            Arc::new(Lambertian::new(Color::ones())),
+        );
+    }
 }
@@ -2,7 +2,8 @@ use crate::{
     aabb::AABB,
     hittable::{HitRecord, HitTable},
     ray::Ray,
-    vec3::Point3,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
 };
 use std::{sync::Arc, vec};
 
@@ -24,6 +25,18 @@ impl HitTableList {
     pub fn add(&mut self, object: Arc<dyn HitTable>) {
         self.objects.push(object);
     }
+
+    // the objects that can be sampled as lights
+    pub fn lights(&self) -> HitTableList {
+        HitTableList {
+            objects: self
+                .objects
+                .iter()
+                .filter(|object| object.is_light())
+                .cloned()
+                .collect(),
+        }
+    }
 }
 
 impl Default for HitTableList {
@@ -41,6 +54,7 @@ impl HitTable for HitTableList {
             if object.hit(r, t_min, closest_so_far, &mut tmp_rec) {
                 hit_anything = true;
                 closest_so_far = tmp_rec.t;
+                tmp_rec.is_light = object.is_light();
                 *rec = tmp_rec.clone();
             }
         }
@@ -68,4 +82,21 @@ impl HitTable for HitTableList {
     fn distance(&self, _other_center: &Point3) -> f64 {
         0.0
     }
+    // one object chosen uniformly, then sampled
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|object| object.pdf_value(o, v))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+    fn random(&self, o: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let n = self.objects.len();
+        let index = ((sampler.get_1d() * n as f64) as usize).min(n - 1);
+        self.objects[index].random(o, sampler)
+    }
 }
@@ -0,0 +1,418 @@
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, HitTable},
+    hittablelist::HitTableList,
+    material::{Isotropic, Lambertian, Material, ScatterRecord},
+    ray::Ray,
+    rtweekend::{fmax, fmin},
+    sampler::Sampler,
+    spectrum::{hero_wavelengths, rgb_to_spectrum},
+    texture::ConstTexture,
+    vec3::{Color, Vec3},
+};
+use std::{f64::INFINITY, fmt, sync::Arc};
+
+// why a path stopped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Termination {
+    Escaped,
+    Absorbed,
+    MaxDepth,
+    Roulette,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PathStats {
+    pub paths: u64,
+    pub bounces: u64,
+    pub escaped: u64,
+    pub absorbed: u64,
+    pub max_depth: u64,
+    pub roulette: u64,
+}
+
+impl PathStats {
+    pub fn record(&mut self, bounces: i64, reason: Termination) {
+        self.paths += 1;
+        self.bounces += bounces as u64;
+        match reason {
+            Termination::Escaped => self.escaped += 1,
+            Termination::Absorbed => self.absorbed += 1,
+            Termination::MaxDepth => self.max_depth += 1,
+            Termination::Roulette => self.roulette += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &PathStats) {
+        self.paths += other.paths;
+        self.bounces += other.bounces;
+        self.escaped += other.escaped;
+        self.absorbed += other.absorbed;
+        self.max_depth += other.max_depth;
+        self.roulette += other.roulette;
+    }
+}
+
+impl fmt::Display for PathStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.paths.max(1) as f64;
+        let percent = |n: u64| 100.0 * n as f64 / total;
+        write!(
+            f,
+            "paths: {}, average depth {:.2}, escaped {:.1}%, absorbed {:.1}%, \
+             depth limit {:.1}%, roulette {:.1}%",
+            self.paths,
+            self.bounces as f64 / total,
+            percent(self.escaped),
+            percent(self.absorbed),
+            percent(self.max_depth),
+            percent(self.roulette)
+        )
+    }
+}
+
+// weight of a sample from the strategy with density `pdf` against one with `other_pdf`
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+// Homogeneous medium filling `bounds`, like haze over the whole scene. Unlike `ConstantMedium`
+// it also attenuates shadow rays exactly, instead of through a random scattering event.
+pub struct Atmosphere {
+    // scattering events per unit length
+    pub density: f64,
+    pub bounds: AABB,
+    phase_function: Arc<dyn Material>,
+}
+
+impl Atmosphere {
+    pub fn new(density: f64, albedo: Color, bounds: AABB) -> Self {
+        Self {
+            density,
+            bounds,
+            phase_function: Arc::new(Isotropic::new(albedo)),
+        }
+    }
+
+    // where `r` scatters before reaching `t_max`, if it does
+    fn sample_distance(&self, r: &Ray, t_max: f64, sampler: &mut dyn Sampler) -> Option<f64> {
+        let u = sampler.get_1d();
+        let (t0, t1) = self.bounds.clip(r, 0.001, t_max)?;
+        let t = t0 - (1.0 - u).ln() / (self.density * r.dir.length());
+        if t < t1 {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    // fraction of the light that crosses `r` up to `t_max` unscattered
+    fn transmittance(&self, r: &Ray, t_max: f64) -> f64 {
+        match self.bounds.clip(r, 0.001, t_max) {
+            Some((t0, t1)) => (-self.density * (t1 - t0) * r.dir.length()).exp(),
+            None => 1.0,
+        }
+    }
+}
+
+// Path tracer with next event estimation and Russian roulette. One is made per render job; the
+// hit and scatter records are reused, so tracing a path does not allocate.
+pub struct PathTracer<'a> {
+    world: &'a dyn HitTable,
+    lights: &'a HitTableList,
+    background: Color,
+    atmosphere: Option<&'a Atmosphere>,
+    max_depth: i64,
+    // bounces after which paths are randomly terminated
+    rr_depth: i64,
+    rec: HitRecord,
+    light_rec: HitRecord,
+    srec: ScatterRecord,
+    pub stats: PathStats,
+}
+
+impl<'a> PathTracer<'a> {
+    pub fn new(
+        world: &'a dyn HitTable,
+        lights: &'a HitTableList,
+        background: Color,
+        atmosphere: Option<&'a Atmosphere>,
+        max_depth: i64,
+        rr_depth: i64,
+    ) -> Self {
+        let rec = HitRecord::new(Arc::new(Lambertian {
+            albedo: Arc::new(ConstTexture {
+                color_value: Color::zero(),
+            }),
+        }));
+        Self {
+            world,
+            lights,
+            background,
+            atmosphere,
+            max_depth,
+            rr_depth,
+            light_rec: rec.clone(),
+            rec,
+            srec: ScatterRecord::new(),
+            stats: PathStats::default(),
+        }
+    }
+
+    // radiance arriving along `r`. When `r` carries a wavelength this is the spectral mode, and
+    // the channels of the result are the radiance at its `hero_wavelengths`.
+    pub fn trace(&mut self, r: &Ray, sampler: &mut dyn Sampler) -> Color {
+        let lambda = r.wavelength.map(hero_wavelengths);
+        let mut radiance = Color::zero();
+        let mut throughput = Color::ones();
+        let mut ray = r.clone();
+        // density the last bounce sampled `ray` with, when it sampled the lights too, so that
+        // emission found by `ray` is weighted against finding it through light sampling
+        let mut bsdf_pdf = None;
+        // whether dispersion has left only the hero wavelength
+        let mut single_wavelength = false;
+        let mut bounces = 0;
+        let reason = loop {
+            if bounces >= self.max_depth {
+                break Termination::MaxDepth;
+            }
+            let hit = self.world.hit(&ray, 0.001, INFINITY, &mut self.rec);
+            let t_max = if hit { self.rec.t } else { INFINITY };
+            let scattered = self.scatter_in_atmosphere(&ray, t_max, sampler);
+            if !hit && !scattered {
+                let background = match &lambda {
+                    Some(lambda) => rgb_to_spectrum(&self.background, lambda),
+                    None => self.background,
+                };
+                radiance += throughput.elemul(background);
+                break Termination::Escaped;
+            }
+            let mat = self.rec.mat_ptr.clone();
+            let (u, v, p) = (self.rec.u, self.rec.v, self.rec.p);
+            let mut emitted = match &lambda {
+                Some(lambda) => mat.emitted_spectral(u, v, &p, lambda),
+                None => mat.emitted(u, v, &p),
+            };
+            // emitters that are not lights, like volumes, moving objects and meshes, are never
+            // picked by light sampling, so they keep their full weight
+            if let Some(pdf) = bsdf_pdf {
+                if self.rec.is_light {
+                    emitted *= power_heuristic(pdf, self.lights.pdf_value(&ray.orig, &ray.dir));
+                }
+            }
+            radiance += throughput.elemul(emitted);
+            self.srec.dispersed = false;
+            if !mat.scatter(&ray, &self.rec, &mut self.srec, sampler) {
+                break Termination::Absorbed;
+            }
+            // the lights are sampled for the whole non-delta part of the BSDF, even when this
+            // sample came from a delta lobe of a mixed material
+            let light_sampling = mat.has_non_delta() && !self.lights.objects.is_empty();
+            if light_sampling {
+                radiance += throughput.elemul(self.sample_lights(&ray, lambda.as_ref(), sampler));
+            }
+            bsdf_pdf = if light_sampling && !self.srec.is_specular {
+                Some(self.srec.pdf)
+            } else {
+                None
+            };
+            let attenuation = match &lambda {
+                Some(lambda) => mat.attenuation_spectral(&ray, &self.rec, &self.srec, lambda),
+                None => self.srec.attenuation,
+            };
+            throughput = throughput.elemul(attenuation);
+            if lambda.is_some() && !single_wavelength && self.srec.dispersed {
+                // the other wavelengths would have gone elsewhere
+                throughput = Color::new(throughput.x * 3.0, 0.0, 0.0);
+                single_wavelength = true;
+            }
+            ray = self.srec.scattered.clone();
+            bounces += 1;
+            if bounces >= self.rr_depth {
+                let survive = fmin(fmax(throughput.x, fmax(throughput.y, throughput.z)), 1.0);
+                if sampler.get_1d() >= survive {
+                    break Termination::Roulette;
+                }
+                throughput *= 1.0 / survive;
+            }
+        };
+        self.stats.record(bounces, reason);
+        radiance
+    }
+
+    // turn `self.rec` into a scattering event of the atmosphere when `ray` has one before `t_max`
+    fn scatter_in_atmosphere(&mut self, ray: &Ray, t_max: f64, sampler: &mut dyn Sampler) -> bool {
+        let atmosphere = match self.atmosphere {
+            Some(atmosphere) => atmosphere,
+            None => return false,
+        };
+        let t = match atmosphere.sample_distance(ray, t_max, sampler) {
+            Some(t) => t,
+            None => return false,
+        };
+        let rec = &mut self.rec;
+        rec.t = t;
+        rec.p = ray.at(t);
+        rec.normal = -ray.dir.unit();
+        rec.front_face = true;
+        rec.is_light = false;
+        rec.u = 0.0;
+        rec.v = 0.0;
+        rec.mat_ptr = atmosphere.phase_function.clone();
+        true
+    }
+
+    // next event estimation at `self.rec`: one shadow ray towards a sampled light, weighted
+    // against the BSDF, at the wavelengths `lambda` in the spectral mode
+    fn sample_lights(
+        &mut self,
+        r: &Ray,
+        lambda: Option<&Vec3>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let rec = &self.rec;
+        let to_light = Ray {
+            orig: rec.p,
+            dir: self.lights.random(&rec.p, sampler),
+            time: r.time,
+            wavelength: r.wavelength,
+        };
+        let light_pdf = self.lights.pdf_value(&to_light.orig, &to_light.dir);
+        if light_pdf <= 0.0 {
+            return Color::zero();
+        }
+        let f = match lambda {
+            Some(lambda) => rec.mat_ptr.eval_spectral(r, rec, &to_light.dir, lambda),
+            None => rec.mat_ptr.eval(r, rec, &to_light.dir),
+        };
+        if f == Color::zero() {
+            return Color::zero();
+        }
+        // other emitters in the way are left to BSDF sampling
+        if !self
+            .world
+            .hit(&to_light, 0.001, INFINITY, &mut self.light_rec)
+            || !self.light_rec.is_light
+        {
+            return Color::zero();
+        }
+        let light_rec = &self.light_rec;
+        let (u, v, p) = (light_rec.u, light_rec.v, light_rec.p);
+        let emitted = match lambda {
+            Some(lambda) => light_rec.mat_ptr.emitted_spectral(u, v, &p, lambda),
+            None => light_rec.mat_ptr.emitted(u, v, &p),
+        };
+        let transmittance = match self.atmosphere {
+            Some(atmosphere) => atmosphere.transmittance(&to_light, light_rec.t),
+            None => 1.0,
+        };
+        let bsdf_pdf = rec.mat_ptr.pdf(r, rec, &to_light.dir);
+        f.elemul(emitted) * (transmittance * power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aarect::XZRect,
+        hittable::{MovingSphere, Sphere},
+        material::{Coated, DiffuseLight, Metal, MixMaterial},
+        microfacet::TrowbridgeReitz,
+        sampler::IndependentSampler,
+        vec3::Point3,
+    };
+
+    // a floor of `mat` under a spherical light
+    fn floor_under_light(mat: Arc<dyn Material>) -> HitTableList {
+        let mut world = HitTableList::default();
+        world.add(Arc::new(XZRect::new(-10.0, 10.0, -10.0, 10.0, 0.0, mat)));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 3.0, 0.0),
+            1.5,
+            Arc::new(DiffuseLight::new(Color::ones())),
+        )));
+        world
+    }
+
+    // mean radiance reflected off the floor of `world`, with the lights sampled or found by the
+    // BSDF alone
+    fn direct_light(world: &HitTableList, light_sampling: bool) -> f64 {
+        let lights = if light_sampling {
+            world.lights()
+        } else {
+            HitTableList::default()
+        };
+        let mut tracer = PathTracer::new(world, &lights, Color::zero(), None, 2, 10);
+        let r = Ray {
+            orig: Point3::new(2.0, 1.0, 0.0),
+            dir: Vec3::new(-1.0, -1.0, 0.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        let mut sampler = IndependentSampler::new(7);
+        let n = 100_000;
+        let mut sum = 0.0;
+        for i in 0..n {
+            sampler.start_sample(0, 0, i);
+            sum += tracer.trace(&r, &mut sampler).x;
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn test_light_sampling_mixed_lobes() {
+        let diffuse: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+        let mirror: Arc<dyn Material> = Arc::new(Metal::new(&Color::new(0.9, 0.9, 0.9), 0.0));
+        let materials: Vec<Arc<dyn Material>> = vec![
+            diffuse.clone(),
+            Arc::new(MixMaterial {
+                a: mirror,
+                b: diffuse.clone(),
+                weight: Arc::new(ConstTexture {
+                    color_value: Color::new(0.5, 0.5, 0.5),
+                }),
+            }),
+            Arc::new(Coated::new(diffuse, 1.5, TrowbridgeReitz::new(0.0, 0.0))),
+        ];
+        for mat in materials {
+            let world = floor_under_light(mat);
+            let nee = direct_light(&world, true);
+            let bsdf = direct_light(&world, false);
+            assert!((nee - bsdf).abs() < 0.02 * bsdf, "{} != {}", nee, bsdf);
+        }
+    }
+
+    #[test]
+    fn test_emitter_in_front_of_light() {
+        let mut world = floor_under_light(Arc::new(Lambertian::new(Color::ones())));
+        // moving objects are never sampled as lights
+        world.add(Arc::new(MovingSphere::new(
+            Point3::new(0.0, 1.2, 0.0),
+            Point3::new(0.0, 1.2, 0.0),
+            0.0,
+            1.0,
+            0.5,
+            Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0))),
+        )));
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::ones())));
+        for &(y, is_light) in &[(1.2, false), (3.0, true)] {
+            let r = Ray {
+                orig: Point3::new(5.0, y, 0.0),
+                dir: Vec3::new(-1.0, 0.0, 0.0),
+                time: 0.5,
+                wavelength: None,
+            };
+            assert!(world.hit(&r, 0.001, INFINITY, &mut rec));
+            assert_eq!(rec.is_light, is_light);
+        }
+        let nee = direct_light(&world, true);
+        let bsdf = direct_light(&world, false);
+        assert!((nee - bsdf).abs() < 0.02 * bsdf, "{} != {}", nee, bsdf);
+    }
+}
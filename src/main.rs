@@ -1,27 +1,53 @@
 mod aabb;
 mod aarect;
+mod bench;
 mod bvh;
 #[allow(clippy::float_cmp)]
 mod camera;
+mod cli;
 mod color;
 mod hittable;
 mod hittablelist;
+mod integrator;
 mod material;
+mod matrix;
+mod medium;
+mod mesh;
+mod microfacet;
+mod noise;
+mod obj;
+mod onb;
 mod ray;
 mod rtweekend;
+mod sampler;
+mod scenefile;
+mod shapes;
+mod spectrum;
 mod texture;
+mod transform;
+mod triangle;
 mod vec3;
+use aabb::AABB;
 use aarect::XYRect;
-use bvh::BVHNode;
-use camera::Camera;
-use color::{ray_color, write_color};
-use hittable::Sphere;
+use bench::bench_bvh;
+use bvh::{BVHNode, FlatBVH};
+use camera::CameraSettings;
+use cli::{BVHLayout, CliError, Options, SceneKind, USAGE};
+use color::write_color;
+use hittable::{HitTable, Sphere};
 use hittablelist::HitTableList;
 use image::{GenericImageView, ImageBuffer, RgbImage};
 use indicatif::ProgressBar;
+use integrator::{Atmosphere, PathStats, PathTracer};
 use material::{Dielectric, DiffuseLight, FrostedGlass, Lambertian, Metal};
-use rtweekend::random_double;
-use std::sync::{mpsc::channel, Arc};
+use rtweekend::Rng;
+use sampler::IndependentSampler;
+use scenefile::{load_scene, Scene};
+use spectrum::{hero_wavelengths, sample_wavelength, spectrum_to_xyz, xyz_to_film};
+use std::{
+    env, process,
+    sync::{mpsc::channel, Arc},
+};
 use texture::{CheckerTexture, ConstTexture};
 use threadpool::ThreadPool;
 use vec3::{randomvec, Color, Point3, Vec3};
@@ -59,7 +85,7 @@ pub fn check(world: &HitTableList, center: &Point3) -> bool {
     true
 }
 
-pub fn read_image() -> HitTableList {
+pub fn read_image(rng: &mut Rng) -> HitTableList {
     let mut world = HitTableList::new();
     let image1 = image::open("src/1.png").unwrap();
     for a in 0..image1.width() {
@@ -95,7 +121,7 @@ pub fn read_image() -> HitTableList {
             }
         }
     }
-    let albedo = randomvec().elemul(randomvec());
+    let albedo = randomvec(rng).elemul(randomvec(rng));
     // let fuzz = random_double(0.0, 0.5);
     let fuzz = 0.0;
     let sphere_material1 = Arc::new(Metal::new(&albedo, fuzz));
@@ -128,7 +154,7 @@ pub fn read_image() -> HitTableList {
     world
 }
 
-pub fn random_scene() -> HitTableList {
+pub fn random_scene(rng: &mut Rng) -> HitTableList {
     let mut world = HitTableList::new();
     let checker = Arc::new(CheckerTexture::new(
         Color::new(0.2, 0.3, 0.1),
@@ -150,24 +176,24 @@ pub fn random_scene() -> HitTableList {
     )));
     for a in -15..15 {
         for b in -15..15 {
-            let choose_mat = random_double(0.0, 1.0);
+            let choose_mat = rng.random_double(0.0, 1.0);
             let center = Point3::new(
-                a as f64 + 0.9 * random_double(0.0, 1.0),
-                random_double(0.05, 0.5),
-                b as f64 + 0.9 * random_double(0.0, 1.0),
+                a as f64 + 0.9 * rng.random_double(0.0, 1.0),
+                rng.random_double(0.05, 0.5),
+                b as f64 + 0.9 * rng.random_double(0.0, 1.0),
             );
             if !check(&world, &center) {
                 continue;
             }
             if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.2 {
-                    // let difflight = randomvec().elemul(randomvec()) * 2.0;
+                    // let difflight = randomvec(rng).elemul(randomvec(rng)) * 2.0;
                     // let sphere_material = Arc::new(DiffuseLight::new(difflight));
                     // world.add(Arc::new(Sphere::new(center, center.y, sphere_material)));
                 } else if choose_mat < 0.5 {
                     let sphere_material1 = Arc::new(FrostedGlass::new(1.5, choose_mat));
                     world.add(Arc::new(Sphere::new(center, center.y, sphere_material1)));
-                    let difflight = randomvec().elemul(randomvec()) * 2.0;
+                    let difflight = randomvec(rng).elemul(randomvec(rng)) * 2.0;
                     let sphere_material2 = Arc::new(DiffuseLight::new(difflight));
                     world.add(Arc::new(Sphere::new(
                         center,
@@ -175,12 +201,12 @@ pub fn random_scene() -> HitTableList {
                         sphere_material2,
                     )));
                 } else if choose_mat < 0.6 {
-                    let albedo = randomvec().elemul(randomvec());
+                    let albedo = randomvec(rng).elemul(randomvec(rng));
                     let sphere_material = Arc::new(Lambertian::new(albedo));
                     world.add(Arc::new(Sphere::new(center, center.y, sphere_material)));
                 } else if choose_mat < 0.8 {
-                    let albedo = randomvec().elemul(randomvec());
-                    let fuzz = random_double(0.0, 0.5);
+                    let albedo = randomvec(rng).elemul(randomvec(rng));
+                    let fuzz = rng.random_double(0.0, 0.5);
                     let sphere_material = Arc::new(Metal::new(&albedo, fuzz));
                     world.add(Arc::new(Sphere::new(center, center.y, sphere_material)));
                 } else {
@@ -197,43 +223,159 @@ pub fn is_ci() -> bool {
     option_env!("CI").unwrap_or_default() == "true"
 }
 
+// default camera for each built-in scene
+pub fn scene_camera(scene: SceneKind) -> CameraSettings {
+    let (lookfrom, lookat, vfov, aperture, focus_dist) = match scene {
+        SceneKind::RandomScene => (
+            Point3::new(13.0, 2.0, 3.0),
+            Point3::new(0.0, 0.0, 0.0),
+            20.0,
+            0.1,
+            10.0,
+        ),
+        SceneKind::ReadImage => (
+            Point3::new(10.0, 10.0, 16.0),
+            Point3::new(0.0, 4.0, -3.0),
+            60.0,
+            0.0,
+            15.0,
+        ),
+        SceneKind::SimpleLight => (
+            Point3::new(26.0, 3.0, 6.0),
+            Point3::new(0.0, 2.0, 0.0),
+            20.0,
+            0.0,
+            10.0,
+        ),
+    };
+    CameraSettings {
+        lookfrom,
+        lookat,
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov,
+        aperture,
+        focus_dist,
+        ..CameraSettings::default()
+    }
+}
+
 fn main() {
     // get environment variable CI, which is true for GitHub Action
     let is_ci = is_ci();
-    // jobs: split image into how many parts
-    // workers: maximum allowed concurrent running threads
-    let (n_jobs, n_workers): (usize, usize) = if is_ci { (32, 2) } else { (16, 4) };
+    let opts = match Options::parse(env::args().skip(1), is_ci) {
+        Ok(opts) => opts,
+        Err(CliError::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    };
+    let (n_jobs, n_workers) = (opts.n_jobs, opts.n_workers);
     println!(
         "CI: {}, using {} jobs and {} workers",
         is_ci, n_jobs, n_workers
     );
+    // the scene layout and every pixel are derived from this seed
+    let seed = opts.seed.unwrap_or_else(rand::random);
+    println!("seed: {}", seed);
+    let mut rng = Rng::new(seed);
     // image
-    let aspect_ratio = 16.0 / 9.0;
-    let image_width = 1600;
-    let image_height = (image_width as f64 / aspect_ratio) as u32;
-    let samples_per_pixel = 200;
-    let max_depth = 50;
+    let aspect_ratio = opts.aspect_ratio();
+    let image_width = opts.image_width;
+    let image_height = opts.image_height;
+    let samples_per_pixel = opts.samples_per_pixel;
+    let max_depth = opts.max_depth;
+    let rr_depth = opts.rr_depth;
+    if opts.bench_bvh {
+        for &kind in &[
+            SceneKind::RandomScene,
+            SceneKind::ReadImage,
+            SceneKind::SimpleLight,
+        ] {
+            let mut rng = Rng::new(seed);
+            let world = match kind {
+                SceneKind::RandomScene => random_scene(&mut rng),
+                SceneKind::ReadImage => read_image(&mut rng),
+                SceneKind::SimpleLight => simple_light(),
+            };
+            let cam = scene_camera(kind).build(aspect_ratio);
+            let name = format!("{:?}", kind);
+            bench_bvh(
+                &name,
+                &world,
+                &cam,
+                image_width,
+                image_height,
+                opts.max_leaf_size,
+                &mut IndependentSampler::new(seed),
+            );
+        }
+        return;
+    }
     // World
-    // let mut world = random_scene();
-    let mut world = read_image();
-    let length = world.objects.len();
-    let world = BVHNode::new(&mut world.objects, 0, length, 0.0, 0.1);
-    let background = Color::new(0.0, 0.0, 0.0);
+    let scene = match &opts.scene_file {
+        Some(path) => match load_scene(path) {
+            Ok(scene) => scene,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                process::exit(1);
+            }
+        },
+        None => Scene {
+            world: match opts.scene {
+                SceneKind::RandomScene => random_scene(&mut rng),
+                SceneKind::ReadImage => read_image(&mut rng),
+                SceneKind::SimpleLight => simple_light(),
+            },
+            camera: scene_camera(opts.scene),
+            background: Color::new(0.0, 0.0, 0.0),
+            atmosphere: None,
+        },
+    };
     // Camera
-    let lookfrom = Point3::new(10.0, 10.0, 16.0);
-    let lookat = Point3::new(0.0, 4.0, -3.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 15.0;
-    let aperture = 0.0;
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        60.0,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-    );
+    let mut settings = scene.camera;
+    settings.lookfrom = opts.lookfrom.unwrap_or(settings.lookfrom);
+    settings.lookat = opts.lookat.unwrap_or(settings.lookat);
+    settings.vfov = opts.vfov.unwrap_or(settings.vfov);
+    settings.aperture = opts.aperture.unwrap_or(settings.aperture);
+    settings.focus_dist = opts.focus_dist.unwrap_or(settings.focus_dist);
+    if let Some((open, close)) = opts.shutter {
+        settings.time0 = open;
+        settings.time1 = close;
+    }
+    let cam = settings.build(aspect_ratio);
+    let lights = Arc::new(scene.world.lights());
+    let world: Arc<dyn HitTable> = match opts.bvh {
+        BVHLayout::Tree => {
+            let bvh = BVHNode::new(
+                &scene.world.objects,
+                settings.time0,
+                settings.time1,
+                opts.max_leaf_size,
+            );
+            println!("{}", bvh.stats());
+            Arc::new(bvh)
+        }
+        BVHLayout::Flat => {
+            let bvh = FlatBVH::new(
+                &scene.world.objects,
+                settings.time0,
+                settings.time1,
+                opts.max_leaf_size,
+            );
+            println!("{}", bvh.stats());
+            Arc::new(bvh)
+        }
+    };
+    let background = scene.background;
+    let atmosphere = scene.atmosphere.map(|(density, albedo)| {
+        let mut bounds = AABB::new(Point3::zero(), Point3::zero());
+        world.bounding_box(settings.time0, settings.time1, &mut bounds);
+        Arc::new(Atmosphere::new(density, albedo, bounds))
+    });
     // create a channel to send objects between threads
     let (tx, rx) = channel();
     let pool = ThreadPool::new(n_workers);
@@ -241,7 +383,20 @@ fn main() {
     for i in 0..n_jobs {
         let tx = tx.clone();
         let world_ptr = world.clone();
+        let lights = lights.clone();
+        let atmosphere = atmosphere.clone();
+        let sampler_kind = opts.sampler;
+        let spectral = opts.spectral;
         pool.execute(move || {
+            let mut sampler = sampler_kind.build(seed, samples_per_pixel as u32);
+            let mut tracer = PathTracer::new(
+                &*world_ptr,
+                &lights,
+                background,
+                atmosphere.as_deref(),
+                max_depth,
+                rr_depth,
+            );
             // here, we render some of the rows of image in one thread
             let row_begin = image_height as usize * i / n_jobs;
             let row_end = image_height as usize * (i + 1) / n_jobs;
@@ -253,23 +408,43 @@ fn main() {
                 for (img_y, y) in (row_begin..row_end).enumerate() {
                     let y = y as u32;
                     let mut pixel_color = Color::zero();
-                    for _s in 0..samples_per_pixel {
-                        let u = (x as f64 + random_double(0.0, 1.0)) / (image_width - 1) as f64;
-                        let v = ((image_height - y) as f64 + random_double(0.0, 1.0))
-                            / (image_height - 1) as f64;
-                        let r = cam.get_ray(u, v);
-                        pixel_color += ray_color(&r, &background, &world_ptr, max_depth);
+                    for s in 0..samples_per_pixel {
+                        sampler.start_sample(x, y, s as u32);
+                        let (du, dv) = sampler.get_2d();
+                        let u = (x as f64 + du) / (image_width - 1) as f64;
+                        let v = ((image_height - y) as f64 + dv) / (image_height - 1) as f64;
+                        let lens = sampler.get_2d();
+                        let shutter = if cam.has_motion() {
+                            sampler.get_1d()
+                        } else {
+                            0.0
+                        };
+                        let mut r = cam.get_ray(u, v, lens, shutter);
+                        if spectral {
+                            // the film adds up XYZ, turned into sRGB once the pixel is done
+                            let hero = sample_wavelength(sampler.get_1d());
+                            r.wavelength = Some(hero);
+                            let radiance = tracer.trace(&r, &mut *sampler);
+                            pixel_color += spectrum_to_xyz(&radiance, &hero_wavelengths(hero));
+                        } else {
+                            pixel_color += tracer.trace(&r, &mut *sampler);
+                        }
+                    }
+                    if spectral {
+                        pixel_color = xyz_to_film(&pixel_color);
                     }
                     write_color(&mut img, x, img_y as u32, &pixel_color, samples_per_pixel);
                 }
             }
-            // send row range and rendered image to main thread
-            tx.send((row_begin..row_end, img))
+            // send row range, rendered image and path statistics to main thread
+            tx.send((row_begin..row_end, img, tracer.stats))
                 .expect("failed to send result");
         });
     }
     let mut result: RgbImage = ImageBuffer::new(image_width, image_height);
-    for (rows, data) in rx.iter().take(n_jobs) {
+    let mut stats = PathStats::default();
+    for (rows, data, job_stats) in rx.iter().take(n_jobs) {
+        stats.merge(&job_stats);
         // idx is the corrsponding row in partial-rendered image
         for (idx, row) in rows.enumerate() {
             for col in 0..image_width {
@@ -281,21 +456,26 @@ fn main() {
         bar.inc(1);
     }
     bar.finish();
+    println!("{}", stats);
     /* Main Loop without Multithreading
     for x in 0..image_width {
         for y in 0..image_height {
             let mut pixel_color = Color::zero();
-            for _s in 0..samples_per_pixel {
-                let u = (x as f64 + random_double(0.0, 1.0)) / (image_width - 1) as f64;
-                let v = ((image_height - y) as f64 + random_double(0.0, 1.0))
+            for s in 0..samples_per_pixel {
+                sampler.start_sample(x, y, s as u32);
+                let (du, dv) = sampler.get_2d();
+                let u = (x as f64 + du) / (image_width - 1) as f64;
+                let v = ((image_height - y) as f64 + dv)
                     / (image_height - 1) as f64;
-                let r = cam.get_ray(u, v);
-                pixel_color += ray_color(&r, &background, &world, max_depth);
+                let lens = sampler.get_2d();
+                let shutter = if cam.has_motion() { sampler.get_1d() } else { 0.0 };
+                let r = cam.get_ray(u, v, lens, shutter);
+                pixel_color += tracer.trace(&r, &mut *sampler);
             }
             write_color(&mut img, x, y, &pixel_color, samples_per_pixel);
         }
         bar.inc(1);
     } */
     // Save
-    result.save("output/test.png").unwrap();
+    result.save(&opts.output).unwrap();
 }
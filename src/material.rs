@@ -1,21 +1,112 @@
 use crate::{
     hittable::HitRecord,
+    microfacet::{
+        fresnel_conductor, fresnel_dielectric, refract_across, TrowbridgeReitz, SMOOTH_ALPHA,
+    },
+    onb::Onb,
     ray::Ray,
-    rtweekend::{fmin, random_double},
+    rtweekend::{clamp, fmax, fmin},
+    sampler::Sampler,
+    spectrum::{
+        film_color, interpolate_ior, planck, rgb_to_spectrum, sample_wavelength, wavelength_to_rgb,
+        Dispersion,
+    },
     texture::{ConstTexture, Texture},
-    vec3::{random_in_unit_sphere, random_unit_vector, reflect, refract, Color, Point3},
+    vec3::{
+        reflect, refract, sample_cosine_direction, sample_in_unit_sphere, sample_unit_vector,
+        Color, Point3, Vec3,
+    },
 };
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
+
+// One sampled continuation of a path. For non-specular samples `attenuation` is
+// `eval(scattered) / pdf`, for specular ones `pdf` is meaningless and `attenuation` is the
+// weight of the only possible direction. Materials made of others push the component they
+// sampled onto the low bit of `choices`, so the spectral weight of a specular sample can be
+// asked of the same component afterwards. `dispersed` is set when the direction only holds for
+// the wavelength of the scattered ray.
+#[derive(Clone, Debug)]
+pub struct ScatterRecord {
+    pub scattered: Ray,
+    pub attenuation: Color,
+    pub pdf: f64,
+    pub is_specular: bool,
+    pub choices: u32,
+    pub dispersed: bool,
+}
+
+impl ScatterRecord {
+    pub fn new() -> Self {
+        Self {
+            scattered: Ray {
+                orig: Point3::zero(),
+                dir: Vec3::zero(),
+                time: 0.0,
+                wavelength: None,
+            },
+            attenuation: Color::zero(),
+            pdf: 0.0,
+            is_specular: false,
+            choices: 0,
+            dispersed: false,
+        }
+    }
+}
+
+impl Default for ScatterRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub trait Material: Send + Sync {
+    // sample the direction the path continues in, false when the path is absorbed
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool;
+    // BSDF times |cos| for light arriving along `dir` and leaving along `-r_in.dir`, zero for
+    // specular (delta) lobes
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _dir: &Vec3) -> Color {
+        Color::zero()
+    }
+    // solid angle density of `scatter` choosing `dir`, zero for specular lobes
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _dir: &Vec3) -> f64 {
+        0.0
+    }
+    // whether `eval` is non-zero somewhere, so that sampling the lights can find light for the
+    // material whichever lobe `scatter` picks
+    fn has_non_delta(&self) -> bool {
+        false
+    }
     fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color;
+    fn is_emissive(&self) -> bool {
+        false
+    }
+    // counterparts of `eval`, the attenuation of a sample and `emitted` for the spectral mode, at
+    // the wavelengths `lambda`. By default the RGB values are upsampled.
+    fn eval_spectral(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3, lambda: &Vec3) -> Vec3 {
+        rgb_to_spectrum(&self.eval(r_in, rec, dir), lambda)
+    }
+    fn attenuation_spectral(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        srec: &ScatterRecord,
+        lambda: &Vec3,
+    ) -> Vec3 {
+        rgb_to_spectrum(&srec.attenuation, lambda)
+    }
+    fn emitted_spectral(&self, u: f64, v: f64, p: &Point3, lambda: &Vec3) -> Vec3 {
+        rgb_to_spectrum(&self.emitted(u, v, p), lambda)
+    }
+    // whether the direction `scatter` picks depends on the wavelength of the ray
+    fn is_dispersive(&self) -> bool {
+        false
+    }
 }
 
 pub struct Lambertian {
@@ -31,19 +122,42 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
+    // cosine-weighted hemisphere sampling, which cancels the cosine and 1 / pi of the BSDF
     fn scatter(
         &self,
-        _r_in: &Ray,
+        r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        let scatter_direction = rec.normal + random_unit_vector();
-        *scattered = Ray {
+        let dir = Onb::build_from_w(&rec.normal).local(&sample_cosine_direction(sampler.get_2d()));
+        srec.scattered = Ray {
             orig: rec.p,
-            dir: scatter_direction,
+            dir,
+            time: r_in.time,
+            wavelength: r_in.wavelength,
         };
-        *attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        srec.pdf = rec.normal * dir / PI;
+        srec.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        srec.is_specular = false;
+        srec.pdf > 0.0
+    }
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> Color {
+        let cosine = rec.normal * dir.unit();
+        if cosine <= 0.0 {
+            return Color::zero();
+        }
+        self.albedo.value(rec.u, rec.v, &rec.p) * (cosine / PI)
+    }
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> f64 {
+        let cosine = rec.normal * dir.unit();
+        if cosine <= 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
+    }
+    fn has_non_delta(&self) -> bool {
         true
     }
     fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
@@ -72,33 +186,64 @@ impl Metal {
 }
 
 impl Material for Metal {
+    // a delta lobe, perturbed by `fuzz` without a known density
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool {
         let reflected = reflect(&r_in.dir.unit(), &rec.normal);
-        *scattered = Ray {
+        srec.scattered = Ray {
             orig: rec.p,
-            dir: reflected + random_in_unit_sphere() * self.fuzz,
+            dir: reflected + sample_in_unit_sphere(sampler.get_2d(), sampler.get_1d()) * self.fuzz,
+            time: r_in.time,
+            wavelength: r_in.wavelength,
         };
-        *attenuation = self.albedo;
-        scattered.dir * rec.normal > 0.0
+        srec.attenuation = self.albedo;
+        srec.is_specular = true;
+        srec.scattered.dir * rec.normal > 0.0
     }
     fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
         Color::zero()
     }
 }
 
+// Clear glass. Light travelling inside is absorbed per unit distance by `absorption`
+// (Beer-Lambert), which assumes the object is closed and nothing else is inside it. With a
+// `dispersion` the first refraction picks a single wavelength for the rest of the path.
 pub struct Dielectric {
     pub ref_idx: f64,
+    pub absorption: Color,
+    pub dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
     pub fn new(r: f64) -> Self {
-        Self { ref_idx: r }
+        Self {
+            ref_idx: r,
+            absorption: Color::zero(),
+            dispersion: None,
+        }
+    }
+
+    // index of the far side over the near side at `rec`, for glass of index `ref_idx`
+    fn relative_ior(rec: &HitRecord, ref_idx: f64) -> f64 {
+        if rec.front_face {
+            1.0 / ref_idx
+        } else {
+            ref_idx
+        }
+    }
+
+    // chance of reflecting at an angle with cosine `cos_theta`, certain past the critical angle
+    fn reflectance(cos_theta: f64, eta: f64) -> f64 {
+        if eta * (1.0 - cos_theta * cos_theta).sqrt() > 1.0 {
+            1.0
+        } else {
+            schlick(cos_theta, eta)
+        }
     }
 }
 
@@ -107,44 +252,79 @@ impl Material for Dielectric {
         &self,
         r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        *attenuation = Color::ones();
-        let mut etai_over_etat = 1.0 / self.ref_idx;
+        srec.attenuation = Color::ones();
+        srec.is_specular = true;
         if !rec.front_face {
-            etai_over_etat = self.ref_idx;
+            // the ray has crossed the inside since it entered
+            let distance = rec.t * r_in.dir.length();
+            let a = self.absorption * distance;
+            srec.attenuation = Color::new((-a.x).exp(), (-a.y).exp(), (-a.z).exp());
         }
+        let mut wavelength = r_in.wavelength;
+        let ref_idx = match (&self.dispersion, wavelength) {
+            (Some(dispersion), Some(lambda)) => dispersion.ior(lambda),
+            _ => self.ref_idx,
+        };
         let unit_dir = r_in.dir.unit();
         let cos_theta = fmin(1.0, -unit_dir * rec.normal);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        if etai_over_etat * sin_theta > 1.0 {
-            let reflected = reflect(&unit_dir, &rec.normal);
-            *scattered = Ray {
-                orig: rec.p,
-                dir: reflected,
-            };
-            return true;
-        }
-        let reflect_prob = schlick(cos_theta, etai_over_etat);
-        if random_double(0.0, 1.0) < reflect_prob {
-            let reflected = reflect(&unit_dir, &rec.normal);
-            *scattered = Ray {
-                orig: rec.p,
-                dir: reflected,
-            };
-            return true;
+        let mut eta = Self::relative_ior(rec, ref_idx);
+        let mut reflected = eta * sin_theta > 1.0 || sampler.get_1d() < schlick(cos_theta, eta);
+        if let (Some(dispersion), None, false) = (&self.dispersion, wavelength, reflected) {
+            // only refraction splits the light, reflections keep all of its colors
+            let lambda = sample_wavelength(sampler.get_1d());
+            wavelength = Some(lambda);
+            srec.attenuation = srec.attenuation.elemul(wavelength_to_rgb(lambda));
+            eta = Self::relative_ior(rec, dispersion.ior(lambda));
+            reflected = eta * sin_theta > 1.0;
         }
-        let refracted = refract(&unit_dir, &rec.normal, etai_over_etat);
-        *scattered = Ray {
+        let dir = if reflected {
+            reflect(&unit_dir, &rec.normal)
+        } else {
+            refract(&unit_dir, &rec.normal, eta)
+        };
+        srec.scattered = Ray {
             orig: rec.p,
-            dir: refracted,
+            dir,
+            time: r_in.time,
+            wavelength,
         };
+        srec.dispersed = !reflected && self.dispersion.is_some() && wavelength.is_some();
         true
     }
     fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
         Color::zero()
     }
+    // The hero wavelength chose between reflecting and refracting by its own reflectance. The
+    // other wavelengths reflect along with it, weighted by theirs.
+    fn attenuation_spectral(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        lambda: &Vec3,
+    ) -> Vec3 {
+        let attenuation = rgb_to_spectrum(&srec.attenuation, lambda);
+        let dispersion = match &self.dispersion {
+            Some(dispersion) if !srec.dispersed => dispersion,
+            _ => return attenuation,
+        };
+        let cos_theta = fmin(1.0, -r_in.dir.unit() * rec.normal);
+        let reflectance =
+            |l: f64| Self::reflectance(cos_theta, Self::relative_ior(rec, dispersion.ior(l)));
+        let hero = reflectance(lambda.x);
+        attenuation.elemul(Vec3::new(
+            1.0,
+            reflectance(lambda.y) / hero,
+            reflectance(lambda.z) / hero,
+        ))
+    }
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some()
+    }
 }
 
 pub struct FrostedGlass {
@@ -166,10 +346,11 @@ impl Material for FrostedGlass {
         &self,
         r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        *attenuation = Color::ones();
+        srec.attenuation = Color::ones();
+        srec.is_specular = true;
         let mut etai_over_etat = 1.0 / self.ref_idx;
         if !rec.front_face {
             etai_over_etat = self.ref_idx;
@@ -179,25 +360,31 @@ impl Material for FrostedGlass {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
         if etai_over_etat * sin_theta > 1.0 {
             let reflected = reflect(&unit_dir, &rec.normal);
-            *scattered = Ray {
+            srec.scattered = Ray {
                 orig: rec.p,
                 dir: reflected,
+                time: r_in.time,
+                wavelength: r_in.wavelength,
             };
             return true;
         }
         let reflect_prob = schlick(cos_theta, etai_over_etat);
-        if random_double(0.0, 1.0) < reflect_prob {
+        if sampler.get_1d() < reflect_prob {
             let reflected = reflect(&unit_dir, &rec.normal);
-            *scattered = Ray {
+            srec.scattered = Ray {
                 orig: rec.p,
                 dir: reflected,
+                time: r_in.time,
+                wavelength: r_in.wavelength,
             };
             return true;
         }
         let refracted = refract(&unit_dir, &rec.normal, etai_over_etat);
-        *scattered = Ray {
+        srec.scattered = Ray {
             orig: rec.p,
-            dir: refracted + random_in_unit_sphere() * self.fuzz,
+            dir: refracted + sample_in_unit_sphere(sampler.get_2d(), sampler.get_1d()) * self.fuzz,
+            time: r_in.time,
+            wavelength: r_in.wavelength,
         };
         true
     }
@@ -206,6 +393,779 @@ impl Material for FrostedGlass {
     }
 }
 
+// `-r_in.dir` in the frame around the shading normal, which faces the incoming ray
+fn local_frame(r_in: &Ray, rec: &HitRecord) -> (Onb, Vec3) {
+    let frame = Onb::build_from_w(&rec.normal);
+    let wo = frame.project(&-r_in.dir.unit());
+    (frame, wo)
+}
+
+// Metal with GGX microfacets and the Fresnel reflectance of its complex index of refraction.
+// Anisotropic roughness is aligned with the frame `Onb::build_from_w` builds around the normal.
+// The spectral mode reads the index from a measured `spectrum` of (wavelength, eta, k) when
+// there is one, and upsamples `eta` and `k` otherwise.
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub distribution: TrowbridgeReitz,
+    pub spectrum: Option<&'static [(f64, f64, f64)]>,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, distribution: TrowbridgeReitz) -> Self {
+        Self {
+            eta,
+            k,
+            distribution,
+            spectrum: None,
+        }
+    }
+
+    // Fresnel reflectance at the wavelengths `lambda`
+    fn fresnel_spectral(&self, cos_i: f64, lambda: &Vec3) -> Vec3 {
+        let (eta, k) = match self.spectrum {
+            Some(table) => {
+                let (x, y, z) = (
+                    interpolate_ior(table, lambda.x),
+                    interpolate_ior(table, lambda.y),
+                    interpolate_ior(table, lambda.z),
+                );
+                (Vec3::new(x.0, y.0, z.0), Vec3::new(x.1, y.1, z.1))
+            }
+            None => (
+                rgb_to_spectrum(&self.eta, lambda),
+                rgb_to_spectrum(&self.k, lambda),
+            ),
+        };
+        fresnel_conductor(cos_i, &eta, &k)
+    }
+
+    // `eval_local` at the wavelengths `lambda`
+    fn eval_local_spectral(&self, wo: &Vec3, wi: &Vec3, lambda: &Vec3) -> Vec3 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vec3::zero();
+        }
+        let wm = *wo + *wi;
+        if wm.squared_length() == 0.0 {
+            return Vec3::zero();
+        }
+        let wm = wm.unit();
+        self.fresnel_spectral(*wo * wm, lambda)
+            * (self.distribution.d(&wm) * self.distribution.g(wo, wi) / (4.0 * wo.z))
+    }
+
+    fn eval_local(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::zero();
+        }
+        let wm = *wo + *wi;
+        if wm.squared_length() == 0.0 {
+            return Color::zero();
+        }
+        let wm = wm.unit();
+        let f = fresnel_conductor(*wo * wm, &self.eta, &self.k);
+        f * (self.distribution.d(&wm) * self.distribution.g(wo, wi) / (4.0 * wo.z))
+    }
+
+    fn pdf_local(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wm = *wo + *wi;
+        if wm.squared_length() == 0.0 {
+            return 0.0;
+        }
+        let wm = wm.unit();
+        self.distribution.visible_pdf(wo, &wm) / (4.0 * (*wo * wm))
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let (frame, wo) = local_frame(r_in, rec);
+        if wo.z <= 0.0 {
+            return false;
+        }
+        let u = sampler.get_2d();
+        let wi = if self.distribution.is_smooth() {
+            srec.attenuation = fresnel_conductor(wo.z, &self.eta, &self.k);
+            srec.is_specular = true;
+            Vec3::new(-wo.x, -wo.y, wo.z)
+        } else {
+            let wm = self.distribution.sample_wm(&wo, u);
+            let wi = reflect(&-wo, &wm);
+            srec.pdf = self.pdf_local(&wo, &wi);
+            if srec.pdf <= 0.0 {
+                return false;
+            }
+            srec.attenuation = self.eval_local(&wo, &wi) / srec.pdf;
+            srec.is_specular = false;
+            wi
+        };
+        srec.scattered = Ray {
+            orig: rec.p,
+            dir: frame.local(&wi),
+            time: r_in.time,
+            wavelength: r_in.wavelength,
+        };
+        true
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> Color {
+        if self.distribution.is_smooth() {
+            return Color::zero();
+        }
+        let (frame, wo) = local_frame(r_in, rec);
+        self.eval_local(&wo, &frame.project(&dir.unit()))
+    }
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+        let (frame, wo) = local_frame(r_in, rec);
+        self.pdf_local(&wo, &frame.project(&dir.unit()))
+    }
+    fn has_non_delta(&self) -> bool {
+        !self.distribution.is_smooth()
+    }
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        Color::zero()
+    }
+    fn eval_spectral(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3, lambda: &Vec3) -> Vec3 {
+        if self.distribution.is_smooth() {
+            return Vec3::zero();
+        }
+        let (frame, wo) = local_frame(r_in, rec);
+        self.eval_local_spectral(&wo, &frame.project(&dir.unit()), lambda)
+    }
+    fn attenuation_spectral(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        lambda: &Vec3,
+    ) -> Vec3 {
+        let (frame, wo) = local_frame(r_in, rec);
+        if srec.is_specular {
+            return self.fresnel_spectral(wo.z, lambda);
+        }
+        let wi = frame.project(&srec.scattered.dir.unit());
+        self.eval_local_spectral(&wo, &wi, lambda) / srec.pdf
+    }
+}
+
+// Glass with GGX microfacets on its surface (Walter et al. 2007, "Microfacet Models for
+// Refraction through Rough Surfaces"). Transmitted radiance is scaled by the squared ratio of
+// the indices, so light entering and leaving an object evens out.
+pub struct RoughDielectric {
+    pub ior: f64,
+    pub distribution: TrowbridgeReitz,
+}
+
+impl RoughDielectric {
+    pub fn new(ior: f64, distribution: TrowbridgeReitz) -> Self {
+        Self { ior, distribution }
+    }
+
+    // index beyond the surface over the index on the side of the incoming ray
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.ior
+        } else {
+            1.0 / self.ior
+        }
+    }
+
+    // BSDF times |cos| and the density of sampling `wi`, which share the generalized half vector
+    fn eval_pdf_local(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> (f64, f64) {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return (0.0, 0.0);
+        }
+        let reflect = wi.z > 0.0;
+        let wm = if reflect { *wi + *wo } else { *wi * eta + *wo };
+        if wm.squared_length() == 0.0 {
+            return (0.0, 0.0);
+        }
+        let wm = if wm.z < 0.0 { -wm.unit() } else { wm.unit() };
+        // facets seen from behind by either direction do not contribute
+        if (wm * *wi) * wi.z < 0.0 || (wm * *wo) < 0.0 {
+            return (0.0, 0.0);
+        }
+        let dist = &self.distribution;
+        let f = fresnel_dielectric(*wo * wm, eta);
+        let d_g = dist.d(&wm) * dist.g(wo, wi);
+        let visible = dist.visible_pdf(wo, &wm);
+        if reflect {
+            let eval = f * d_g / (4.0 * wo.z);
+            (eval, f * visible / (4.0 * (*wo * wm)))
+        } else {
+            let denom = (*wi * wm + (*wo * wm) / eta).powi(2);
+            let eval =
+                (1.0 - f) * d_g * ((*wi * wm) * (*wo * wm) / (wo.z * denom)).abs() / (eta * eta);
+            (eval, (1.0 - f) * visible * (*wi * wm).abs() / denom)
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let (frame, wo) = local_frame(r_in, rec);
+        if wo.z <= 0.0 {
+            return false;
+        }
+        let eta = self.eta(rec);
+        let u = sampler.get_2d();
+        let choice = sampler.get_1d();
+        let wi = if self.distribution.is_smooth() {
+            // picking reflection with the Fresnel probability cancels it from the weight
+            let n = Vec3::new(0.0, 0.0, 1.0);
+            srec.is_specular = true;
+            match refract_across(&wo, &n, eta) {
+                Some(wt) if choice >= fresnel_dielectric(wo.z, eta) => {
+                    srec.attenuation = Color::ones() / (eta * eta);
+                    wt
+                }
+                _ => {
+                    srec.attenuation = Color::ones();
+                    Vec3::new(-wo.x, -wo.y, wo.z)
+                }
+            }
+        } else {
+            let wm = self.distribution.sample_wm(&wo, u);
+            let wi = if choice < fresnel_dielectric(wo * wm, eta) {
+                reflect(&-wo, &wm)
+            } else {
+                match refract_across(&wo, &wm, eta) {
+                    Some(wt) => wt,
+                    None => return false,
+                }
+            };
+            let (eval, pdf) = self.eval_pdf_local(&wo, &wi, eta);
+            if pdf <= 0.0 {
+                return false;
+            }
+            srec.pdf = pdf;
+            srec.attenuation = Color::ones() * (eval / pdf);
+            srec.is_specular = false;
+            wi
+        };
+        srec.scattered = Ray {
+            orig: rec.p,
+            dir: frame.local(&wi),
+            time: r_in.time,
+            wavelength: r_in.wavelength,
+        };
+        true
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> Color {
+        if self.distribution.is_smooth() {
+            return Color::zero();
+        }
+        let (frame, wo) = local_frame(r_in, rec);
+        let (eval, _) = self.eval_pdf_local(&wo, &frame.project(&dir.unit()), self.eta(rec));
+        Color::ones() * eval
+    }
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+        let (frame, wo) = local_frame(r_in, rec);
+        self.eval_pdf_local(&wo, &frame.project(&dir.unit()), self.eta(rec))
+            .1
+    }
+    fn has_non_delta(&self) -> bool {
+        !self.distribution.is_smooth()
+    }
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        Color::zero()
+    }
+}
+
+fn solid(c: Color) -> Arc<dyn Texture> {
+    Arc::new(ConstTexture { color_value: c })
+}
+
+fn schlick_color(f0: Color, cosine: f64) -> Color {
+    f0 + (Color::ones() - f0) * (1.0 - cosine).max(0.0).powi(5)
+}
+
+// Principled material in the spirit of the Disney BRDF: a diffuse base with sheen, a GGX specular
+// lobe which turns into a metal with `metallic`, rough glass with `transmission` and a clear
+// coat on top. Scalar parameters are read from the first channel of their textures. It is
+// sampled by picking one lobe, and evaluated as the mix of all of them.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // reflectance of the dielectric base, 0.5 gives the usual 4% at normal incidence
+    pub specular: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub emission: Option<Arc<dyn Texture>>,
+    pub ior: f64,
+}
+
+impl Principled {
+    // a plastic of the given color, the other parameters are meant to be changed as fields
+    pub fn new(base_color: Color) -> Self {
+        Self {
+            base_color: solid(base_color),
+            metallic: solid(Color::zero()),
+            roughness: solid(Color::new(0.5, 0.5, 0.5)),
+            specular: solid(Color::new(0.5, 0.5, 0.5)),
+            transmission: solid(Color::zero()),
+            clearcoat: solid(Color::zero()),
+            clearcoat_roughness: solid(Color::new(0.1, 0.1, 0.1)),
+            sheen: solid(Color::zero()),
+            emission: None,
+            ior: 1.5,
+        }
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> PrincipledLobes {
+        let scalar = |t: &Arc<dyn Texture>| clamp(t.value(rec.u, rec.v, &rec.p).x, 0.0, 1.0);
+        let base_color = self.base_color.value(rec.u, rec.v, &rec.p);
+        let metallic = scalar(&self.metallic);
+        let transmission = scalar(&self.transmission);
+        let roughness = scalar(&self.roughness);
+        // the lobes stay rough enough to be evaluated, delta lobes would not mix
+        let alpha = fmax(roughness * roughness, SMOOTH_ALPHA);
+        let cc_roughness = scalar(&self.clearcoat_roughness);
+        let dielectric_f0 = Color::ones() * (0.08 * scalar(&self.specular));
+        let weights = [
+            (1.0 - metallic) * (1.0 - transmission),
+            1.0 - (1.0 - metallic) * transmission,
+            (1.0 - metallic) * transmission,
+            0.25 * scalar(&self.clearcoat),
+        ];
+        let total: f64 = weights.iter().sum();
+        PrincipledLobes {
+            base_color,
+            f0: dielectric_f0 * (1.0 - metallic) + base_color * metallic,
+            sheen: scalar(&self.sheen),
+            specular: TrowbridgeReitz::new(alpha, alpha),
+            clearcoat: TrowbridgeReitz::new(
+                fmax(cc_roughness * cc_roughness, SMOOTH_ALPHA),
+                fmax(cc_roughness * cc_roughness, SMOOTH_ALPHA),
+            ),
+            glass: RoughDielectric::new(self.ior, TrowbridgeReitz::new(alpha, alpha)),
+            eta: if rec.front_face {
+                self.ior
+            } else {
+                1.0 / self.ior
+            },
+            weights,
+            probs: [
+                weights[0] / total,
+                weights[1] / total,
+                weights[2] / total,
+                weights[3] / total,
+            ],
+        }
+    }
+}
+
+// the parameters of `Principled` at one point
+struct PrincipledLobes {
+    base_color: Color,
+    f0: Color,
+    sheen: f64,
+    specular: TrowbridgeReitz,
+    clearcoat: TrowbridgeReitz,
+    glass: RoughDielectric,
+    eta: f64,
+    // of the diffuse, specular, glass and clear coat lobes, and the chance of sampling each
+    weights: [f64; 4],
+    probs: [f64; 4],
+}
+
+impl PrincipledLobes {
+    // BSDF times |cos| and the density of `sample`, in the local frame
+    fn eval_pdf(&self, wo: &Vec3, wi: &Vec3) -> (Color, f64) {
+        let mut f = Color::zero();
+        let mut pdf = 0.0;
+        if wo.z <= 0.0 {
+            return (f, pdf);
+        }
+        if wi.z > 0.0 {
+            let wm = (*wo + *wi).unit();
+            let cos_d = *wi * wm;
+            // diffuse, and sheen towards grazing angles
+            let sheen = self.sheen * (1.0 - cos_d).max(0.0).powi(5);
+            f += (self.base_color / PI + Color::ones() * sheen) * (self.weights[0] * wi.z);
+            pdf += self.probs[0] * wi.z / PI;
+            for &(dist, f0, weight, prob) in &[
+                (&self.specular, self.f0, self.weights[1], self.probs[1]),
+                (
+                    &self.clearcoat,
+                    Color::new(0.04, 0.04, 0.04),
+                    self.weights[3],
+                    self.probs[3],
+                ),
+            ] {
+                if prob > 0.0 {
+                    let d_g = dist.d(&wm) * dist.g(wo, wi);
+                    f += schlick_color(f0, *wo * wm) * (weight * d_g / (4.0 * wo.z));
+                    pdf += prob * dist.visible_pdf(wo, &wm) / (4.0 * (*wo * wm));
+                }
+            }
+        }
+        if self.probs[2] > 0.0 {
+            let (glass_f, glass_pdf) = self.glass.eval_pdf_local(wo, wi, self.eta);
+            f += self.base_color * (self.weights[2] * glass_f);
+            pdf += self.probs[2] * glass_pdf;
+        }
+        (f, pdf)
+    }
+
+    fn sample(&self, wo: &Vec3, choice: f64, u: (f64, f64)) -> Option<Vec3> {
+        let mut choice = choice;
+        let mut lobe = 0;
+        while lobe < 3 && choice >= self.probs[lobe] {
+            choice -= self.probs[lobe];
+            lobe += 1;
+        }
+        match lobe {
+            0 => Some(sample_cosine_direction(u)),
+            1 => Some(reflect(&-*wo, &self.specular.sample_wm(wo, u))),
+            2 => {
+                // what is left of the choice decides between reflection and refraction
+                let wm = self.glass.distribution.sample_wm(wo, u);
+                if choice / self.probs[2] < fresnel_dielectric(*wo * wm, self.eta) {
+                    Some(reflect(&-*wo, &wm))
+                } else {
+                    refract_across(wo, &wm, self.eta)
+                }
+            }
+            _ => Some(reflect(&-*wo, &self.clearcoat.sample_wm(wo, u))),
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let (frame, wo) = local_frame(r_in, rec);
+        if wo.z <= 0.0 {
+            return false;
+        }
+        let lobes = self.lobes(rec);
+        let choice = sampler.get_1d();
+        let wi = match lobes.sample(&wo, choice, sampler.get_2d()) {
+            Some(wi) => wi,
+            None => return false,
+        };
+        let (f, pdf) = lobes.eval_pdf(&wo, &wi);
+        if pdf <= 0.0 {
+            return false;
+        }
+        srec.scattered = Ray {
+            orig: rec.p,
+            dir: frame.local(&wi),
+            time: r_in.time,
+            wavelength: r_in.wavelength,
+        };
+        srec.pdf = pdf;
+        srec.attenuation = f / pdf;
+        srec.is_specular = false;
+        true
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> Color {
+        let (frame, wo) = local_frame(r_in, rec);
+        self.lobes(rec).eval_pdf(&wo, &frame.project(&dir.unit())).0
+    }
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> f64 {
+        let (frame, wo) = local_frame(r_in, rec);
+        self.lobes(rec).eval_pdf(&wo, &frame.project(&dir.unit())).1
+    }
+    fn has_non_delta(&self) -> bool {
+        true
+    }
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        match &self.emission {
+            Some(emission) => emission.value(u, v, p),
+            None => Color::zero(),
+        }
+    }
+    fn is_emissive(&self) -> bool {
+        self.emission.is_some()
+    }
+}
+
+// Blend of two materials, `b` in the proportion given by the first channel of `weight` and `a`
+// in the rest. Each sample comes from one of them, and is weighted against both.
+pub struct MixMaterial {
+    pub a: Arc<dyn Material>,
+    pub b: Arc<dyn Material>,
+    pub weight: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    fn weight(&self, u: f64, v: f64, p: &Point3) -> f64 {
+        clamp(self.weight.value(u, v, p).x, 0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let w = self.weight(rec.u, rec.v, &rec.p);
+        let pick_b = sampler.get_1d() < w;
+        let chosen = if pick_b { &self.b } else { &self.a };
+        if !chosen.scatter(r_in, rec, srec, sampler) {
+            return false;
+        }
+        srec.choices = srec.choices << 1 | pick_b as u32;
+        if srec.is_specular {
+            // the weight of a delta lobe and the chance of choosing it cancel
+            return true;
+        }
+        let dir = srec.scattered.dir;
+        srec.pdf = self.pdf(r_in, rec, &dir);
+        if srec.pdf <= 0.0 {
+            return false;
+        }
+        srec.attenuation = self.eval(r_in, rec, &dir) / srec.pdf;
+        true
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> Color {
+        let w = self.weight(rec.u, rec.v, &rec.p);
+        self.a.eval(r_in, rec, dir) * (1.0 - w) + self.b.eval(r_in, rec, dir) * w
+    }
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> f64 {
+        let w = self.weight(rec.u, rec.v, &rec.p);
+        self.a.pdf(r_in, rec, dir) * (1.0 - w) + self.b.pdf(r_in, rec, dir) * w
+    }
+    fn has_non_delta(&self) -> bool {
+        self.a.has_non_delta() || self.b.has_non_delta()
+    }
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let w = self.weight(u, v, p);
+        self.a.emitted(u, v, p) * (1.0 - w) + self.b.emitted(u, v, p) * w
+    }
+    fn is_emissive(&self) -> bool {
+        self.a.is_emissive() || self.b.is_emissive()
+    }
+    fn eval_spectral(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3, lambda: &Vec3) -> Vec3 {
+        let w = self.weight(rec.u, rec.v, &rec.p);
+        self.a.eval_spectral(r_in, rec, dir, lambda) * (1.0 - w)
+            + self.b.eval_spectral(r_in, rec, dir, lambda) * w
+    }
+    fn attenuation_spectral(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        lambda: &Vec3,
+    ) -> Vec3 {
+        if !srec.is_specular {
+            return self.eval_spectral(r_in, rec, &srec.scattered.dir, lambda) / srec.pdf;
+        }
+        let chosen = if srec.choices & 1 == 1 {
+            &self.b
+        } else {
+            &self.a
+        };
+        let mut inner = srec.clone();
+        inner.choices >>= 1;
+        chosen.attenuation_spectral(r_in, rec, &inner, lambda)
+    }
+    fn emitted_spectral(&self, u: f64, v: f64, p: &Point3, lambda: &Vec3) -> Vec3 {
+        let w = self.weight(u, v, p);
+        self.a.emitted_spectral(u, v, p, lambda) * (1.0 - w)
+            + self.b.emitted_spectral(u, v, p, lambda) * w
+    }
+    fn is_dispersive(&self) -> bool {
+        self.a.is_dispersive() || self.b.is_dispersive()
+    }
+}
+
+// A thin clear layer of index `ior` over any `base`, like varnish over wood. Light reflects off
+// the layer by Fresnel and reaches the base with the rest, which is assumed not to change its
+// direction. Emission of the base shines through unchanged.
+pub struct Coated {
+    pub base: Arc<dyn Material>,
+    pub ior: f64,
+    pub distribution: TrowbridgeReitz,
+}
+
+impl Coated {
+    pub fn new(base: Arc<dyn Material>, ior: f64, distribution: TrowbridgeReitz) -> Self {
+        Self {
+            base,
+            ior,
+            distribution,
+        }
+    }
+
+    // fraction of the light crossing the layer at an angle with cosine `cosine`
+    fn transmitted(&self, cosine: f64) -> f64 {
+        1.0 - fresnel_dielectric(cosine.abs(), self.ior)
+    }
+
+    // chance of sampling the layer rather than the base, a little more than it reflects so that
+    // highlights on dark bases are found
+    fn coat_prob(&self, wo: &Vec3) -> f64 {
+        fmax(fresnel_dielectric(wo.z, self.ior), 0.1)
+    }
+
+    // weight of a specular sample of the base, which crosses the layer twice
+    fn base_weight(&self, rec: &HitRecord, wo: &Vec3, dir: &Vec3) -> f64 {
+        let cos_i = dir.unit() * rec.normal;
+        self.transmitted(wo.z) * self.transmitted(cos_i) / (1.0 - self.coat_prob(wo))
+    }
+
+    // reflection off a rough layer, BSDF times |cos| and the density of sampling it
+    fn coat_eval_pdf(&self, wo: &Vec3, wi: &Vec3) -> (f64, f64) {
+        if self.distribution.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let wm = (*wo + *wi).unit();
+        let dist = &self.distribution;
+        let f = fresnel_dielectric(*wo * wm, self.ior);
+        (
+            f * dist.d(&wm) * dist.g(wo, wi) / (4.0 * wo.z),
+            dist.visible_pdf(wo, &wm) / (4.0 * (*wo * wm)),
+        )
+    }
+}
+
+impl Material for Coated {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let (frame, wo) = local_frame(r_in, rec);
+        if wo.z <= 0.0 {
+            return false;
+        }
+        let coat_prob = self.coat_prob(&wo);
+        let choice = sampler.get_1d();
+        let u = sampler.get_2d();
+        if choice < coat_prob {
+            let (wi, is_specular) = if self.distribution.is_smooth() {
+                (Vec3::new(-wo.x, -wo.y, wo.z), true)
+            } else {
+                (reflect(&-wo, &self.distribution.sample_wm(&wo, u)), false)
+            };
+            srec.scattered = Ray {
+                orig: rec.p,
+                dir: frame.local(&wi),
+                time: r_in.time,
+                wavelength: r_in.wavelength,
+            };
+            srec.is_specular = is_specular;
+            srec.choices = srec.choices << 1 | 1;
+            if is_specular {
+                let f = fresnel_dielectric(wo.z, self.ior);
+                srec.attenuation = Color::ones() * (f / coat_prob);
+                return true;
+            }
+        } else {
+            if !self.base.scatter(r_in, rec, srec, sampler) {
+                return false;
+            }
+            srec.choices <<= 1;
+            if srec.is_specular {
+                srec.attenuation *= self.base_weight(rec, &wo, &srec.scattered.dir);
+                return true;
+            }
+        }
+        let dir = srec.scattered.dir;
+        srec.pdf = self.pdf(r_in, rec, &dir);
+        if srec.pdf <= 0.0 {
+            return false;
+        }
+        srec.attenuation = self.eval(r_in, rec, &dir) / srec.pdf;
+        true
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> Color {
+        let (frame, wo) = local_frame(r_in, rec);
+        let wi = frame.project(&dir.unit());
+        let through = self.transmitted(wo.z) * self.transmitted(wi.z);
+        self.base.eval(r_in, rec, dir) * through + Color::ones() * self.coat_eval_pdf(&wo, &wi).0
+    }
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> f64 {
+        let (frame, wo) = local_frame(r_in, rec);
+        let wi = frame.project(&dir.unit());
+        let coat_prob = self.coat_prob(&wo);
+        let coat_pdf = if self.distribution.is_smooth() {
+            0.0
+        } else {
+            self.coat_eval_pdf(&wo, &wi).1
+        };
+        coat_prob * coat_pdf + (1.0 - coat_prob) * self.base.pdf(r_in, rec, dir)
+    }
+    fn has_non_delta(&self) -> bool {
+        !self.distribution.is_smooth() || self.base.has_non_delta()
+    }
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.base.emitted(u, v, p)
+    }
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
+    fn eval_spectral(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3, lambda: &Vec3) -> Vec3 {
+        let (frame, wo) = local_frame(r_in, rec);
+        let wi = frame.project(&dir.unit());
+        let through = self.transmitted(wo.z) * self.transmitted(wi.z);
+        self.base.eval_spectral(r_in, rec, dir, lambda) * through
+            + Vec3::ones() * self.coat_eval_pdf(&wo, &wi).0
+    }
+    fn attenuation_spectral(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        lambda: &Vec3,
+    ) -> Vec3 {
+        if !srec.is_specular {
+            return self.eval_spectral(r_in, rec, &srec.scattered.dir, lambda) / srec.pdf;
+        }
+        if srec.choices & 1 == 1 {
+            // the layer reflects all wavelengths alike
+            return Vec3::ones() * srec.attenuation.x;
+        }
+        let (_, wo) = local_frame(r_in, rec);
+        let mut inner = srec.clone();
+        inner.choices >>= 1;
+        self.base.attenuation_spectral(r_in, rec, &inner, lambda)
+            * self.base_weight(rec, &wo, &srec.scattered.dir)
+    }
+    fn emitted_spectral(&self, u: f64, v: f64, p: &Point3, lambda: &Vec3) -> Vec3 {
+        self.base.emitted_spectral(u, v, p, lambda)
+    }
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+}
+
 pub fn schlick(cosine: f64, ref_idx: f64) -> f64 {
     let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     r0 = r0 * r0;
@@ -229,12 +1189,659 @@ impl Material for DiffuseLight {
         &self,
         _r_in: &Ray,
         _rec: &HitRecord,
-        _attenuation: &mut Color,
-        _scattered: &mut Ray,
+        _srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
     ) -> bool {
         false
     }
     fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
         self.emit.value(u, v, p)
     }
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+// Coldest black body a light may have; below about 50K the spectrum underflows in the visible
+// range and cannot be scaled to a luminance.
+pub const MIN_TEMPERATURE: f64 = 100.0;
+
+// Light with the spectrum of a black body at `temperature` kelvin, scaled to `luminance`.
+pub struct Blackbody {
+    pub temperature: f64,
+    // film color of the scaled spectrum, what the RGB mode emits
+    color: Color,
+    scale: f64,
+}
+
+impl Blackbody {
+    pub fn new(temperature: f64, luminance: f64) -> Self {
+        assert!(temperature >= MIN_TEMPERATURE && luminance >= 0.0);
+        let color = film_color(|lambda| planck(lambda, temperature));
+        let scale = luminance / (0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z);
+        Self {
+            temperature,
+            color: color * scale,
+            scale,
+        }
+    }
+}
+
+impl Material for Blackbody {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        false
+    }
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.color
+    }
+    fn emitted_spectral(&self, _u: f64, _v: f64, _p: &Point3, lambda: &Vec3) -> Vec3 {
+        Vec3::new(
+            planck(lambda.x, self.temperature),
+            planck(lambda.y, self.temperature),
+            planck(lambda.z, self.temperature),
+        ) * self.scale
+    }
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+// Phase function of a participating medium, which scatters equally in all directions. The
+// albedo is the fraction of light scattered rather than absorbed at each event.
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(a: Color) -> Self {
+        Self {
+            albedo: Arc::new(ConstTexture { color_value: a }),
+        }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        srec.scattered = Ray {
+            orig: rec.p,
+            dir: sample_unit_vector(sampler.get_2d()),
+            time: r_in.time,
+            wavelength: r_in.wavelength,
+        };
+        srec.pdf = 1.0 / (4.0 * PI);
+        srec.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        srec.is_specular = false;
+        true
+    }
+    // there is no surface, so no cosine either
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, _dir: &Vec3) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p) / (4.0 * PI)
+    }
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _dir: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+    fn has_non_delta(&self) -> bool {
+        true
+    }
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        Color::zero()
+    }
+}
+
+// Henyey-Greenstein phase function, which favours scattering forward for `g` > 0 and backward
+// for `g` < 0. The medium glows with `emit` at every collision, which grid volumes scale by
+// their emission grid.
+pub struct HenyeyGreenstein {
+    pub g: f64,
+    pub albedo: Arc<dyn Texture>,
+    pub emit: Color,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f64, albedo: Arc<dyn Texture>, emit: Color) -> Self {
+        Self { g, albedo, emit }
+    }
+
+    // density of turning by an angle with cosine `cos_theta`
+    fn phase(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let (u1, u2) = sampler.get_2d();
+        let g = self.g;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        };
+        let sin_theta = fmax(0.0, 1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * u2;
+        let dir = Onb::build_from_w(&r_in.dir).local(&Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+        srec.scattered = Ray {
+            orig: rec.p,
+            dir,
+            time: r_in.time,
+            wavelength: r_in.wavelength,
+        };
+        srec.pdf = self.phase(cos_theta);
+        srec.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        srec.is_specular = false;
+        true
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(r_in, rec, dir)
+    }
+    fn pdf(&self, r_in: &Ray, _rec: &HitRecord, dir: &Vec3) -> f64 {
+        self.phase(r_in.dir.unit() * dir.unit())
+    }
+    fn has_non_delta(&self) -> bool {
+        true
+    }
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.emit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::{
+        microfacet::conductor_preset,
+        spectrum::{conductor_spectrum, hero_wavelengths, LAMBDA_MAX, LAMBDA_MIN},
+    };
+
+    #[test]
+    fn test_lambertian_sampling() {
+        let mat = Lambertian::new(Color::new(0.5, 0.25, 1.0));
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        rec.normal = Vec3::new(0.0, 0.6, 0.8);
+        let r_in = Ray {
+            orig: Point3::new(0.0, 1.0, 1.0),
+            dir: Vec3::new(0.0, -1.0, -1.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        let mut sampler = IndependentSampler::new(1);
+        let mut srec = ScatterRecord::new();
+        for i in 0..100 {
+            sampler.start_sample(0, 0, i);
+            assert!(mat.scatter(&r_in, &rec, &mut srec, &mut sampler));
+            let dir = srec.scattered.dir;
+            assert!(!srec.is_specular);
+            assert!(dir * rec.normal >= 0.0);
+            assert!((mat.pdf(&r_in, &rec, &dir) - srec.pdf).abs() < 1e-9);
+            let weight = mat.eval(&r_in, &rec, &dir) / srec.pdf;
+            assert!((weight - srec.attenuation).length() < 1e-9);
+        }
+        assert_eq!(mat.pdf(&r_in, &rec, &-rec.normal), 0.0);
+        assert_eq!(mat.eval(&r_in, &rec, &-rec.normal), Color::zero());
+    }
+
+    #[test]
+    fn test_isotropic_sampling() {
+        let mat = Isotropic::new(Color::new(0.5, 0.5, 0.5));
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        rec.p = Point3::new(1.0, 2.0, 3.0);
+        let r_in = Ray {
+            orig: Point3::zero(),
+            dir: Vec3::new(1.0, 2.0, 3.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        let mut sampler = IndependentSampler::new(1);
+        let mut srec = ScatterRecord::new();
+        let mut mean = Vec3::zero();
+        for i in 0..1000 {
+            sampler.start_sample(0, 0, i);
+            assert!(mat.scatter(&r_in, &rec, &mut srec, &mut sampler));
+            let dir = srec.scattered.dir;
+            assert!((dir.length() - 1.0).abs() < 1e-9);
+            assert!((mat.pdf(&r_in, &rec, &dir) - srec.pdf).abs() < 1e-9);
+            let weight = mat.eval(&r_in, &rec, &dir) / srec.pdf;
+            assert!((weight - srec.attenuation).length() < 1e-9);
+            mean += dir / 1000.0;
+        }
+        // no preferred direction
+        assert!(mean.length() < 0.1);
+    }
+
+    #[test]
+    fn test_henyey_greenstein_sampling() {
+        let r_in = Ray {
+            orig: Point3::zero(),
+            dir: Vec3::new(0.0, 3.0, 4.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        let rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        let mut sampler = IndependentSampler::new(1);
+        let mut srec = ScatterRecord::new();
+        for &g in &[-0.7, 0.0, 0.5] {
+            let mat = HenyeyGreenstein::new(
+                g,
+                Arc::new(ConstTexture {
+                    color_value: Color::new(0.9, 0.8, 0.7),
+                }),
+                Color::zero(),
+            );
+            let n = 4000;
+            let mut mean_cos = 0.0;
+            for i in 0..n {
+                sampler.start_sample(0, 0, i);
+                assert!(mat.scatter(&r_in, &rec, &mut srec, &mut sampler));
+                let dir = srec.scattered.dir;
+                assert!((mat.pdf(&r_in, &rec, &dir) - srec.pdf).abs() < 1e-9 * srec.pdf);
+                let weight = mat.eval(&r_in, &rec, &dir) / srec.pdf;
+                assert!((weight - srec.attenuation).length() < 1e-9);
+                mean_cos += r_in.dir.unit() * dir.unit() / n as f64;
+            }
+            // the mean cosine of the Henyey-Greenstein distribution is g
+            assert!((mean_cos - g).abs() < 0.05);
+        }
+    }
+
+    // eval and pdf agree with what scatter samples, and no energy is created
+    fn check_bsdf(mat: &dyn Material, front_face: bool, eta: f64) {
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        rec.normal = Vec3::new(0.0, 0.6, 0.8);
+        rec.front_face = front_face;
+        let r_in = Ray {
+            orig: Point3::new(0.0, 1.0, 1.0),
+            dir: Vec3::new(0.3, -1.0, -1.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        let mut sampler = IndependentSampler::new(1);
+        let mut srec = ScatterRecord::new();
+        let n = 4000;
+        let mut albedo = 0.0;
+        for i in 0..n {
+            sampler.start_sample(0, 0, i);
+            if !mat.scatter(&r_in, &rec, &mut srec, &mut sampler) {
+                continue;
+            }
+            let dir = srec.scattered.dir;
+            assert!((dir.length() - 1.0).abs() < 1e-9);
+            assert!(!srec.is_specular);
+            let pdf = mat.pdf(&r_in, &rec, &dir);
+            assert!((pdf - srec.pdf).abs() < 1e-6 * pdf);
+            let weight = mat.eval(&r_in, &rec, &dir) / srec.pdf;
+            assert!((weight - srec.attenuation).length() < 1e-6 * weight.length());
+            // undo the radiance scaling of refraction to count energy
+            let transmitted = dir * rec.normal < 0.0;
+            albedo += srec.attenuation.x * if transmitted { eta * eta } else { 1.0 } / n as f64;
+        }
+        assert!(albedo <= 1.0 + 1e-9);
+        assert!(albedo > 0.8);
+    }
+
+    #[test]
+    fn test_conductor() {
+        for &(ax, ay) in &[(0.3, 0.3), (0.1, 0.5)] {
+            let mat = Conductor::new(
+                Color::new(1.0, 1.0, 1.0),
+                Color::new(1e3, 1e3, 1e3),
+                TrowbridgeReitz::new(ax, ay),
+            );
+            check_bsdf(&mat, true, 1.0);
+        }
+    }
+
+    #[test]
+    fn test_rough_dielectric() {
+        for &(ax, ay) in &[(0.2, 0.2), (0.05, 0.4)] {
+            let mat = RoughDielectric::new(1.5, TrowbridgeReitz::new(ax, ay));
+            check_bsdf(&mat, true, 1.5);
+            check_bsdf(&mat, false, 1.0 / 1.5);
+        }
+    }
+
+    #[test]
+    fn test_smooth_dielectric() {
+        let mat = RoughDielectric::new(1.5, TrowbridgeReitz::new(0.0, 0.0));
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        rec.front_face = true;
+        let r_in = Ray {
+            orig: Point3::new(0.0, 0.0, 1.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        let mut sampler = IndependentSampler::new(1);
+        let mut srec = ScatterRecord::new();
+        let n = 10000;
+        let mut reflected = 0;
+        for i in 0..n {
+            sampler.start_sample(0, 0, i);
+            assert!(mat.scatter(&r_in, &rec, &mut srec, &mut sampler));
+            assert!(srec.is_specular);
+            if srec.scattered.dir.z > 0.0 {
+                reflected += 1;
+            }
+        }
+        // 4% at normal incidence
+        assert!((reflected as f64 / n as f64 - 0.04).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_dielectric() {
+        let mat = Dielectric {
+            ref_idx: 1.5,
+            absorption: Color::new(0.0, 0.5, 1.0),
+            dispersion: Some(Dispersion::Cauchy { ior: 1.5, b: 0.01 }),
+        };
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        rec.normal = Vec3::new(0.0, 0.6, 0.8);
+        rec.front_face = true;
+        rec.t = 2.0;
+        let mut r_in = Ray {
+            orig: Point3::new(0.0, 0.0, 1.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        let mut sampler = IndependentSampler::new(1);
+        let mut srec = ScatterRecord::new();
+        // refracting picks a wavelength, which bends blue light more than red
+        let n = 20000;
+        let mut mean = Color::zero();
+        let mut refracted = Vec::new();
+        for i in 0..n {
+            sampler.start_sample(0, 0, i);
+            assert!(mat.scatter(&r_in, &rec, &mut srec, &mut sampler));
+            mean += srec.attenuation / n as f64;
+            if srec.scattered.dir * rec.normal < 0.0 {
+                let lambda = srec.scattered.wavelength.unwrap();
+                assert!((LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda));
+                refracted.push((lambda, srec.scattered.dir.y));
+            } else {
+                // reflections keep every color
+                assert_eq!(srec.scattered.wavelength, None);
+                assert_eq!(srec.attenuation, Color::ones());
+            }
+        }
+        assert!((mean - Color::ones()).length() < 0.1);
+        refracted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        assert!(refracted.windows(2).all(|w| w[0].1 <= w[1].1));
+        // leaving keeps the wavelength and absorbs over the distance travelled inside
+        r_in.wavelength = Some(500.0);
+        rec.front_face = false;
+        rec.normal = -rec.normal;
+        sampler.start_sample(0, 0, 0);
+        assert!(mat.scatter(&r_in, &rec, &mut srec, &mut sampler));
+        assert_eq!(srec.scattered.wavelength, Some(500.0));
+        assert!(
+            (srec.attenuation - Color::new(1.0, (-1.0_f64).exp(), (-2.0_f64).exp())).length()
+                < 1e-12
+        );
+    }
+
+    #[test]
+    fn test_spectral_dielectric() {
+        let mat = Dielectric {
+            ref_idx: 1.5,
+            absorption: Color::zero(),
+            dispersion: Some(Dispersion::Cauchy { ior: 1.5, b: 0.02 }),
+        };
+        // inside the glass, past the critical angle for blue but not for red
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        let cos_theta = (1.0_f64 - 0.66 * 0.66).sqrt();
+        let r_in = Ray {
+            orig: Point3::zero(),
+            dir: Vec3::new(0.66, 0.0, -cos_theta),
+            time: 0.0,
+            wavelength: Some(650.0),
+        };
+        let lambda = hero_wavelengths(650.0);
+        let reflectance =
+            |l: f64| Dielectric::reflectance(cos_theta, mat.dispersion.unwrap().ior(l));
+        assert_eq!(reflectance(lambda.y), 1.0);
+        let expected = Vec3::new(reflectance(lambda.x), 1.0, reflectance(lambda.z));
+        let mut sampler = IndependentSampler::new(1);
+        let mut srec = ScatterRecord::new();
+        let n = 20000;
+        let mut mean = Vec3::zero();
+        for i in 0..n {
+            sampler.start_sample(0, 0, i);
+            assert!(mat.scatter(&r_in, &rec, &mut srec, &mut sampler));
+            // only refraction leaves the hero wavelength alone
+            assert_eq!(srec.dispersed, srec.scattered.dir.z < 0.0);
+            if !srec.dispersed {
+                let a = mat.attenuation_spectral(&r_in, &rec, &srec, &lambda);
+                assert!(
+                    (a - rgb_to_spectrum(&Color::ones(), &lambda).elemul(expected / expected.x))
+                        .length()
+                        < 1e-9
+                );
+                mean += a / n as f64;
+            }
+        }
+        // every wavelength reflects as much as its own reflectance
+        for &(m, e) in &[
+            (mean.x, expected.x),
+            (mean.y, expected.y),
+            (mean.z, expected.z),
+        ] {
+            assert!((m / e - 1.0).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_spectral_conductor() {
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        rec.normal = Vec3::new(0.0, 0.6, 0.8);
+        rec.front_face = true;
+        let r_in = Ray {
+            orig: Point3::new(0.0, 1.0, 1.0),
+            dir: Vec3::new(0.3, -1.0, -1.0),
+            time: 0.0,
+            wavelength: Some(650.0),
+        };
+        let lambda = Vec3::new(650.0, 550.0, 450.0);
+        let mut sampler = IndependentSampler::new(1);
+        let mut srec = ScatterRecord::new();
+        for &alpha in &[0.0, 0.3] {
+            let (eta, k) = conductor_preset("gold").unwrap();
+            let mut gold = Conductor::new(eta, k, TrowbridgeReitz::new(alpha, alpha));
+            gold.spectrum = conductor_spectrum("gold");
+            for i in 0..100 {
+                sampler.start_sample(0, 0, i);
+                if !gold.scatter(&r_in, &rec, &mut srec, &mut sampler) {
+                    continue;
+                }
+                // measured gold reflects red far better than blue
+                let a = gold.attenuation_spectral(&r_in, &rec, &srec, &lambda);
+                assert!(a.x > a.y && a.y > a.z && a.z > 0.0);
+                if !srec.is_specular {
+                    let dir = srec.scattered.dir;
+                    let f = gold.eval_spectral(&r_in, &rec, &dir, &lambda);
+                    assert!((f / srec.pdf - a).length() < 1e-9 * a.length());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_spectral_mix() {
+        let glass: Arc<dyn Material> = Arc::new(Dielectric {
+            ref_idx: 1.5,
+            absorption: Color::zero(),
+            dispersion: Some(Dispersion::Cauchy { ior: 1.5, b: 0.01 }),
+        });
+        let (eta, k) = conductor_preset("gold").unwrap();
+        let mut gold = Conductor::new(eta, k, TrowbridgeReitz::new(0.0, 0.0));
+        gold.spectrum = conductor_spectrum("gold");
+        let gold: Arc<dyn Material> = Arc::new(gold);
+        let mix = MixMaterial {
+            a: gold.clone(),
+            b: glass.clone(),
+            weight: solid(Color::new(0.5, 0.5, 0.5)),
+        };
+        let coated = Coated::new(gold.clone(), 1.5, TrowbridgeReitz::new(0.0, 0.0));
+        assert!(mix.is_dispersive());
+        assert!(Coated::new(glass.clone(), 1.5, TrowbridgeReitz::new(0.0, 0.0)).is_dispersive());
+        assert!(!coated.is_dispersive());
+
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        rec.normal = Vec3::new(0.0, 0.6, 0.8);
+        rec.front_face = true;
+        let r_in = Ray {
+            orig: Point3::new(0.0, 1.0, 1.0),
+            dir: Vec3::new(0.3, -1.0, -1.0),
+            time: 0.0,
+            wavelength: Some(650.0),
+        };
+        let lambda = Vec3::new(650.0, 550.0, 450.0);
+        let mut sampler = IndependentSampler::new(1);
+        let mut srec = ScatterRecord::new();
+        let mut picked = [0; 2];
+        for i in 0..200 {
+            sampler.start_sample(0, 0, i);
+            assert!(mix.scatter(&r_in, &rec, &mut srec, &mut sampler));
+            let a = mix.attenuation_spectral(&r_in, &rec, &srec, &lambda);
+            // the measured spectrum of the gold survives the blend
+            if srec.choices & 1 == 0 {
+                assert_eq!(a, gold.attenuation_spectral(&r_in, &rec, &srec, &lambda));
+                assert!(a.x > a.y && a.y > a.z && a.z > 0.0);
+            } else {
+                assert_eq!(a, glass.attenuation_spectral(&r_in, &rec, &srec, &lambda));
+                assert_eq!(srec.scattered.wavelength, Some(650.0));
+            }
+            picked[(srec.choices & 1) as usize] += 1;
+
+            sampler.start_sample(0, 0, i);
+            assert!(coated.scatter(&r_in, &rec, &mut srec, &mut sampler));
+            let a = coated.attenuation_spectral(&r_in, &rec, &srec, &lambda);
+            if srec.choices & 1 == 0 {
+                assert!(a.x > a.y && a.y > a.z && a.z > 0.0);
+            } else {
+                assert_eq!(a, Vec3::ones() * srec.attenuation.x);
+            }
+        }
+        assert!(picked[0] > 0 && picked[1] > 0);
+    }
+
+    #[test]
+    fn test_blackbody() {
+        let bulb = Blackbody::new(2700.0, 3.0);
+        let p = Point3::zero();
+        let c = bulb.emitted(0.0, 0.0, &p);
+        assert!((0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z - 3.0).abs() < 1e-9);
+        assert!(c.x > c.y && c.y > c.z);
+        let ember = Blackbody::new(MIN_TEMPERATURE, 1.0);
+        let glow = ember.emitted(0.0, 0.0, &p);
+        assert!(glow.x.is_finite() && glow.y.is_finite() && glow.z.is_finite());
+        let e = ember.emitted_spectral(0.0, 0.0, &p, &Vec3::new(LAMBDA_MIN, 550.0, LAMBDA_MAX));
+        assert!(e.x.is_finite() && e.z > e.y && e.y > e.x);
+        // the RGB mode sees the spectral mode's light on the film
+        let film = film_color(|l| bulb.emitted_spectral(0.0, 0.0, &p, &Vec3::new(l, l, l)).x);
+        assert!((film - c).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_principled() {
+        let mut plastic = Principled::new(Color::new(0.8, 0.2, 0.2));
+        plastic.sheen = solid(Color::ones());
+        plastic.clearcoat = solid(Color::ones());
+        check_bsdf(&plastic, true, 1.0);
+        let mut metal = Principled::new(Color::new(0.95, 0.9, 0.8));
+        metal.metallic = solid(Color::ones());
+        metal.roughness = solid(Color::new(0.3, 0.3, 0.3));
+        check_bsdf(&metal, true, 1.0);
+        let mut glass = Principled::new(Color::ones());
+        glass.transmission = solid(Color::ones());
+        glass.roughness = solid(Color::new(0.4, 0.4, 0.4));
+        check_bsdf(&glass, true, 1.5);
+        check_bsdf(&glass, false, 1.0 / 1.5);
+        assert!(!glass.is_emissive());
+    }
+
+    #[test]
+    fn test_mix() {
+        let mat = MixMaterial {
+            a: Arc::new(Lambertian::new(Color::ones())),
+            b: Arc::new(Conductor::new(
+                Color::ones(),
+                Color::new(1e3, 1e3, 1e3),
+                TrowbridgeReitz::new(0.2, 0.2),
+            )),
+            weight: solid(Color::new(0.3, 0.3, 0.3)),
+        };
+        check_bsdf(&mat, true, 1.0);
+        let lamp = MixMaterial {
+            a: Arc::new(DiffuseLight::new(Color::new(2.0, 2.0, 2.0))),
+            b: Arc::new(Lambertian::new(Color::ones())),
+            weight: solid(Color::new(0.75, 0.75, 0.75)),
+        };
+        assert!(lamp.is_emissive());
+        assert_eq!(
+            lamp.emitted(0.0, 0.0, &Point3::zero()),
+            Color::new(0.5, 0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn test_coated() {
+        let base: Arc<dyn Material> = Arc::new(Lambertian::new(Color::ones()));
+        check_bsdf(
+            &Coated::new(base.clone(), 1.5, TrowbridgeReitz::new(0.1, 0.1)),
+            true,
+            1.0,
+        );
+        // a smooth layer mirrors a share of the light and passes the rest to the base
+        let mat = Coated::new(base, 1.5, TrowbridgeReitz::new(0.0, 0.0));
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        let r_in = Ray {
+            orig: Point3::new(0.0, 0.0, 1.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        let mut sampler = IndependentSampler::new(1);
+        let mut srec = ScatterRecord::new();
+        let n = 4000;
+        let mut albedo = 0.0;
+        for i in 0..n {
+            sampler.start_sample(0, 0, i);
+            assert!(mat.scatter(&r_in, &rec, &mut srec, &mut sampler));
+            if !srec.is_specular {
+                let dir = srec.scattered.dir;
+                assert!((mat.pdf(&r_in, &rec, &dir) - srec.pdf).abs() < 1e-9);
+            }
+            albedo += srec.attenuation.x / n as f64;
+        }
+        assert!(albedo < 1.0 && albedo > 0.85);
+    }
 }
@@ -0,0 +1,451 @@
+use crate::{
+    rtweekend::degrees_to_radians,
+    vec3::{Point3, Vec3},
+};
+use std::ops::Mul;
+
+// 3x3 matrix, row major, acting on column vectors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat3 {
+    pub m: [[f64; 3]; 3],
+}
+
+impl Mat3 {
+    pub fn identity() -> Self {
+        Self::scale(&Vec3::ones())
+    }
+
+    pub fn from_cols(a: &Vec3, b: &Vec3, c: &Vec3) -> Self {
+        Self {
+            m: [[a.x, b.x, c.x], [a.y, b.y, c.y], [a.z, b.z, c.z]],
+        }
+    }
+
+    pub fn col(&self, j: usize) -> Vec3 {
+        Vec3::new(self.m[0][j], self.m[1][j], self.m[2][j])
+    }
+
+    pub fn scale(factors: &Vec3) -> Self {
+        let mut m = [[0.0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = factors[i];
+        }
+        Self { m }
+    }
+
+    pub fn transpose(&self) -> Self {
+        Self::from_cols(
+            &Vec3::new(self.m[0][0], self.m[0][1], self.m[0][2]),
+            &Vec3::new(self.m[1][0], self.m[1][1], self.m[1][2]),
+            &Vec3::new(self.m[2][0], self.m[2][1], self.m[2][2]),
+        )
+    }
+
+    pub fn determinant(&self) -> f64 {
+        self.col(0) * self.col(1).cross(self.col(2))
+    }
+
+    // adjugate over determinant, None for a singular matrix
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let (a, b, c) = (self.col(0), self.col(1), self.col(2));
+        // each row of the inverse is perpendicular to two of the columns
+        let inv = Self::from_cols(&b.cross(c), &c.cross(a), &a.cross(b)).transpose();
+        Some(inv * (1.0 / det))
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::from_cols(
+            &(self * other.col(0)),
+            &(self * other.col(1)),
+            &(self * other.col(2)),
+        )
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, v: Vec3) -> Vec3 {
+        self.col(0) * v.x + self.col(1) * v.y + self.col(2) * v.z
+    }
+}
+
+impl Mul<f64> for Mat3 {
+    type Output = Self;
+
+    fn mul(self, s: f64) -> Self {
+        Self::from_cols(&(self.col(0) * s), &(self.col(1) * s), &(self.col(2) * s))
+    }
+}
+
+// Affine 4x4 matrix, row major, acting on column vectors. The last row is always 0, 0, 0, 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        Self::from_parts(&Mat3::identity(), &Vec3::zero())
+    }
+
+    // applies `linear`, then moves by `translation`
+    pub fn from_parts(linear: &Mat3, translation: &Vec3) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate().take(3) {
+            row[..3].copy_from_slice(&linear.m[i]);
+            row[3] = translation[i];
+        }
+        m[3][3] = 1.0;
+        Self { m }
+    }
+
+    pub fn linear(&self) -> Mat3 {
+        let mut m = [[0.0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            row.copy_from_slice(&self.m[i][..3]);
+        }
+        Mat3 { m }
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    pub fn translate(offset: &Vec3) -> Self {
+        Self::from_parts(&Mat3::identity(), offset)
+    }
+
+    pub fn scale(factors: &Vec3) -> Self {
+        Self::from_parts(&Mat3::scale(factors), &Vec3::zero())
+    }
+
+    // None for a singular matrix
+    pub fn inverse(&self) -> Option<Self> {
+        let linear = self.linear().inverse()?;
+        Some(Self::from_parts(&linear, &-(linear * self.translation())))
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        self.linear() * *p + self.translation()
+    }
+
+    // ignores the translation
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        self.linear() * *v
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+// `a * b` applies `b` first
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::from_parts(
+            &(self.linear() * other.linear()),
+            &self.transform_point(&other.translation()),
+        )
+    }
+}
+
+// Unit quaternion representing a rotation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub w: f64,
+    pub v: Vec3,
+}
+
+impl Quat {
+    pub fn identity() -> Self {
+        Self {
+            w: 1.0,
+            v: Vec3::zero(),
+        }
+    }
+
+    // counter-clockwise rotation by `degrees` around `axis`, looking against the axis
+    pub fn from_axis_angle(axis: &Vec3, degrees: f64) -> Self {
+        let (sin, cos) = (degrees_to_radians(degrees) / 2.0).sin_cos();
+        Self {
+            w: cos,
+            v: axis.unit() * sin,
+        }
+    }
+
+    // rotations by `angles` degrees around x, then y, then z
+    pub fn from_euler(angles: &Vec3) -> Self {
+        Self::from_axis_angle(&Vec3::new(0.0, 0.0, 1.0), angles.z)
+            * Self::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), angles.y)
+            * Self::from_axis_angle(&Vec3::new(1.0, 0.0, 0.0), angles.x)
+    }
+
+    pub fn dot(&self, other: &Self) -> f64 {
+        self.w * other.w + self.v * other.v
+    }
+
+    pub fn normalize(&self) -> Self {
+        let len = self.dot(self).sqrt();
+        Self {
+            w: self.w / len,
+            v: self.v / len,
+        }
+    }
+
+    // spherical interpolation along the shorter arc, `t` = 0 gives `self`
+    pub fn slerp(&self, other: &Self, t: f64) -> Self {
+        let mut cos = self.dot(other);
+        let mut end = *other;
+        if cos < 0.0 {
+            cos = -cos;
+            end = Self {
+                w: -end.w,
+                v: -end.v,
+            };
+        }
+        // nearly equal rotations make the sine vanish, fall back to a normalized lerp
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Self {
+            w: self.w * a + end.w * b,
+            v: self.v * a + end.v * b,
+        }
+        .normalize()
+    }
+
+    pub fn rotate(&self, p: &Vec3) -> Vec3 {
+        let t = self.v.cross(*p) * 2.0;
+        *p + t * self.w + self.v.cross(t)
+    }
+
+    // renormalizes first, so rounding errors piled up by composition do not leak into a scale
+    pub fn to_mat3(self) -> Mat3 {
+        let q = self.normalize();
+        Mat3::from_cols(
+            &q.rotate(&Vec3::new(1.0, 0.0, 0.0)),
+            &q.rotate(&Vec3::new(0.0, 1.0, 0.0)),
+            &q.rotate(&Vec3::new(0.0, 0.0, 1.0)),
+        )
+    }
+}
+
+// `a * b` rotates by `b` first
+impl Mul for Quat {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            w: self.w * other.w - self.v * other.v,
+            v: other.v * self.w + self.v * other.w + self.v.cross(other.v),
+        }
+    }
+}
+
+#[allow(clippy::float_cmp)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Vec3, b: &Vec3) {
+        assert!((*a - *b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_mat3_close(a: &Mat3, b: &Mat3) {
+        for j in 0..3 {
+            assert_close(&a.col(j), &b.col(j));
+        }
+    }
+
+    fn sample_mat3() -> Mat3 {
+        Mat3 {
+            m: [[2.0, 0.0, 1.0], [1.0, 3.0, 0.0], [0.0, -1.0, 1.0]],
+        }
+    }
+
+    #[test]
+    fn test_mat3_cols() {
+        let m = sample_mat3();
+        assert_eq!(m.col(0), Vec3::new(2.0, 1.0, 0.0));
+        assert_eq!(Mat3::from_cols(&m.col(0), &m.col(1), &m.col(2)), m);
+    }
+
+    #[test]
+    fn test_mat3_mul_vec3() {
+        assert_eq!(
+            sample_mat3() * Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(5.0, 7.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_mat3_mul() {
+        let (a, b) = (
+            sample_mat3(),
+            Quat::from_axis_angle(&Vec3::new(1.0, 2.0, 0.5), 70.0).to_mat3(),
+        );
+        let v = Vec3::new(-1.0, 0.5, 2.0);
+        assert_close(&((a * b) * v), &(a * (b * v)));
+        assert_eq!(a * Mat3::identity(), a);
+    }
+
+    #[test]
+    fn test_mat3_transpose() {
+        let m = sample_mat3();
+        assert_eq!(m.transpose().m[0], [2.0, 1.0, 0.0]);
+        assert_eq!(m.transpose().transpose(), m);
+    }
+
+    #[test]
+    fn test_mat3_determinant() {
+        assert_eq!(sample_mat3().determinant(), 5.0);
+        assert_eq!(Mat3::scale(&Vec3::new(2.0, 3.0, 4.0)).determinant(), 24.0);
+    }
+
+    #[test]
+    fn test_mat3_inverse() {
+        let m = sample_mat3();
+        assert_mat3_close(&(m * m.inverse().unwrap()), &Mat3::identity());
+        assert!(Mat3::scale(&Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn test_quat_to_mat3() {
+        let r = Quat::from_axis_angle(&Vec3::new(0.0, 0.0, 2.0), 90.0).to_mat3();
+        assert_close(&(r * Vec3::new(1.0, 0.0, 0.0)), &Vec3::new(0.0, 1.0, 0.0));
+        let r = Quat::from_axis_angle(&Vec3::new(1.0, 1.0, 1.0), 120.0).to_mat3();
+        assert_close(&(r * Vec3::new(1.0, 0.0, 0.0)), &Vec3::new(0.0, 1.0, 0.0));
+        assert_mat3_close(&(r * r.transpose()), &Mat3::identity());
+    }
+
+    #[test]
+    fn test_mat4_transform() {
+        let t = Mat4::translate(&Vec3::new(1.0, 2.0, 3.0))
+            * Mat4::from_parts(
+                &Quat::from_axis_angle(&Vec3::new(0.0, 0.0, 1.0), 90.0).to_mat3(),
+                &Vec3::zero(),
+            )
+            * Mat4::scale(&Vec3::new(2.0, 2.0, 2.0));
+        let p = Point3::new(1.0, 0.0, 0.0);
+        assert_close(&t.transform_point(&p), &Point3::new(1.0, 4.0, 3.0));
+        assert_close(&t.transform_vector(&p), &Vec3::new(0.0, 2.0, 0.0));
+        assert_eq!(t.translation(), Vec3::new(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn test_mat4_inverse() {
+        let t = Mat4::translate(&Vec3::new(-1.0, 0.5, 3.0))
+            * Mat4::from_parts(
+                &Quat::from_axis_angle(&Vec3::new(1.0, 1.0, 0.0), 30.0).to_mat3(),
+                &Vec3::zero(),
+            )
+            * Mat4::scale(&Vec3::new(1.0, 3.0, 0.5));
+        let inv = t.inverse().unwrap();
+        let p = Point3::new(0.3, -2.0, 1.5);
+        assert_close(&inv.transform_point(&t.transform_point(&p)), &p);
+        let id = t * inv;
+        assert_mat3_close(&id.linear(), &Mat3::identity());
+        assert_close(&id.translation(), &Vec3::zero());
+        assert!(Mat4::scale(&Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    // normals go through the inverse transpose to stay perpendicular to transformed tangents
+    #[test]
+    fn test_mat4_normal() {
+        let rotation = Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 1.0), 45.0).to_mat3();
+        let t = Mat4::from_parts(&rotation, &Vec3::zero()) * Mat4::scale(&Vec3::new(1.0, 4.0, 0.5));
+        let normal_matrix = t.inverse().unwrap().linear().transpose();
+        let n = Vec3::new(1.0, 1.0, 1.0);
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+        assert!(((normal_matrix * n) * t.transform_vector(&tangent)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_quat_axis_angle() {
+        let axis = Vec3::new(0.3, -1.0, 2.0);
+        let q = Quat::from_axis_angle(&axis, 75.0);
+        assert!((q.dot(&q) - 1.0).abs() < 1e-12);
+        // the axis stays, perpendicular vectors turn by the angle
+        assert_close(&q.rotate(&axis), &axis);
+        let p = Vec3::new(2.0, 0.0, -0.3);
+        let cos = p * q.rotate(&p) / p.squared_length();
+        assert!((cos - 75.0_f64.to_radians().cos()).abs() < 1e-12);
+        assert!(p.cross(q.rotate(&p)) * axis > 0.0);
+    }
+
+    #[test]
+    fn test_quat_euler() {
+        let q = Quat::from_euler(&Vec3::new(90.0, 0.0, 90.0));
+        // x maps to itself, then to y
+        assert_close(
+            &q.rotate(&Vec3::new(1.0, 0.0, 0.0)),
+            &Vec3::new(0.0, 1.0, 0.0),
+        );
+        // y maps to z, which stays
+        assert_close(
+            &q.rotate(&Vec3::new(0.0, 1.0, 0.0)),
+            &Vec3::new(0.0, 0.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn test_quat_mul() {
+        let a = Quat::from_axis_angle(&Vec3::new(1.0, 0.0, 0.0), 30.0);
+        let b = Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 1.0), -50.0);
+        assert_mat3_close(&(a * b).to_mat3(), &(a.to_mat3() * b.to_mat3()));
+        assert_eq!(Quat::identity() * a, a);
+    }
+
+    #[test]
+    fn test_quat_slerp() {
+        let axis = Vec3::new(1.0, 2.0, -1.0);
+        let a = Quat::from_axis_angle(&axis, 20.0);
+        let b = Quat::from_axis_angle(&axis, 100.0);
+        let mid = a.slerp(&b, 0.25);
+        assert_mat3_close(
+            &mid.to_mat3(),
+            &Quat::from_axis_angle(&axis, 40.0).to_mat3(),
+        );
+        assert_mat3_close(&a.slerp(&b, 1.0).to_mat3(), &b.to_mat3());
+        // the same rotation with the opposite sign takes the short way
+        let c = Quat { w: -b.w, v: -b.v };
+        assert_mat3_close(&a.slerp(&c, 0.25).to_mat3(), &mid.to_mat3());
+        assert_mat3_close(&a.slerp(&a, 0.5).to_mat3(), &a.to_mat3());
+    }
+
+    #[test]
+    fn test_quat_normalize() {
+        let q = Quat {
+            w: 2.0,
+            v: Vec3::new(0.0, 2.0, 0.0),
+        }
+        .normalize();
+        assert!((q.dot(&q) - 1.0).abs() < 1e-12);
+        assert_close(
+            &q.rotate(&Vec3::new(1.0, 0.0, 0.0)),
+            &Vec3::new(0.0, 0.0, -1.0),
+        );
+        let scaled = Quat {
+            w: q.w * 3.0,
+            v: q.v * 3.0,
+        };
+        assert_mat3_close(&scaled.to_mat3(), &q.to_mat3());
+    }
+}
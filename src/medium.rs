@@ -0,0 +1,510 @@
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, HitTable},
+    material::{Material, ScatterRecord},
+    ray::Ray,
+    rtweekend::{mix, Rng},
+    sampler::Sampler,
+    vec3::{Color, Point3, Vec3},
+};
+use std::{convert::TryInto, f64::INFINITY, fs, sync::Arc};
+
+// Random numbers are derived from the ray alone, so that testing the same ray twice gives the
+// same answer and renders stay reproducible.
+fn ray_seed(r: &Ray) -> u64 {
+    let mut h = 0;
+    for x in &[
+        r.orig.x, r.orig.y, r.orig.z, r.dir.x, r.dir.y, r.dir.z, r.time,
+    ] {
+        h = mix(h ^ x.to_bits());
+    }
+    h
+}
+
+// uniform in (0, 1)
+fn ray_random(r: &Ray) -> f64 {
+    ((ray_seed(r) >> 11) as f64 + 0.5) * (1.0 / 9007199254740992.0)
+}
+
+// Fog or smoke of constant density filling a closed boundary. A ray is scattered at a distance
+// drawn from the exponential distribution, or passes through when that lies past the exit.
+pub struct ConstantMedium {
+    pub boundary: Arc<dyn HitTable>,
+    // scattering events per unit length
+    pub density: f64,
+    pub phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Arc<dyn HitTable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            boundary,
+            density,
+            phase_function,
+        }
+    }
+}
+
+impl HitTable for ConstantMedium {
+    // the boundary must be convex, a ray entering it a second time is ignored
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut rec1 = rec.clone();
+        let mut rec2 = rec.clone();
+        if !self.boundary.hit(r, -INFINITY, INFINITY, &mut rec1) {
+            return false;
+        }
+        if !self.boundary.hit(r, rec1.t + 0.0001, INFINITY, &mut rec2) {
+            return false;
+        }
+        let t0 = if rec1.t < t_min { t_min } else { rec1.t };
+        let t1 = if rec2.t > t_max { t_max } else { rec2.t };
+        if t0 >= t1 {
+            return false;
+        }
+        let ray_length = r.dir.length();
+        let distance_inside = (t1 - t0) * ray_length;
+        let hit_distance = -ray_random(r).ln() / self.density;
+        if hit_distance > distance_inside {
+            return false;
+        }
+        rec.t = t0 + hit_distance / ray_length;
+        rec.p = r.at(rec.t);
+        // arbitrary, the phase function does not look at it
+        rec.normal = Vec3::new(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.u = 0.0;
+        rec.v = 0.0;
+        rec.mat_ptr = self.phase_function.clone();
+        true
+    }
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut AABB) -> bool {
+        self.boundary.bounding_box(t0, t1, output_box)
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        self.boundary.distance(other_center)
+    }
+}
+
+// Dense voxel grid of non-negative values, stored with x varying fastest and z slowest. Samples
+// sit at the voxel centers and are interpolated trilinearly.
+pub struct Grid {
+    pub nx: usize,
+    pub ny: usize,
+    pub nz: usize,
+    data: Vec<f64>,
+    // largest value, which bounds every lookup
+    pub max: f64,
+}
+
+impl Grid {
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f64>) -> Self {
+        assert_eq!(
+            data.len(),
+            nx * ny * nz,
+            "grid size does not match its data"
+        );
+        let max = data.iter().fold(0.0, |max: f64, &x| max.max(x));
+        Self {
+            nx,
+            ny,
+            nz,
+            data,
+            max,
+        }
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.data[(z * self.ny + y) * self.nx + x]
+    }
+
+    // value at `p` in [0, 1]^3, clamped to the outer voxels beyond it
+    pub fn lookup(&self, p: &Point3) -> f64 {
+        // lower voxel and weight of the upper one along an axis
+        let split = |x: f64, n: usize| {
+            let x = x * n as f64 - 0.5;
+            if x <= 0.0 {
+                (0, 0, 0.0)
+            } else if x >= (n - 1) as f64 {
+                (n - 1, n - 1, 0.0)
+            } else {
+                let i = x as usize;
+                (i, i + 1, x - i as f64)
+            }
+        };
+        let (x0, x1, fx) = split(p.x, self.nx);
+        let (y0, y1, fy) = split(p.y, self.ny);
+        let (z0, z1, fz) = split(p.z, self.nz);
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let plane = |z| {
+            lerp(
+                lerp(self.voxel(x0, y0, z), self.voxel(x1, y0, z), fx),
+                lerp(self.voxel(x0, y1, z), self.voxel(x1, y1, z), fx),
+                fy,
+            )
+        };
+        lerp(plane(z0), plane(z1), fz)
+    }
+}
+
+// Raw grid file: the sizes nx, ny and nz as little-endian u32, then nx * ny * nz little-endian
+// f32 values in the order of `Grid`.
+pub fn parse_grid(bytes: &[u8], path: &str) -> Result<Grid, String> {
+    if bytes.len() < 12 {
+        return Err(format!("{}: missing grid header", path));
+    }
+    let word = |i: usize| -> [u8; 4] { bytes[4 * i..4 * i + 4].try_into().unwrap() };
+    let nx = u32::from_le_bytes(word(0)) as usize;
+    let ny = u32::from_le_bytes(word(1)) as usize;
+    let nz = u32::from_le_bytes(word(2)) as usize;
+    if nx == 0 || ny == 0 || nz == 0 {
+        return Err(format!("{}: empty grid", path));
+    }
+    let n = match nx.checked_mul(ny).and_then(|n| n.checked_mul(nz)) {
+        Some(n) => n,
+        None => {
+            return Err(format!(
+                "{}: grid of {}x{}x{} is too large",
+                path, nx, ny, nz
+            ))
+        }
+    };
+    if 4_usize.checked_mul(n).and_then(|len| len.checked_add(12)) != Some(bytes.len()) {
+        return Err(format!(
+            "{}: expected {} values for a {}x{}x{} grid, found {} bytes",
+            path,
+            n,
+            nx,
+            ny,
+            nz,
+            bytes.len() - 12
+        ));
+    }
+    let mut data = Vec::with_capacity(n);
+    for i in 0..n {
+        let x = f32::from_le_bytes(word(3 + i)) as f64;
+        if !(x >= 0.0 && x.is_finite()) {
+            return Err(format!("{}: value {} of the grid is {}", path, i, x));
+        }
+        data.push(x);
+    }
+    Ok(Grid::new(nx, ny, nz, data))
+}
+
+pub fn load_grid(path: &str) -> Result<Grid, String> {
+    match fs::read(path) {
+        Ok(bytes) => parse_grid(&bytes, path),
+        Err(err) => Err(format!("{}: {}", path, err)),
+    }
+}
+
+// Heterogeneous medium, such as a cloud or an explosion, with a density grid stretched over a box.
+// Collisions are found by delta tracking: distances are drawn against the largest density and
+// accepted with the ratio of the local density to it, so the rest are null collisions.
+pub struct GridMedium {
+    bounds: AABB,
+    density: Arc<Grid>,
+    // multiplies the grid values, in scattering events per unit length
+    density_scale: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl GridMedium {
+    pub fn new(
+        bounds: AABB,
+        density: Arc<Grid>,
+        density_scale: f64,
+        emission: Option<Arc<Grid>>,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        let phase_function = match emission {
+            Some(emission) => Arc::new(GridEmission {
+                bounds: bounds.clone(),
+                emission,
+                phase_function,
+            }),
+            None => phase_function,
+        };
+        Self {
+            bounds,
+            density,
+            density_scale,
+            phase_function,
+        }
+    }
+}
+
+// position of `p` within `bounds`, in [0, 1]^3
+fn grid_point(bounds: &AABB, p: &Point3) -> Point3 {
+    let (min, max) = (bounds._min, bounds._max);
+    Point3::new(
+        (p.x - min.x) / (max.x - min.x),
+        (p.y - min.y) / (max.y - min.y),
+        (p.z - min.z) / (max.z - min.z),
+    )
+}
+
+// phase function of a `GridMedium` whose emission is scaled by a grid over `bounds`
+struct GridEmission {
+    bounds: AABB,
+    emission: Arc<Grid>,
+    phase_function: Arc<dyn Material>,
+}
+
+impl GridEmission {
+    fn scale(&self, p: &Point3) -> f64 {
+        self.emission.lookup(&grid_point(&self.bounds, p))
+    }
+}
+
+impl Material for GridEmission {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        self.phase_function.scatter(r_in, rec, srec, sampler)
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> Color {
+        self.phase_function.eval(r_in, rec, dir)
+    }
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3) -> f64 {
+        self.phase_function.pdf(r_in, rec, dir)
+    }
+    fn has_non_delta(&self) -> bool {
+        self.phase_function.has_non_delta()
+    }
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.phase_function.emitted(u, v, p) * self.scale(p)
+    }
+    fn eval_spectral(&self, r_in: &Ray, rec: &HitRecord, dir: &Vec3, lambda: &Vec3) -> Vec3 {
+        self.phase_function.eval_spectral(r_in, rec, dir, lambda)
+    }
+    fn attenuation_spectral(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        lambda: &Vec3,
+    ) -> Vec3 {
+        self.phase_function
+            .attenuation_spectral(r_in, rec, srec, lambda)
+    }
+    fn emitted_spectral(&self, u: f64, v: f64, p: &Point3, lambda: &Vec3) -> Vec3 {
+        self.phase_function.emitted_spectral(u, v, p, lambda) * self.scale(p)
+    }
+}
+
+impl HitTable for GridMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let majorant = self.density_scale * self.density.max;
+        if majorant <= 0.0 {
+            return false;
+        }
+        let (mut t, t1) = match self.bounds.clip(r, t_min, t_max) {
+            Some(interval) => interval,
+            None => return false,
+        };
+        let mut rng = Rng::new(ray_seed(r));
+        let step = 1.0 / (majorant * r.dir.length());
+        loop {
+            t -= (1.0 - rng.random_double(0.0, 1.0)).ln() * step;
+            if t >= t1 {
+                return false;
+            }
+            let q = grid_point(&self.bounds, &r.at(t));
+            if rng.random_double(0.0, majorant) < self.density_scale * self.density.lookup(&q) {
+                rec.t = t;
+                rec.p = r.at(t);
+                rec.normal = Vec3::new(1.0, 0.0, 0.0);
+                rec.front_face = true;
+                rec.u = 0.0;
+                rec.v = 0.0;
+                rec.mat_ptr = self.phase_function.clone();
+                return true;
+            }
+        }
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        *output_box = self.bounds.clone();
+        true
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        self.bounds.centroid().distance(*other_center)
+    }
+}
+
+#[allow(clippy::float_cmp)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::Sphere,
+        material::{HenyeyGreenstein, Isotropic, Lambertian},
+        texture::ConstTexture,
+    };
+
+    #[test]
+    fn test_transmittance() {
+        let mat: Arc<dyn Material> = Arc::new(Isotropic::new(Color::ones()));
+        let medium = ConstantMedium::new(
+            Arc::new(Sphere::new(Point3::zero(), 1.0, mat.clone())),
+            0.5,
+            mat,
+        );
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        // rays through the center cross 2 units of the medium
+        let n = 20000;
+        let mut passed = 0;
+        for i in 0..n {
+            let r = Ray {
+                orig: Point3::new(0.0, 0.0, -5.0 - i as f64 * 1e-6),
+                dir: Vec3::new(0.0, 0.0, 2.0),
+                time: 0.0,
+                wavelength: None,
+            };
+            if medium.hit(&r, 0.001, INFINITY, &mut rec) {
+                assert!(rec.p.z >= -1.0 && rec.p.z <= 1.0);
+                // the same ray scatters at the same place
+                let t = rec.t;
+                assert!(medium.hit(&r, 0.001, INFINITY, &mut rec));
+                assert_eq!(rec.t, t);
+            } else {
+                passed += 1;
+            }
+        }
+        let expected = (-0.5_f64 * 2.0).exp();
+        assert!((passed as f64 / n as f64 - expected).abs() < 0.02);
+        // from inside, only the way to the exit counts
+        let r = Ray {
+            orig: Point3::zero(),
+            dir: Vec3::new(0.0, 1.0, 0.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        if medium.hit(&r, 0.001, INFINITY, &mut rec) {
+            assert!(rec.t > 0.001 && rec.t < 1.0);
+        }
+    }
+
+    fn grid_bytes(n: [u32; 3], values: &[f32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for x in &n {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        for x in values {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_grid() {
+        let values: Vec<f32> = (0..8).map(|i| i as f32).collect();
+        let grid = parse_grid(&grid_bytes([2, 2, 2], &values), "g").unwrap();
+        assert_eq!(grid.max, 7.0);
+        // voxel centers, their midpoint and clamping outside
+        assert_eq!(grid.lookup(&Point3::new(0.25, 0.25, 0.25)), 0.0);
+        assert_eq!(grid.lookup(&Point3::new(0.75, 0.25, 0.25)), 1.0);
+        assert_eq!(grid.lookup(&Point3::new(0.25, 0.75, 0.75)), 6.0);
+        assert_eq!(grid.lookup(&Point3::new(0.5, 0.5, 0.5)), 3.5);
+        assert_eq!(grid.lookup(&Point3::new(2.0, -1.0, 0.25)), 1.0);
+        assert_eq!(
+            parse_grid(&grid_bytes([2, 2, 2], &values[..7]), "g").err(),
+            Some(String::from(
+                "g: expected 8 values for a 2x2x2 grid, found 28 bytes"
+            ))
+        );
+        assert!(parse_grid(&grid_bytes([1, 1, 1], &[-1.0]), "g").is_err());
+        // sizes whose product or byte length overflows
+        let huge = u32::MAX;
+        assert!(parse_grid(&grid_bytes([huge, huge, huge], &[1.0]), "g").is_err());
+        assert!(parse_grid(&grid_bytes([huge, huge, 2], &[1.0]), "g").is_err());
+        assert!(parse_grid(&[0, 1], "g").is_err());
+    }
+
+    #[test]
+    fn test_delta_tracking() {
+        // density rising linearly along x from 0 to 2, so the optical depth across is 1
+        let n = 64;
+        let values: Vec<f32> = (0..n).map(|i| 2.0 * (i as f32 + 0.5) / n as f32).collect();
+        let grid = Arc::new(parse_grid(&grid_bytes([n, 1, 1], &values), "g").unwrap());
+        let mat: Arc<dyn Material> = Arc::new(Isotropic::new(Color::ones()));
+        let medium = GridMedium::new(
+            AABB::new(Point3::zero(), Point3::ones()),
+            grid,
+            1.0,
+            None,
+            mat,
+        );
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        let trials = 20000;
+        let mut passed = 0;
+        let mut depth = 0.0;
+        for i in 0..trials {
+            let r = Ray {
+                orig: Point3::new(-1.0, 0.5, 0.5 + i as f64 * 1e-6),
+                dir: Vec3::new(1.0, 0.0, 0.0),
+                time: 0.0,
+                wavelength: None,
+            };
+            if medium.hit(&r, 0.001, INFINITY, &mut rec) {
+                assert!(rec.p.x >= 0.0 && rec.p.x <= 1.0);
+                depth += rec.p.x;
+            } else {
+                passed += 1;
+            }
+        }
+        let expected = (-1.0_f64).exp();
+        assert!((passed as f64 / trials as f64 - expected).abs() < 0.02);
+        // collisions are denser where the medium is
+        assert!(depth / ((trials - passed) as f64) > 0.5);
+    }
+
+    #[test]
+    fn test_grid_emission() {
+        // emission rising from 0 to 2 along x scales the glow of the phase function
+        let density = Arc::new(parse_grid(&grid_bytes([1, 1, 1], &[4.0]), "g").unwrap());
+        let emission = Arc::new(parse_grid(&grid_bytes([2, 1, 1], &[0.0, 2.0]), "g").unwrap());
+        let emit = Color::new(1.0, 0.5, 0.25);
+        let mat: Arc<dyn Material> = Arc::new(HenyeyGreenstein::new(
+            0.3,
+            Arc::new(ConstTexture {
+                color_value: Color::ones(),
+            }),
+            emit,
+        ));
+        let medium = GridMedium::new(
+            AABB::new(Point3::zero(), Point3::ones()),
+            density,
+            1.0,
+            Some(emission.clone()),
+            mat,
+        );
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        let mut hits = 0;
+        for i in 0..200 {
+            let r = Ray {
+                orig: Point3::new(-1.0, 0.5, 0.5 + i as f64 * 1e-6),
+                dir: Vec3::new(1.0, 0.0, 0.0),
+                time: 0.0,
+                wavelength: None,
+            };
+            if !medium.hit(&r, 0.001, INFINITY, &mut rec) {
+                continue;
+            }
+            hits += 1;
+            // texture coordinates are left alone for the albedo
+            assert_eq!((rec.u, rec.v), (0.0, 0.0));
+            let scale = emission.lookup(&rec.p);
+            let emitted = rec.mat_ptr.emitted(rec.u, rec.v, &rec.p);
+            assert!((emitted - emit * scale).length() < 1e-12);
+        }
+        assert!(hits > 0);
+    }
+}
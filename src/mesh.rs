@@ -0,0 +1,127 @@
+use crate::{
+    aabb::AABB,
+    bvh::LinearBVH,
+    hittable::{HitRecord, HitTable},
+    material::Material,
+    ray::Ray,
+    triangle::{closest_point_on_triangle, hit_triangle, set_triangle_hit, triangle_box},
+    vec3::{Point3, Vec3},
+};
+use std::sync::Arc;
+
+// triangles per leaf of the internal BVH
+const MAX_LEAF_SIZE: usize = 4;
+
+// Triangles sharing flat vertex buffers and one material, seen by the scene as a single object.
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    // per-vertex normals and uvs, either empty or as long as `positions`
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub indices: Vec<[u32; 3]>,
+    pub mat_ptr: Arc<dyn Material>,
+    bvh: LinearBVH,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[u32; 3]>,
+        m: Arc<dyn Material>,
+    ) -> Self {
+        assert!(normals.is_empty() || normals.len() == positions.len());
+        assert!(uvs.is_empty() || uvs.len() == positions.len());
+        let mut mesh = Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            mat_ptr: m,
+            bvh: LinearBVH::default(),
+        };
+        let boxes = (0..mesh.indices.len() as u32)
+            .map(|tri| mesh.triangle_box(tri))
+            .collect();
+        mesh.bvh = LinearBVH::new(boxes, MAX_LEAF_SIZE);
+        mesh
+    }
+
+    fn vertices(&self, tri: u32) -> [&Point3; 3] {
+        let idx = &self.indices[tri as usize];
+        [
+            &self.positions[idx[0] as usize],
+            &self.positions[idx[1] as usize],
+            &self.positions[idx[2] as usize],
+        ]
+    }
+
+    fn triangle_box(&self, tri: u32) -> AABB {
+        let v = self.vertices(tri);
+        triangle_box(v[0], v[1], v[2])
+    }
+
+    fn hit_triangle(&self, tri: u32, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let v = self.vertices(tri);
+        let (t, b1, b2) = match hit_triangle(v[0], v[1], v[2], r, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let idx = &self.indices[tri as usize];
+        let n = if self.normals.is_empty() {
+            None
+        } else {
+            Some([
+                &self.normals[idx[0] as usize],
+                &self.normals[idx[1] as usize],
+                &self.normals[idx[2] as usize],
+            ])
+        };
+        let uv = if self.uvs.is_empty() {
+            None
+        } else {
+            Some([
+                &self.uvs[idx[0] as usize],
+                &self.uvs[idx[1] as usize],
+                &self.uvs[idx[2] as usize],
+            ])
+        };
+        set_triangle_hit(rec, r, v, n, uv, t, b1, b2);
+        true
+    }
+}
+
+impl HitTable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let hit_anything = self.bvh.traverse(r, t_min, t_max, |tri, closest_so_far| {
+            if self.hit_triangle(tri as u32, r, t_min, closest_so_far, rec) {
+                Some(rec.t)
+            } else {
+                None
+            }
+        });
+        if hit_anything {
+            rec.mat_ptr = self.mat_ptr.clone();
+        }
+        hit_anything
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        match self.bvh.bbox() {
+            Some(bbox) => {
+                *output_box = bbox.clone();
+                true
+            }
+            None => false,
+        }
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        let mut dis = f64::INFINITY;
+        for tri in 0..self.indices.len() as u32 {
+            let v = self.vertices(tri);
+            let closest = closest_point_on_triangle(other_center, v[0], v[1], v[2]);
+            dis = dis.min(other_center.distance(closest));
+        }
+        dis
+    }
+}
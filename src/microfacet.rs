@@ -0,0 +1,238 @@
+use crate::{
+    rtweekend::clamp,
+    vec3::{sample_in_unit_disk, Color, Vec3},
+};
+use std::f64::consts::PI;
+
+// Below this roughness the lobes are narrower than floating point handles well, and materials
+// switch to perfectly smooth (delta) scattering.
+pub const SMOOTH_ALPHA: f64 = 1e-3;
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals with Smith shadowing. Directions are
+// in a local frame with the macro surface normal along +z, `alpha_x` and `alpha_y` are the
+// roughness along x and y.
+#[derive(Clone, Copy, Debug)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self { alpha_x, alpha_y }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    // density of microfacets facing `wm`, per unit of projected area
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        if wm.z <= 0.0 {
+            return 0.0;
+        }
+        let z2 = wm.z * wm.z;
+        let e = (wm.x * wm.x / (self.alpha_x * self.alpha_x)
+            + wm.y * wm.y / (self.alpha_y * self.alpha_y))
+            / z2;
+        1.0 / (PI * self.alpha_x * self.alpha_y * z2 * z2 * (1.0 + e) * (1.0 + e))
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let alpha2_tan2 = (w.x * w.x * self.alpha_x * self.alpha_x
+            + w.y * w.y * self.alpha_y * self.alpha_y)
+            / (w.z * w.z);
+        if !alpha2_tan2.is_finite() {
+            return 0.0;
+        }
+        ((1.0 + alpha2_tan2).sqrt() - 1.0) / 2.0
+    }
+
+    // fraction of the microfacets seen from `w` that are not hidden
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // fraction seen from both `wo` and `wi`, height correlated
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // density of `sample_wm` picking `wm` when seen from `w`
+    pub fn visible_pdf(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z.abs() * self.d(wm) * (*w * *wm).max(0.0)
+    }
+
+    // microfacet normal visible from `w`, which must lie above the surface (Heitz 2018,
+    // "Sampling the GGX Distribution of Visible Normals")
+    pub fn sample_wm(&self, w: &Vec3, u: (f64, f64)) -> Vec3 {
+        // stretch to the configuration with unit roughness
+        let wh = Vec3::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z).unit();
+        let t1 = if wh.z < 0.99999 {
+            Vec3::new(0.0, 0.0, 1.0).cross(wh).unit()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(t1);
+        // uniform disk sample, squeezed onto the visible part of the hemisphere
+        let p = sample_in_unit_disk(u);
+        let h = (1.0 - p.x * p.x).sqrt();
+        let s = (1.0 + wh.z) / 2.0;
+        let py = (1.0 - s) * h + s * p.y;
+        let pz = (1.0 - p.x * p.x - py * py).max(0.0).sqrt();
+        let nh = t1 * p.x + t2 * py + wh * pz;
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).unit()
+    }
+}
+
+// unpolarized reflectance of a dielectric interface, with `eta` the ratio of the index on the
+// far side to the one on the side of the light, and `cos_i` the cosine to the normal on its side
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = clamp(cos_i, 0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+// reflectance of a conductor with complex index `eta + i k`, for one wavelength
+fn fresnel_complex(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    (rp + rs) / 2.0
+}
+
+pub fn fresnel_conductor(cos_i: f64, eta: &Color, k: &Color) -> Color {
+    let cos_i = clamp(cos_i, 0.0, 1.0);
+    Color::new(
+        fresnel_complex(cos_i, eta.x, k.x),
+        fresnel_complex(cos_i, eta.y, k.y),
+        fresnel_complex(cos_i, eta.z, k.z),
+    )
+}
+
+// complex index of refraction (eta, k) of common metals at red, green and blue wavelengths
+pub fn conductor_preset(name: &str) -> Option<(Color, Color)> {
+    match name {
+        "gold" => Some((
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+        )),
+        "copper" => Some((
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+        )),
+        "aluminium" => Some((
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+        )),
+        "silver" => Some((
+            Color::new(0.155, 0.117, 0.138),
+            Color::new(4.828, 3.122, 2.147),
+        )),
+        _ => None,
+    }
+}
+
+// refraction of `wo` (pointing away from the surface) through a facet with normal `n` on its
+// side, None under total internal reflection
+pub fn refract_across(wo: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = *wo * *n;
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*wo / eta + *n * (cos_i / eta - cos_t))
+}
+
+#[allow(clippy::float_cmp)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::sample_unit_vector;
+
+    #[test]
+    fn test_distribution() {
+        // the projected microfacet area is the macro surface area
+        for &(ax, ay) in &[(0.5, 0.5), (0.2, 0.6), (0.9, 0.1)] {
+            let dist = TrowbridgeReitz::new(ax, ay);
+            let n = 200000;
+            let mut projected = 0.0;
+            let mut visible = 0.0;
+            let w = Vec3::new(0.3, -0.5, 0.6).unit();
+            for i in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (i as f64 * 0.618034) % 1.0);
+                let wm = sample_unit_vector(u);
+                projected += dist.d(&wm) * wm.z.max(0.0) * 4.0 * PI / n as f64;
+                visible += dist.visible_pdf(&w, &wm) * 4.0 * PI / n as f64;
+            }
+            assert!((projected - 1.0).abs() < 0.02);
+            assert!((visible - 1.0).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn test_sample_wm() {
+        let dist = TrowbridgeReitz::new(0.3, 0.6);
+        let w = Vec3::new(-0.4, 0.2, 0.7).unit();
+        let n = 20000;
+        let mut mean = Vec3::zero();
+        let mut expected = Vec3::zero();
+        for i in 0..n {
+            let u = ((i as f64 + 0.5) / n as f64, (i as f64 * 0.618034) % 1.0);
+            let wm = dist.sample_wm(&w, u);
+            assert!((wm.length() - 1.0).abs() < 1e-9);
+            assert!(wm * w >= -1e-9);
+            mean += wm / n as f64;
+            let d = sample_unit_vector(u);
+            expected += d * (dist.visible_pdf(&w, &d) * 4.0 * PI / n as f64);
+        }
+        assert!((mean - expected).length() < 0.02);
+    }
+
+    #[test]
+    fn test_fresnel() {
+        // 4% at normal incidence on glass, total internal reflection from inside
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+        // a conductor without absorption behaves like a dielectric
+        for &c in &[0.1, 0.5, 0.9] {
+            let f = fresnel_conductor(c, &Color::new(1.5, 1.5, 1.5), &Color::zero());
+            assert!((f.x - fresnel_dielectric(c, 1.5)).abs() < 1e-9);
+        }
+        let (eta, k) = conductor_preset("gold").unwrap();
+        let f = fresnel_conductor(1.0, &eta, &k);
+        assert!(f.x > f.z && f.x > 0.9 && f.z < 0.5);
+        assert!(conductor_preset("unobtainium").is_none());
+    }
+
+    #[test]
+    fn test_refract_across() {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let wt = refract_across(&wo, &n, 1.5).unwrap();
+        assert!((wt.length() - 1.0).abs() < 1e-12);
+        // Snell's law
+        assert!((wo.x - 1.5 * -wt.x).abs() < 1e-12);
+        assert!(refract_across(&Vec3::new(0.8, 0.0, 0.6), &n, 1.0 / 1.5).is_none());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{
+    rtweekend::{mix, Rng},
+    vec3::{sample_unit_vector, Point3, Vec3},
+};
+
+const POINT_COUNT: usize = 256;
+
+// Perlin gradient noise. The gradients and permutation tables come from `seed`, so different
+// seeds give unrelated patterns.
+pub struct Perlin {
+    ranvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let ranvec = (0..POINT_COUNT)
+            .map(|_| {
+                let u = (rng.random_double(0.0, 1.0), rng.random_double(0.0, 1.0));
+                sample_unit_vector(u)
+            })
+            .collect();
+        Self {
+            ranvec,
+            perm_x: Self::generate_perm(&mut rng),
+            perm_y: Self::generate_perm(&mut rng),
+            perm_z: Self::generate_perm(&mut rng),
+        }
+    }
+
+    // Fisher-Yates shuffle of 0..POINT_COUNT
+    fn generate_perm(rng: &mut Rng) -> Vec<usize> {
+        let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+        for i in (1..POINT_COUNT).rev() {
+            let target = (rng.next_u32() as usize) % (i + 1);
+            p.swap(i, target);
+        }
+        p
+    }
+
+    // smooth noise in about [-1, 1], zero at every lattice point
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+        // Hermite smoothing hides the lattice
+        let (uu, vv, ww) = (
+            u * u * (3.0 - 2.0 * u),
+            v * v * (3.0 - 2.0 * v),
+            w * w * (3.0 - 2.0 * w),
+        );
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.ranvec[self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize]];
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - a, v - b, w - c);
+                    accum += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * (gradient * weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // fractal sum of `octaves` layers of |noise|, each twice as fine and half as strong
+    pub fn turb(&self, p: &Point3, octaves: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            accum += weight * self.noise(&temp_p).abs();
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+        accum
+    }
+}
+
+// Worley (cellular) noise with one feature point in every unit cell.
+pub struct Worley {
+    seed: u64,
+}
+
+impl Worley {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    fn feature_point(&self, i: i64, j: i64, k: i64) -> Point3 {
+        let h = mix(self.seed ^ mix(i as u64 ^ mix(j as u64 ^ mix(k as u64))));
+        let unit = |bits: u64| (bits & 0x1fffff) as f64 / 2097152.0;
+        Point3::new(
+            i as f64 + unit(h),
+            j as f64 + unit(h >> 21),
+            k as f64 + unit(h >> 42),
+        )
+    }
+
+    // distance from `p` to the closest feature point
+    pub fn distance(&self, p: &Point3) -> f64 {
+        let (i, j, k) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+        let mut closest = f64::INFINITY;
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let d = self.feature_point(i + di, j + dj, k + dk).distance(*p);
+                    closest = closest.min(d);
+                }
+            }
+        }
+        closest
+    }
+}
+
+#[allow(clippy::float_cmp)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perlin() {
+        let perlin = Perlin::new(7);
+        let same = Perlin::new(7);
+        let other = Perlin::new(8);
+        let mut differs = false;
+        for i in 0..200 {
+            let p = Point3::new(i as f64 * 0.37, i as f64 * -0.11, i as f64 * 0.05 + 0.5);
+            let n = perlin.noise(&p);
+            assert!(n.abs() <= 1.5);
+            assert_eq!(n.to_bits(), same.noise(&p).to_bits());
+            differs |= n != other.noise(&p);
+            assert!(perlin.turb(&p, 7) >= 0.0 && perlin.turb(&p, 7) < 2.0 * 1.5);
+        }
+        assert!(differs);
+        assert!(perlin.noise(&Point3::new(3.0, -2.0, 5.0)).abs() < 1e-12);
+        // continuous
+        let p = Point3::new(1.3, 2.7, -0.4);
+        let q = p + Vec3::new(1e-6, 0.0, 0.0);
+        assert!((perlin.noise(&p) - perlin.noise(&q)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_worley() {
+        let worley = Worley::new(3);
+        for i in 0..200 {
+            let p = Point3::new(i as f64 * 0.37, i as f64 * -0.11, i as f64 * 0.05 + 0.5);
+            let d = worley.distance(&p);
+            // the feature point of the cell containing `p` is at most a diagonal away
+            assert!(d >= 0.0 && d <= 3.0_f64.sqrt());
+        }
+        let feature = worley.feature_point(2, -1, 4);
+        assert!(worley.distance(&feature) < 1e-12);
+    }
+}
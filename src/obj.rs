@@ -0,0 +1,457 @@
+use crate::{
+    hittablelist::HitTableList,
+    material::{DiffuseLight, Lambertian, Material, Principled},
+    mesh::TriangleMesh,
+    rtweekend::{clamp, fmax},
+    texture::{ConstTexture, Texture},
+    vec3::{Color, Point3, Vec3},
+};
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+// the subset of an MTL material we can map onto the principled material, including the usual
+// PBR extension keys
+struct MtlMaterial {
+    kd: Color,
+    ks: Color,
+    ke: Color,
+    ns: Option<f64>,
+    ni: f64,
+    d: f64,
+    illum: i64,
+    roughness: Option<f64>,
+    metallic: Option<f64>,
+    sheen: Option<f64>,
+    clearcoat: Option<f64>,
+    clearcoat_roughness: Option<f64>,
+}
+
+impl MtlMaterial {
+    fn new() -> Self {
+        Self {
+            kd: Color::new(0.8, 0.8, 0.8),
+            ks: Color::zero(),
+            ke: Color::zero(),
+            ns: None,
+            ni: 1.5,
+            d: 1.0,
+            illum: 2,
+            roughness: None,
+            metallic: None,
+            sheen: None,
+            clearcoat: None,
+            clearcoat_roughness: None,
+        }
+    }
+
+    fn to_material(&self) -> Arc<dyn Material> {
+        // lamps only emit, as they always have
+        if fmax(self.ke.x, fmax(self.ke.y, self.ke.z)) > 0.0 {
+            return Arc::new(DiffuseLight::new(self.ke));
+        }
+        Arc::new(self.to_principled())
+    }
+
+    fn to_principled(&self) -> Principled {
+        let max = |c: &Color| fmax(c.x, fmax(c.y, c.z));
+        let gray = |x: f64| -> Arc<dyn Texture> {
+            Arc::new(ConstTexture {
+                color_value: Color::new(x, x, x),
+            })
+        };
+        // illumination models 4, 6 and 7 are the refractive ones
+        let refractive = self.illum == 4 || self.illum == 6 || self.illum == 7;
+        // without the extension keys, illumination model 3 or an opaque material with a strong
+        // specular color means metal
+        let metallic = match self.metallic {
+            Some(metallic) => metallic,
+            None if self.illum == 3 || (!refractive && max(&self.ks) > max(&self.kd)) => 1.0,
+            None => 0.0,
+        };
+        let mut mat = Principled::new(if self.metallic.is_none() && metallic > 0.0 {
+            self.ks
+        } else {
+            self.kd
+        });
+        mat.metallic = gray(metallic);
+        // the principled specular is a reflectance of 0.08 at most, black `Ks` means none
+        mat.specular = gray(clamp(max(&self.ks) / 0.08, 0.0, 1.0));
+        // usual conversion from a phong exponent to a roughness
+        if let Some(roughness) = self
+            .roughness
+            .or_else(|| self.ns.map(|ns| (2.0 / (ns + 2.0)).sqrt()))
+        {
+            mat.roughness = gray(roughness);
+        }
+        let transmission = if refractive { 1.0 } else { 1.0 - self.d };
+        mat.transmission = gray(transmission);
+        mat.ior = self.ni;
+        if let Some(sheen) = self.sheen {
+            mat.sheen = gray(sheen);
+        }
+        if let Some(clearcoat) = self.clearcoat {
+            mat.clearcoat = gray(clearcoat);
+        }
+        if let Some(roughness) = self.clearcoat_roughness {
+            mat.clearcoat_roughness = gray(roughness);
+        }
+        mat
+    }
+}
+
+fn parse_f64(path: &str, line: usize, word: Option<&str>) -> Result<f64, String> {
+    match word.map(|w| w.parse::<f64>()) {
+        Some(Ok(v)) => Ok(v),
+        Some(Err(_)) => Err(format!("{}:{}: invalid number", path, line)),
+        None => Err(format!("{}:{}: missing number", path, line)),
+    }
+}
+
+fn parse_vec3<'a, I: Iterator<Item = &'a str>>(
+    path: &str,
+    line: usize,
+    words: &mut I,
+) -> Result<Vec3, String> {
+    Ok(Vec3::new(
+        parse_f64(path, line, words.next())?,
+        parse_f64(path, line, words.next())?,
+        parse_f64(path, line, words.next())?,
+    ))
+}
+
+fn parse_mtl(src: &str, path: &str) -> Result<HashMap<String, Arc<dyn Material>>, String> {
+    Ok(parse_mtl_entries(src, path)?
+        .into_iter()
+        .map(|(name, mtl)| (name, mtl.to_material()))
+        .collect())
+}
+
+fn parse_mtl_entries(src: &str, path: &str) -> Result<HashMap<String, MtlMaterial>, String> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+    for (idx, line) in src.lines().enumerate() {
+        let line_no = idx + 1;
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        if keyword == "newmtl" {
+            if let Some((name, mtl)) = current.take() {
+                materials.insert(name, mtl);
+            }
+            let name = words.collect::<Vec<&str>>().join(" ");
+            current = Some((name, MtlMaterial::new()));
+            continue;
+        }
+        let mtl = match current.as_mut() {
+            Some((_, mtl)) => mtl,
+            None => return Err(format!("{}:{}: '{}' before newmtl", path, line_no, keyword)),
+        };
+        match keyword {
+            "Kd" => mtl.kd = parse_vec3(path, line_no, &mut words)?,
+            "Ks" => mtl.ks = parse_vec3(path, line_no, &mut words)?,
+            "Ke" => mtl.ke = parse_vec3(path, line_no, &mut words)?,
+            "Ns" => mtl.ns = Some(parse_f64(path, line_no, words.next())?),
+            "Ni" => mtl.ni = parse_f64(path, line_no, words.next())?,
+            "d" => mtl.d = parse_f64(path, line_no, words.next())?,
+            "Tr" => mtl.d = 1.0 - parse_f64(path, line_no, words.next())?,
+            "illum" => mtl.illum = parse_f64(path, line_no, words.next())? as i64,
+            "Pr" => mtl.roughness = Some(parse_f64(path, line_no, words.next())?),
+            "Pm" => mtl.metallic = Some(parse_f64(path, line_no, words.next())?),
+            "Ps" => mtl.sheen = Some(parse_f64(path, line_no, words.next())?),
+            "Pc" => mtl.clearcoat = Some(parse_f64(path, line_no, words.next())?),
+            "Pcr" => mtl.clearcoat_roughness = Some(parse_f64(path, line_no, words.next())?),
+            // texture maps, ambient color and the like are not supported
+            _ => {}
+        }
+    }
+    if let Some((name, mtl)) = current {
+        materials.insert(name, mtl);
+    }
+    Ok(materials)
+}
+
+// resolve a 1-based (or negative, relative to the end) OBJ index
+fn index(path: &str, line: usize, word: &str, len: usize) -> Result<usize, String> {
+    let idx: i64 = match word.parse() {
+        Ok(idx) => idx,
+        Err(_) => return Err(format!("{}:{}: invalid index '{}'", path, line, word)),
+    };
+    let resolved = if idx < 0 { len as i64 + idx } else { idx - 1 };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!("{}:{}: index {} out of range", path, line, idx));
+    }
+    Ok(resolved as usize)
+}
+
+// faces of one material, with the (v, vt, vn) corners of the OBJ file merged into mesh vertices
+struct MeshBuilder {
+    material: Arc<dyn Material>,
+    vertices: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+    positions: Vec<Point3>,
+    normals: Vec<Option<Vec3>>,
+    uvs: Vec<Option<(f64, f64)>>,
+    indices: Vec<[u32; 3]>,
+}
+
+impl MeshBuilder {
+    fn new(material: Arc<dyn Material>) -> Self {
+        Self {
+            material,
+            vertices: HashMap::new(),
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn vertex(
+        &mut self,
+        corner: (usize, Option<usize>, Option<usize>),
+        positions: &[Point3],
+        normals: &[Vec3],
+        uvs: &[(f64, f64)],
+    ) -> u32 {
+        let next = self.positions.len() as u32;
+        let idx = *self.vertices.entry(corner).or_insert(next);
+        if idx == next {
+            self.positions.push(positions[corner.0]);
+            self.uvs.push(corner.1.map(|i| uvs[i]));
+            self.normals.push(corner.2.map(|i| normals[i]));
+        }
+        idx
+    }
+
+    // normals and uvs are only kept when every vertex has them
+    fn build(self) -> TriangleMesh {
+        let normals = self.normals.iter().cloned().collect::<Option<Vec<Vec3>>>();
+        let uvs = self
+            .uvs
+            .iter()
+            .cloned()
+            .collect::<Option<Vec<(f64, f64)>>>();
+        TriangleMesh::new(
+            self.positions,
+            normals.unwrap_or_default(),
+            uvs.unwrap_or_default(),
+            self.indices,
+            self.material,
+        )
+    }
+}
+
+// Parse an OBJ file into one triangle mesh per material. `dir` is where `mtllib` files are
+// looked up.
+pub fn parse_obj(src: &str, path: &str, dir: &Path) -> Result<HitTableList, String> {
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    // builders in order of first use, the unnamed default material is ""
+    let mut meshes: Vec<MeshBuilder> = Vec::new();
+    let mut mesh_of: HashMap<String, usize> = HashMap::new();
+    let mut current = String::new();
+    for (idx, line) in src.lines().enumerate() {
+        let line_no = idx + 1;
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => positions.push(parse_vec3(path, line_no, &mut words)?),
+            Some("vn") => normals.push(parse_vec3(path, line_no, &mut words)?),
+            Some("vt") => {
+                let u = parse_f64(path, line_no, words.next())?;
+                // v defaults to 0 for 1D texture coordinates
+                let v = match words.next() {
+                    Some(word) => parse_f64(path, line_no, Some(word))?,
+                    None => 0.0,
+                };
+                uvs.push((u, v));
+            }
+            Some("f") => {
+                // each corner is v, v/vt, v//vn or v/vt/vn
+                let mut corners = Vec::new();
+                for word in words {
+                    let mut parts = word.split('/');
+                    let v = index(path, line_no, parts.next().unwrap_or(""), positions.len())?;
+                    let vt = match parts.next() {
+                        Some(w) if !w.is_empty() => Some(index(path, line_no, w, uvs.len())?),
+                        _ => None,
+                    };
+                    let vn = match parts.next() {
+                        Some(w) if !w.is_empty() => Some(index(path, line_no, w, normals.len())?),
+                        _ => None,
+                    };
+                    corners.push((v, vt, vn));
+                }
+                if corners.len() < 3 {
+                    return Err(format!(
+                        "{}:{}: face with less than 3 vertices",
+                        path, line_no
+                    ));
+                }
+                let mesh_idx = match mesh_of.get(&current) {
+                    Some(&i) => i,
+                    None => {
+                        let material = match materials.get(&current) {
+                            Some(m) => m.clone(),
+                            None => Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+                        };
+                        meshes.push(MeshBuilder::new(material));
+                        mesh_of.insert(current.clone(), meshes.len() - 1);
+                        meshes.len() - 1
+                    }
+                };
+                let mesh = &mut meshes[mesh_idx];
+                let ids: Vec<u32> = corners
+                    .iter()
+                    .map(|c| mesh.vertex(*c, &positions, &normals, &uvs))
+                    .collect();
+                // triangulate polygons as a fan around the first corner
+                for i in 1..ids.len() - 1 {
+                    mesh.indices.push([ids[0], ids[i], ids[i + 1]]);
+                }
+            }
+            Some("mtllib") => {
+                for name in words {
+                    let mtl_path = dir.join(name);
+                    let mtl_name = mtl_path.to_string_lossy().to_string();
+                    let src = match fs::read_to_string(&mtl_path) {
+                        Ok(src) => src,
+                        Err(err) => return Err(format!("{}:{}: {}", mtl_name, line_no, err)),
+                    };
+                    materials.extend(parse_mtl(&src, &mtl_name)?);
+                }
+            }
+            Some("usemtl") => {
+                current = words.collect::<Vec<&str>>().join(" ");
+                if !materials.contains_key(&current) {
+                    return Err(format!(
+                        "{}:{}: undefined material '{}'",
+                        path, line_no, current
+                    ));
+                }
+            }
+            // groups, smoothing groups, comments and so on
+            _ => {}
+        }
+    }
+    if meshes.is_empty() {
+        return Err(format!("{}: no faces", path));
+    }
+    let mut list = HitTableList::new();
+    for mesh in meshes {
+        list.add(Arc::new(mesh.build()));
+    }
+    Ok(list)
+}
+
+pub fn load_obj(path: &str) -> Result<HitTableList, String> {
+    let src = match fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(err) => return Err(format!("{}: {}", path, err)),
+    };
+    let dir = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+    parse_obj(&src, path, dir)
+}
+
+#[allow(clippy::float_cmp)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::{HitRecord, HitTable},
+        ray::Ray,
+    };
+
+    #[test]
+    fn test_quad() {
+        let src = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                   vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                   f 1/1 2/2 3/3 -1/-1\n";
+        let list = parse_obj(src, "quad.obj", Path::new(".")).unwrap();
+        assert_eq!(list.objects.len(), 1);
+        let r = Ray {
+            orig: Point3::new(0.75, 0.25, 1.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        let mut rec = HitRecord::new(Arc::new(Lambertian::new(Color::zero())));
+        assert!(list.hit(&r, 0.001, 10.0, &mut rec));
+        assert_eq!(rec.t, 1.0);
+        assert_eq!((rec.u, rec.v), (0.75, 0.25));
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!(rec.front_face);
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = Path::new(".");
+        assert_eq!(
+            parse_obj("v 0 0 0\nf 1 2 3\n", "a.obj", dir).err().unwrap(),
+            "a.obj:2: index 2 out of range"
+        );
+        assert_eq!(
+            parse_obj("v 0 0\n", "a.obj", dir).err().unwrap(),
+            "a.obj:1: missing number"
+        );
+        assert_eq!(
+            parse_obj("vt 0.5 abc\n", "a.obj", dir).err().unwrap(),
+            "a.obj:1: invalid number"
+        );
+        let src = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5\nf 1/1 2/1 3/1\n";
+        assert!(parse_obj(src, "a.obj", dir).is_ok());
+        assert_eq!(
+            parse_obj("usemtl red\n", "a.obj", dir).err().unwrap(),
+            "a.obj:1: undefined material 'red'"
+        );
+    }
+
+    #[test]
+    fn test_mtl() {
+        let materials = parse_mtl(
+            "newmtl glass\nNi 1.3\nillum 7\nnewmtl lamp\nKe 1 1 1\n\
+             newmtl chrome\nKd 0.9 0.9 0.9\nPm 1\nPr 0.2\nPc 0.5\n",
+            "a.mtl",
+        )
+        .unwrap();
+        assert_eq!(materials.len(), 3);
+        assert!(materials["lamp"].is_emissive());
+        assert!(!materials["glass"].is_emissive());
+        assert!(parse_mtl("Kd 1 1 1\n", "a.mtl").is_err());
+        // (metallic, roughness, transmission, specular) of each illumination model
+        let entries = parse_mtl_entries(
+            "newmtl matte\nillum 1\n\
+             newmtl plastic\nillum 2\nKs 0.04 0.04 0.04\nNs 98\n\
+             newmtl mirror\nillum 3\nKs 0.9 0.9 0.9\nNs 1000\n\
+             newmtl glass\nillum 4\nKs 1 1 1\nNi 1.5\n\
+             newmtl faded\nillum 2\nd 0.25\n\
+             newmtl chrome\nKd 0.9 0.9 0.9\nPm 1\nPr 0.2\n",
+            "a.mtl",
+        )
+        .unwrap();
+        let value = |t: &Arc<dyn Texture>| t.value(0.0, 0.0, &Point3::zero()).x;
+        for &(name, metallic, roughness, transmission, specular) in &[
+            ("matte", 0.0, 0.5, 0.0, 0.0),
+            ("plastic", 0.0, (2.0_f64 / 100.0).sqrt(), 0.0, 0.5),
+            ("mirror", 1.0, (2.0_f64 / 1002.0).sqrt(), 0.0, 1.0),
+            ("glass", 0.0, 0.5, 1.0, 1.0),
+            ("faded", 0.0, 0.5, 0.75, 0.0),
+            ("chrome", 1.0, 0.2, 0.0, 0.0),
+        ] {
+            let mat = entries[name].to_principled();
+            assert!((value(&mat.metallic) - metallic).abs() < 1e-12, "{}", name);
+            assert!(
+                (value(&mat.roughness) - roughness).abs() < 1e-12,
+                "{}",
+                name
+            );
+            assert!(
+                (value(&mat.transmission) - transmission).abs() < 1e-12,
+                "{}",
+                name
+            );
+            assert!((value(&mat.specular) - specular).abs() < 1e-12, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::vec3::{Point3, Vec3};
+
+// Orthonormal basis with `w` along a given direction, to turn samples drawn around the z axis
+// into world space.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    // branchless construction from Duff et al., "Building an Orthonormal Basis, Revisited"
+    pub fn build_from_w(n: &Vec3) -> Self {
+        let w = n.unit();
+        let sign = 1.0_f64.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+        Self {
+            u: Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x),
+            v: Vec3::new(b, sign + w.y * w.y * a, -w.y),
+            w,
+        }
+    }
+
+    // camera basis at `from`: `w` points back from `at`, `u` to the right and `v` up
+    pub fn look_at(from: &Point3, at: &Point3, up: &Vec3) -> Self {
+        let w = (*from - *at).unit();
+        let u = up.cross(w).unit();
+        Self {
+            u,
+            v: w.cross(u),
+            w,
+        }
+    }
+
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+
+    // coordinates of `a` in the basis, the inverse of `local`
+    pub fn project(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(*a * self.u, *a * self.v, *a * self.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_orthonormal(b: &Onb) {
+        for (x, y) in &[(b.u, b.v), (b.v, b.w), (b.w, b.u)] {
+            assert!((*x * *y).abs() < 1e-12);
+            assert!((x.length() - 1.0).abs() < 1e-12);
+        }
+        // right handed
+        assert!((b.u.cross(b.v) - b.w).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_build_from_w() {
+        for n in &[
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(-0.3, 0.1, -5.0),
+        ] {
+            let b = Onb::build_from_w(n);
+            assert_orthonormal(&b);
+            assert!((b.w - n.unit()).length() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_local() {
+        let b = Onb::build_from_w(&Vec3::new(1.0, -1.0, 0.5));
+        let a = Vec3::new(0.2, 3.0, -1.0);
+        assert!((b.local(&Vec3::new(0.0, 0.0, 2.0)) - b.w * 2.0).length() < 1e-12);
+        assert!((b.project(&b.local(&a)) - a).length() < 1e-12);
+        assert!((b.local(&b.project(&a)) - a).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_look_at() {
+        let b = Onb::look_at(
+            &Point3::new(0.0, 0.0, 5.0),
+            &Point3::zero(),
+            &Vec3::new(0.0, 2.0, 0.0),
+        );
+        assert_orthonormal(&b);
+        assert_eq!(b.u, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(b.v, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(b.w, Vec3::new(0.0, 0.0, 1.0));
+    }
+}
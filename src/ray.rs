@@ -4,6 +4,11 @@ use crate::vec3::{Point3, Vec3};
 pub struct Ray {
     pub orig: Point3,
     pub dir: Vec3,
+    // the moment within the camera shutter interval the ray exists at
+    pub time: f64,
+    // the single wavelength in nanometers the ray carries once dispersion has split the light,
+    // None while it carries all of red, green and blue
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
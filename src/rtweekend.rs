@@ -1,14 +1,57 @@
-use rand::Rng;
 use std::f64::consts::PI;
 
-pub fn random_double(min: f64, max: f64) -> f64 {
-    let mut rng = rand::thread_rng();
-    min + (max - min) * rng.gen::<f64>()
+// PCG32 (pcg-random.org). Every pixel sample gets its own generator derived from the global
+// seed, so an image depends only on the seed and not on how the work is split between threads.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
 }
 
-pub fn random_int(min: i64, max: i64) -> i64 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(min, max)
+const PCG_MULT: u64 = 6364136223846793005;
+
+// splitmix64 finalizer, used to spread seeds and pixel coordinates over the state space
+pub fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self::with_stream(seed, 0)
+    }
+
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(mix(seed));
+        rng.next_u32();
+        rng
+    }
+
+    // generator for sample `index` of pixel (x, y)
+    pub fn for_sample(seed: u64, x: u32, y: u32, index: u32) -> Self {
+        let pixel = mix(seed ^ mix(((y as u64) << 32) | x as u64));
+        Self::with_stream(pixel ^ mix(index as u64), pixel)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(PCG_MULT).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    // uniform in [min, max)
+    pub fn random_double(&mut self, min: f64, max: f64) -> f64 {
+        let unit = self.next_u32() as f64 * (1.0 / 4294967296.0);
+        min + (max - min) * unit
+    }
 }
 
 pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
@@ -38,3 +81,33 @@ pub fn fmax(x: f64, y: f64) -> f64 {
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * PI / 180.0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reproducible() {
+        let mut a = Rng::for_sample(7, 3, 5, 0);
+        let mut b = Rng::for_sample(7, 3, 5, 0);
+        let mut c = Rng::for_sample(7, 5, 3, 0);
+        let xs: Vec<u32> = (0..16).map(|_| a.next_u32()).collect();
+        let ys: Vec<u32> = (0..16).map(|_| b.next_u32()).collect();
+        let zs: Vec<u32> = (0..16).map(|_| c.next_u32()).collect();
+        assert_eq!(xs, ys);
+        assert_ne!(xs, zs);
+        assert_ne!(Rng::new(1).next_u32(), Rng::new(2).next_u32());
+    }
+
+    #[test]
+    fn test_random_double() {
+        let mut rng = Rng::new(42);
+        let mut sum = 0.0;
+        for _ in 0..10000 {
+            let x = rng.random_double(-1.0, 3.0);
+            assert!((-1.0..3.0).contains(&x));
+            sum += x;
+        }
+        assert!((sum / 10000.0 - 1.0).abs() < 0.05);
+    }
+}
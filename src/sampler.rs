@@ -0,0 +1,374 @@
+use crate::rtweekend::{mix, Rng};
+
+// Source of the random numbers for one pixel sample. The camera takes the first two dimensions
+// for the position in the pixel and the next two for the lens, then every bounce takes what its
+// material needs. All samplers are pure functions of (seed, pixel, sample index, dimension), so an
+// image does not depend on the order in which pixels are rendered.
+pub trait Sampler {
+    // start sample `index` of pixel (x, y), going back to the first dimension
+    fn start_sample(&mut self, x: u32, y: u32, index: u32);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "independent" => Some(SamplerKind::Independent),
+            "stratified" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            _ => None,
+        }
+    }
+
+    pub fn build(self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed, samples_per_pixel)),
+        }
+    }
+}
+
+// per-pixel state shared by the samplers that derive every dimension from a hash
+#[derive(Clone, Debug, Default)]
+struct SampleState {
+    pixel: u64,
+    index: u32,
+    dim: u32,
+}
+
+impl SampleState {
+    fn start(&mut self, seed: u64, x: u32, y: u32, index: u32) {
+        self.pixel = mix(seed ^ mix(((y as u64) << 32) | x as u64));
+        self.index = index;
+        self.dim = 0;
+    }
+
+    // hash of the pixel and the next dimension, which is then consumed
+    fn next_dim(&mut self) -> u64 {
+        let h = mix(self.pixel ^ mix(self.dim as u64));
+        self.dim += 1;
+        h
+    }
+}
+
+fn to_unit(bits: u32) -> f64 {
+    bits as f64 * (1.0 / 4294967296.0)
+}
+
+// element i of a pseudo-random permutation of 0..n chosen by `seed`, from Kensler's
+// "Correlated Multi-Jittered Sampling"
+pub fn permute(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    i.wrapping_add(seed) % n
+}
+
+// Uniform random numbers, with one generator per pixel sample.
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.rng = Rng::for_sample(self.seed, x, y, index);
+    }
+    fn get_1d(&mut self) -> f64 {
+        self.rng.random_double(0.0, 1.0)
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        (
+            self.rng.random_double(0.0, 1.0),
+            self.rng.random_double(0.0, 1.0),
+        )
+    }
+}
+
+// Jittered strata: the samples of a pixel fall in distinct strata of every dimension, with the
+// strata shuffled independently per dimension so that dimensions stay uncorrelated.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: u32,
+    // 2D strata, nx * ny >= samples_per_pixel
+    nx: u32,
+    ny: u32,
+    state: SampleState,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: u32) -> Self {
+        let nx = ((samples_per_pixel as f64).sqrt().round() as u32).max(1);
+        let ny = (samples_per_pixel.max(1) - 1) / nx + 1;
+        Self {
+            seed,
+            samples_per_pixel,
+            nx,
+            ny,
+            state: SampleState::default(),
+        }
+    }
+
+    fn jitter(&self, h: u64, k: u32) -> f64 {
+        to_unit((mix(h ^ ((self.state.index as u64) << 2) ^ k as u64) >> 32) as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.state.start(self.seed, x, y, index);
+    }
+    fn get_1d(&mut self) -> f64 {
+        let h = self.state.next_dim();
+        let n = self.samples_per_pixel;
+        let stratum = permute(self.state.index % n, n, h as u32);
+        (stratum as f64 + self.jitter(h, 0)) / n as f64
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        let h = self.state.next_dim();
+        self.state.dim += 1;
+        let n = self.nx * self.ny;
+        let stratum = permute(self.state.index % n, n, h as u32);
+        (
+            ((stratum % self.nx) as f64 + self.jitter(h, 1)) / self.nx as f64,
+            ((stratum / self.nx) as f64 + self.jitter(h, 2)) / self.ny as f64,
+        )
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+fn radical_inverse(base: u32, mut i: u32) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv = inv_base;
+    let mut result = 0.0;
+    while i > 0 {
+        result += (i % base) as f64 * inv;
+        i /= base;
+        inv *= inv_base;
+    }
+    result
+}
+
+// The Halton sequence over the samples of a pixel, one prime base per dimension, decorrelated
+// between pixels by a random toroidal shift (Cranley-Patterson rotation) of every dimension.
+// Dimensions past the prime table reuse its bases, with different shifts.
+pub struct HaltonSampler {
+    seed: u64,
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: SampleState::default(),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.state.start(self.seed, x, y, index);
+    }
+    fn get_1d(&mut self) -> f64 {
+        let base = PRIMES[self.state.dim as usize % PRIMES.len()];
+        let shift = to_unit((self.state.next_dim() >> 32) as u32);
+        let x = radical_inverse(base, self.state.index) + shift;
+        if x >= 1.0 {
+            x - 1.0
+        } else {
+            x
+        }
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// first two dimensions of the Sobol sequence, as 32-bit fractions
+fn sobol_0(i: u32) -> u32 {
+    i.reverse_bits()
+}
+
+fn sobol_1(mut i: u32) -> u32 {
+    let mut result = 0;
+    let mut v = 1 << 31;
+    while i != 0 {
+        if i & 1 != 0 {
+            result ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+// hash-based nested uniform (Owen) scrambling, from Burley's "Practical Hash-based Owen
+// Scrambling"
+fn owen_scramble(mut v: u32, seed: u32) -> u32 {
+    v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x05526c56);
+    v ^= v.wrapping_mul(0x53a22864);
+    v.reverse_bits()
+}
+
+// Owen-scrambled Sobol points. Every 2D request is a separately scrambled copy of the first two
+// Sobol dimensions with its own shuffle of the sample indices ("padding"), which keeps the
+// (0,2)-sequence stratification of each pair without direction numbers for high dimensions.
+// Works best with a power of two samples per pixel.
+pub struct SobolSampler {
+    seed: u64,
+    samples_per_pixel: u32,
+    state: SampleState,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64, samples_per_pixel: u32) -> Self {
+        Self {
+            seed,
+            samples_per_pixel,
+            state: SampleState::default(),
+        }
+    }
+
+    fn shuffled_index(&self, h: u64) -> u32 {
+        let n = self.samples_per_pixel;
+        permute(self.state.index % n, n, h as u32) + self.state.index / n * n
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.state.start(self.seed, x, y, index);
+    }
+    fn get_1d(&mut self) -> f64 {
+        let h = self.state.next_dim();
+        let i = self.shuffled_index(h);
+        to_unit(owen_scramble(sobol_0(i), (h >> 32) as u32))
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        let h = self.state.next_dim();
+        self.state.dim += 1;
+        let i = self.shuffled_index(h);
+        let h2 = mix(h);
+        (
+            to_unit(owen_scramble(sobol_0(i), (h >> 32) as u32)),
+            to_unit(owen_scramble(sobol_1(i), (h2 >> 32) as u32)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samplers(spp: u32) -> Vec<Box<dyn Sampler>> {
+        vec![
+            SamplerKind::Independent.build(3, spp),
+            SamplerKind::Stratified.build(3, spp),
+            SamplerKind::Halton.build(3, spp),
+            SamplerKind::Sobol.build(3, spp),
+        ]
+    }
+
+    #[test]
+    fn test_permute() {
+        for &n in &[1, 2, 7, 16, 100] {
+            let mut seen: Vec<u32> = (0..n).map(|i| permute(i, n, 12345)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..n).collect::<Vec<u32>>());
+        }
+    }
+
+    #[test]
+    fn test_range_and_determinism() {
+        for (mut a, mut b) in samplers(16).into_iter().zip(samplers(16)) {
+            for index in 0..16 {
+                a.start_sample(4, 9, index);
+                b.start_sample(4, 9, index);
+                for _ in 0..10 {
+                    let x = a.get_1d();
+                    let (u, v) = a.get_2d();
+                    assert!((0.0..1.0).contains(&x));
+                    assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                    assert_eq!(x, b.get_1d());
+                    assert_eq!((u, v), b.get_2d());
+                }
+            }
+        }
+    }
+
+    // the samples of one pixel put exactly one point in each of the 4x4 cells, in every 2D pair
+    #[test]
+    fn test_stratification() {
+        for kind in &[SamplerKind::Stratified, SamplerKind::Sobol] {
+            let mut sampler = kind.build(11, 16);
+            for dim in 0..4 {
+                let mut cells = [0; 16];
+                for index in 0..16 {
+                    sampler.start_sample(2, 3, index);
+                    for _ in 0..dim {
+                        sampler.get_2d();
+                    }
+                    let (u, v) = sampler.get_2d();
+                    cells[(u * 4.0) as usize + 4 * (v * 4.0) as usize] += 1;
+                }
+                assert_eq!(cells, [1; 16], "{:?} dimension {}", kind, dim);
+            }
+        }
+    }
+}
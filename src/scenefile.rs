@@ -0,0 +1,1277 @@
+//! Plain text scene description.
+//!
+//! Every non-empty line is a directive followed by `key=value` pairs, `#` starts a comment:
+//!
+//! ```text
+//! camera lookfrom=26,3,6 lookat=0,2,0 vfov=20 time0=0 time1=1
+//! background color=0,0,0
+//! atmosphere density=0.01 albedo=1,1,1
+//! texture checker checker odd=0.2,0.3,0.1 even=0.9,0.9,0.9
+//! texture earth image path=earthmap.jpg wrap=repeat filter=bilinear
+//! texture veins marble scale=4 octaves=7 strength=10 low=0.1,0.1,0.1 high=checker seed=1
+//! material ground lambertian albedo=checker
+//! material lamp light emit=4,0,4
+//! material bulb blackbody temperature=2700 luminance=8
+//! material gold conductor metal=gold roughness=0.3
+//! material brushed conductor eta=1.5,1,0.5 k=9,6,5 roughness=0.1 roughness_v=0.5
+//! material tinted dielectric ior=1.5 absorption=0.5,0.1,0.05
+//! material prism dielectric ior=1.5 cauchy=0.01
+//! material bk7 dielectric sellmeier_b=1.0396,0.2318,1.0105 sellmeier_c=0.0060,0.0200,103.56
+//! material ice rough_dielectric ior=1.31 roughness=0.2
+//! material paint principled base_color=0.8,0.1,0.1 roughness=0.3 clearcoat=1
+//! material rusty mix a=gold b=ground weight=veins
+//! material varnished coated base=ground ior=1.5 roughness=0.05
+//! material smoke isotropic albedo=0.8,0.8,0.8
+//! material fire henyey_greenstein g=0.3 albedo=0.5,0.5,0.5 emit=8,3,1
+//! sphere center=0,-1000,0 radius=1000 material=ground
+//! sphere center=0,1,0 center1=0,1.5,0 radius=0.5 material=ground
+//! xyrect x0=3 x1=5 y0=1 y1=3 k=-2 material=lamp
+//! xzrect x0=-1 x1=1 z0=-1 z1=1 k=6 material=lamp
+//! yzrect y0=0 y1=2 z0=-1 z1=1 k=4 material=ground
+//! quad q=0,0,0 u=1,0,0 v=0,1,1 material=ground
+//! box min=-1,0,-1 max=1,2,1 material=ground
+//! box min=2,0,2 max=3,1,3 density=0.5 material=smoke
+//! disk center=0,3,0 normal=0,-1,0 radius=0.5 material=lamp
+//! cylinder p0=2,0,0 p1=2,2,0 radius=0.5 material=ground
+//! cone base=-2,0,0 apex=-2,2,0 radius=0.5 material=ground
+//! torus center=0,1,2 axis=0,1,0 major=1 minor=0.25 material=ground
+//! triangle v0=0,0,0 v1=1,0,0 v2=0,1,0 material=ground
+//! volume grid=cloud.vol min=-1,0,-1 max=1,2,1 density=10 material=smoke
+//! volume grid=smoke.vol emission=heat.vol min=2,0,-1 max=4,2,1 material=fire
+//! obj path=teapot.obj
+//! obj path=teapot.obj scale=0.5,0.5,0.5 rotate=0,90,0 translate=3,0,0
+//! ```
+//!
+//! `texture <name> <kind>` and `material <name> <kind>` define named entries which can be
+//! shared by later lines. Wherever a texture is expected, a literal color `r,g,b` is accepted too.
+//! A `dielectric` absorbs `absorption` per unit of distance travelled inside it, and disperses
+//! light with a Cauchy coefficient `cauchy` (in square micrometers, keeping `ior` at 589.3nm) or
+//! the Sellmeier coefficients `sellmeier_b` and `sellmeier_c` instead of an `ior`.
+//! `conductor` takes a complex index of refraction `eta` + i `k` or a `metal` preset: gold, copper,
+//! aluminium or silver. Its `roughness` and that of `rough_dielectric` run from 0 (smooth) to 1,
+//! with `roughness_v` for a different roughness across the surface.
+//! `principled` has a `base_color` with `metallic`, `roughness`, `specular`, `transmission`,
+//! `clearcoat`, `clearcoat_roughness` and `sheen` parameters from 0 to 1, an `emission` and an
+//! `ior`. Each takes a texture, and the scalar ones a single number too.
+//! A `blackbody` light glows with the color of its `temperature` in kelvin (at least 100), at a
+//! `luminance` of 1 by default. The spectral mode uses its exact spectrum, and measured spectra of the `metal`
+//! presets.
+//! `mix` blends material `a` into material `b` by its `weight` (0.5 by default), `coated` puts a
+//! clear layer with an `ior` (1.5 by default) and a `roughness` over its `base` material.
+//! The procedural `noise`, `turbulence`, `marble`, `wood` and `worley` textures blend their
+//! `low` texture into their `high` one, black and white by default.
+//! Image textures wrap with `repeat`, `clamp` or `mirror` and filter with `nearest` or `bilinear`.
+//! Every shape takes optional `scale`, `rotate` (degrees around x, y, then z), `rotate_axis`
+//! with `rotate_angle` (degrees around the axis) and `translate` keys, applied in that order.
+//! A shape with a `density` becomes a volume of fog or smoke scattering with its material, which
+//! should be `isotropic`. `atmosphere` fills the bounds of the whole scene with such a medium.
+//! A `volume` stretches a raw voxel grid (see `medium::parse_grid`) of densities, multiplied by
+//! `density`, over a box. A `henyey_greenstein` material glows with its `emit` color wherever
+//! a volume scatters, scaled by the volume's `emission` grid when it has one.
+//! Loading the same obj file again instances its meshes instead of copying them.
+//!
+//! The camera `time0` and `time1` keys set the shutter interval, which is closed by default.
+//! Shapes move from their placement at `time0` (default 0) to the one given by `scale1`,
+//! `rotate1` and `translate1` at `time1` (default 1), and spheres to `center1`.
+//! Relative paths are resolved against the directory of the scene file.
+
+use crate::{
+    aabb::AABB,
+    aarect::{Quad, XYRect, XZRect, YZRect},
+    camera::CameraSettings,
+    hittable::{HitTable, MovingSphere, Sphere},
+    hittablelist::HitTableList,
+    material::{
+        Blackbody, Coated, Conductor, Dielectric, DiffuseLight, FrostedGlass, HenyeyGreenstein,
+        Isotropic, Lambertian, Material, Metal, MixMaterial, Principled, RoughDielectric,
+        MIN_TEMPERATURE,
+    },
+    matrix::{Mat4, Quat},
+    medium::{load_grid, ConstantMedium, Grid, GridMedium},
+    microfacet::{conductor_preset, TrowbridgeReitz},
+    noise::{Perlin, Worley},
+    obj::load_obj,
+    shapes::{Cone, Cuboid, Cylinder, Disk, Torus},
+    spectrum::{conductor_spectrum, Dispersion},
+    texture::{
+        CheckerTexture, ConstTexture, Filter, ImageTexture, MarbleTexture, NoiseTexture, Texture,
+        TurbulenceTexture, WoodTexture, WorleyTexture, Wrap,
+    },
+    transform::{AnimatedTransform, Keyframe, Transform},
+    triangle::Triangle,
+    vec3::{Color, Vec3},
+};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+pub struct Scene {
+    pub world: HitTableList,
+    pub camera: CameraSettings,
+    pub background: Color,
+    // density and albedo of a haze filling the whole scene
+    pub atmosphere: Option<(f64, Color)>,
+}
+
+#[derive(Debug)]
+pub struct SceneError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.msg)
+        } else {
+            write!(f, "line {}: {}", self.line, self.msg)
+        }
+    }
+}
+
+pub fn load_scene(path: &str) -> Result<Scene, SceneError> {
+    match fs::read_to_string(path) {
+        Ok(src) => parse_scene(
+            &src,
+            Path::new(path).parent().unwrap_or_else(|| Path::new(".")),
+        ),
+        Err(err) => Err(SceneError {
+            line: 0,
+            msg: format!("cannot read scene file '{}': {}", path, err),
+        }),
+    }
+}
+
+// the `key=value` pairs of a single line, which checks that every key is used
+struct Args<'a> {
+    line: usize,
+    pairs: Vec<(&'a str, &'a str, bool)>,
+}
+
+impl<'a> Args<'a> {
+    fn new(line: usize, words: &[&'a str]) -> Result<Self, SceneError> {
+        let mut pairs: Vec<(&'a str, &'a str, bool)> = Vec::new();
+        for word in words {
+            let pos = match word.find('=') {
+                Some(pos) => pos,
+                None => return Err(error(line, format!("expected key=value, got '{}'", word))),
+            };
+            let key = &word[..pos];
+            if pairs.iter().any(|(k, _, _)| *k == key) {
+                return Err(error(line, format!("duplicate key '{}'", key)));
+            }
+            pairs.push((key, &word[pos + 1..], false));
+        }
+        Ok(Self { line, pairs })
+    }
+
+    fn error<T>(&self, msg: String) -> Result<T, SceneError> {
+        Err(error(self.line, msg))
+    }
+
+    fn opt_str(&mut self, key: &str) -> Option<&'a str> {
+        for pair in self.pairs.iter_mut() {
+            if pair.0 == key {
+                pair.2 = true;
+                return Some(pair.1);
+            }
+        }
+        None
+    }
+
+    fn str(&mut self, key: &str) -> Result<&'a str, SceneError> {
+        match self.opt_str(key) {
+            Some(value) => Ok(value),
+            None => self.error(format!("missing '{}'", key)),
+        }
+    }
+
+    fn opt_f64(&mut self, key: &str) -> Result<Option<f64>, SceneError> {
+        match self.opt_str(key) {
+            Some(value) => match value.parse() {
+                Ok(v) => Ok(Some(v)),
+                Err(_) => self.error(format!("'{}' is not a number: '{}'", key, value)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn opt_u64(&mut self, key: &str) -> Result<Option<u64>, SceneError> {
+        match self.opt_str(key) {
+            Some(value) => match value.parse() {
+                Ok(v) => Ok(Some(v)),
+                Err(_) => self.error(format!("'{}' is not a whole number: '{}'", key, value)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn f64(&mut self, key: &str) -> Result<f64, SceneError> {
+        match self.opt_f64(key)? {
+            Some(v) => Ok(v),
+            None => self.error(format!("missing '{}'", key)),
+        }
+    }
+
+    fn opt_vec3(&mut self, key: &str) -> Result<Option<Vec3>, SceneError> {
+        match self.opt_str(key) {
+            Some(value) => match parse_vec3(value) {
+                Some(v) => Ok(Some(v)),
+                None => self.error(format!("'{}' is not a vector x,y,z: '{}'", key, value)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn vec3(&mut self, key: &str) -> Result<Vec3, SceneError> {
+        match self.opt_vec3(key)? {
+            Some(v) => Ok(v),
+            None => self.error(format!("missing '{}'", key)),
+        }
+    }
+
+    // fail on keys that no directive asked for, which are most likely typos
+    fn finish(self) -> Result<(), SceneError> {
+        for (key, _, used) in &self.pairs {
+            if !used {
+                return self.error(format!("unknown key '{}'", key));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn error(line: usize, msg: String) -> SceneError {
+    SceneError { line, msg }
+}
+
+fn parse_vec3(value: &str) -> Option<Vec3> {
+    let parts: Vec<&str> = value.split(',').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    Some(Vec3::new(
+        parts[0].parse().ok()?,
+        parts[1].parse().ok()?,
+        parts[2].parse().ok()?,
+    ))
+}
+
+// how a shape is put into the scene
+enum Placement {
+    Fixed(Mat4),
+    Moving(Keyframe, Keyframe, f64, f64),
+}
+
+// GGX roughness, squared so that it looks linear, and anisotropic with `roughness_v`
+fn distribution(args: &mut Args) -> Result<TrowbridgeReitz, SceneError> {
+    let roughness = args.opt_f64("roughness")?.unwrap_or(0.0);
+    let roughness_v = args.opt_f64("roughness_v")?.unwrap_or(roughness);
+    for r in &[roughness, roughness_v] {
+        if !(0.0..=1.0).contains(r) {
+            return args.error(String::from("roughness must be between 0 and 1"));
+        }
+    }
+    Ok(TrowbridgeReitz::new(
+        roughness * roughness,
+        roughness_v * roughness_v,
+    ))
+}
+
+// number of turbulence layers of a noise texture, 7 by default
+fn octaves(args: &mut Args) -> Result<u32, SceneError> {
+    let octaves = args.opt_f64("octaves")?.unwrap_or(7.0);
+    if !(1.0..=16.0).contains(&octaves) || octaves.fract() != 0.0 {
+        return args.error(String::from("octaves must be a whole number from 1 to 16"));
+    }
+    Ok(octaves as u32)
+}
+
+// the `lo` and `hi` keys bounding one side of a rectangle
+fn side(args: &mut Args, lo: &str, hi: &str) -> Result<(f64, f64), SceneError> {
+    let (a, b) = (args.f64(lo)?, args.f64(hi)?);
+    if b <= a {
+        return args.error(format!("'{}' must be greater than '{}'", hi, lo));
+    }
+    Ok((a, b))
+}
+
+// the optional `time0` and `time1` keys of a moving shape
+fn motion_interval(args: &mut Args) -> Result<(f64, f64), SceneError> {
+    let time0 = args.opt_f64("time0")?.unwrap_or(0.0);
+    let time1 = args.opt_f64("time1")?.unwrap_or(1.0);
+    if time1 <= time0 {
+        return args.error(String::from("'time1' must be after 'time0'"));
+    }
+    Ok((time0, time1))
+}
+
+// The optional `scale`, `rotate`, `rotate_axis` with `rotate_angle`, and `translate` keys of a
+// shape, applied in that order. `rotate` holds angles in degrees around the x, y and z axes,
+// again applied in that order. `scale1`, `rotate1` and `translate1` animate the shape towards
+// another placement.
+fn placement(args: &mut Args) -> Result<Option<Placement>, SceneError> {
+    let scale = args.opt_vec3("scale")?;
+    let euler = args.opt_vec3("rotate")?;
+    let axis = args.opt_vec3("rotate_axis")?;
+    let angle = args.opt_f64("rotate_angle")?;
+    let translate = args.opt_vec3("translate")?;
+    let scale1 = args.opt_vec3("scale1")?;
+    let euler1 = args.opt_vec3("rotate1")?;
+    let translate1 = args.opt_vec3("translate1")?;
+    if angle.is_some() && axis.is_none() {
+        return args.error(String::from("'rotate_angle' needs a 'rotate_axis'"));
+    }
+    let mut start = Keyframe::identity();
+    if let Some(factors) = scale {
+        if factors.x * factors.y * factors.z == 0.0 {
+            return args.error(String::from("scale factors must not be zero"));
+        }
+        start.scale = factors;
+    }
+    if let Some(angles) = euler {
+        start.rotation = Quat::from_euler(&angles);
+    }
+    if let Some(axis) = axis {
+        if axis.squared_length() == 0.0 {
+            return args.error(String::from("rotation axis must not be zero"));
+        }
+        let angle = match angle {
+            Some(angle) => angle,
+            None => return args.error(String::from("'rotate_axis' needs a 'rotate_angle'")),
+        };
+        start.rotation = Quat::from_axis_angle(&axis, angle) * start.rotation;
+    }
+    if let Some(offset) = translate {
+        start.translation = offset;
+    }
+    if scale1.is_none() && euler1.is_none() && translate1.is_none() {
+        if scale.is_none() && euler.is_none() && axis.is_none() && translate.is_none() {
+            return Ok(None);
+        }
+        return Ok(Some(Placement::Fixed(start.matrix())));
+    }
+    let mut end = start;
+    if let Some(factors) = scale1 {
+        // interpolating between opposite signs would flatten the shape on the way
+        if (0..3).any(|i| factors[i] * start.scale[i] <= 0.0) {
+            return args.error(String::from(
+                "'scale1' must be non-zero with the signs of 'scale'",
+            ));
+        }
+        end.scale = factors;
+    }
+    if let Some(angles) = euler1 {
+        end.rotation = Quat::from_euler(&angles);
+    }
+    if let Some(offset) = translate1 {
+        end.translation = offset;
+    }
+    let (time0, time1) = motion_interval(args)?;
+    Ok(Some(Placement::Moving(start, end, time0, time1)))
+}
+
+struct Parser {
+    dir: PathBuf,
+    meshes: HashMap<PathBuf, Vec<Arc<dyn HitTable>>>,
+    grids: HashMap<PathBuf, Arc<Grid>>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    scene: Scene,
+}
+
+impl Parser {
+    fn texture(&self, args: &mut Args, key: &str) -> Result<Arc<dyn Texture>, SceneError> {
+        let value = args.str(key)?;
+        if let Some(color) = parse_vec3(value) {
+            return Ok(Arc::new(ConstTexture { color_value: color }));
+        }
+        match self.textures.get(value) {
+            Some(texture) => Ok(texture.clone()),
+            None => args.error(format!("undefined texture '{}'", value)),
+        }
+    }
+
+    fn material(&self, args: &mut Args, key: &str) -> Result<Arc<dyn Material>, SceneError> {
+        let name = args.str(key)?;
+        match self.materials.get(name) {
+            Some(material) => Ok(material.clone()),
+            None => args.error(format!("undefined material '{}'", name)),
+        }
+    }
+
+    // the textures a procedural pattern blends default to black and white
+    fn texture_or(
+        &self,
+        args: &mut Args,
+        key: &str,
+        default: Color,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        if args.opt_str(key).is_none() {
+            return Ok(Arc::new(ConstTexture {
+                color_value: default,
+            }));
+        }
+        self.texture(args, key)
+    }
+
+    // scalar parameters take a single number as well as a texture or a color
+    fn scalar_texture(
+        &self,
+        args: &mut Args,
+        key: &str,
+        default: f64,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        if let Some(Ok(x)) = args.opt_str(key).map(|value| value.parse::<f64>()) {
+            return Ok(Arc::new(ConstTexture {
+                color_value: Color::new(x, x, x),
+            }));
+        }
+        self.texture_or(args, key, Color::new(default, default, default))
+    }
+
+    fn define_texture(&self, kind: &str, args: &mut Args) -> Result<Arc<dyn Texture>, SceneError> {
+        Ok(match kind {
+            "solid" => Arc::new(ConstTexture {
+                color_value: args.vec3("color")?,
+            }),
+            "checker" => Arc::new(CheckerTexture {
+                odd: self.texture(args, "odd")?,
+                even: self.texture(args, "even")?,
+            }),
+            "noise" | "turbulence" | "marble" | "wood" => {
+                let noise = Perlin::new(args.opt_u64("seed")?.unwrap_or(0));
+                let scale = args.opt_f64("scale")?.unwrap_or(1.0);
+                let low = self.texture_or(args, "low", Color::zero())?;
+                let high = self.texture_or(args, "high", Color::ones())?;
+                match kind {
+                    "noise" => Arc::new(NoiseTexture {
+                        noise,
+                        scale,
+                        low,
+                        high,
+                    }),
+                    "turbulence" => Arc::new(TurbulenceTexture {
+                        noise,
+                        scale,
+                        octaves: octaves(args)?,
+                        low,
+                        high,
+                    }),
+                    "marble" => Arc::new(MarbleTexture {
+                        noise,
+                        scale,
+                        octaves: octaves(args)?,
+                        strength: args.opt_f64("strength")?.unwrap_or(10.0),
+                        low,
+                        high,
+                    }),
+                    _ => Arc::new(WoodTexture {
+                        noise,
+                        scale,
+                        strength: args.opt_f64("strength")?.unwrap_or(0.5),
+                        low,
+                        high,
+                    }),
+                }
+            }
+            "worley" => {
+                let noise = Worley::new(args.opt_u64("seed")?.unwrap_or(0));
+                let scale = args.opt_f64("scale")?.unwrap_or(1.0);
+                let low = self.texture_or(args, "low", Color::zero())?;
+                let high = self.texture_or(args, "high", Color::ones())?;
+                Arc::new(WorleyTexture {
+                    noise,
+                    scale,
+                    low,
+                    high,
+                })
+            }
+            "image" => {
+                let path = self.dir.join(args.str("path")?);
+                let wrap = match args.opt_str("wrap") {
+                    None | Some("repeat") => Wrap::Repeat,
+                    Some("clamp") => Wrap::Clamp,
+                    Some("mirror") => Wrap::Mirror,
+                    Some(other) => return args.error(format!("unknown wrap mode '{}'", other)),
+                };
+                let filter = match args.opt_str("filter") {
+                    None | Some("bilinear") => Filter::Bilinear,
+                    Some("nearest") => Filter::Nearest,
+                    Some(other) => return args.error(format!("unknown filter '{}'", other)),
+                };
+                match ImageTexture::load(&path.to_string_lossy(), wrap, filter) {
+                    Ok(texture) => Arc::new(texture),
+                    Err(err) => return args.error(err),
+                }
+            }
+            _ => return args.error(format!("unknown texture kind '{}'", kind)),
+        })
+    }
+
+    fn define_material(
+        &self,
+        kind: &str,
+        args: &mut Args,
+    ) -> Result<Arc<dyn Material>, SceneError> {
+        Ok(match kind {
+            "lambertian" => Arc::new(Lambertian {
+                albedo: self.texture(args, "albedo")?,
+            }),
+            "metal" => Arc::new(Metal::new(
+                &args.vec3("albedo")?,
+                args.opt_f64("fuzz")?.unwrap_or(0.0),
+            )),
+            "dielectric" => {
+                let dispersion = match args.opt_vec3("sellmeier_b")? {
+                    Some(b) => {
+                        let c = args.vec3("sellmeier_c")?;
+                        Some(Dispersion::Sellmeier {
+                            b: [b.x, b.y, b.z],
+                            c: [c.x, c.y, c.z],
+                        })
+                    }
+                    None => match args.opt_f64("cauchy")? {
+                        Some(b) => Some(Dispersion::Cauchy {
+                            ior: args.f64("ior")?,
+                            b,
+                        }),
+                        None => None,
+                    },
+                };
+                let ref_idx = match &dispersion {
+                    Some(dispersion) => {
+                        if !dispersion.is_physical() {
+                            return args.error(String::from(
+                                "dispersion gives an invalid ior in the visible range",
+                            ));
+                        }
+                        dispersion.ior_d()
+                    }
+                    None => args.f64("ior")?,
+                };
+                if ref_idx <= 0.0 {
+                    return args.error(String::from("ior must be positive"));
+                }
+                let absorption = args.opt_vec3("absorption")?.unwrap_or_else(Color::zero);
+                if absorption.x < 0.0 || absorption.y < 0.0 || absorption.z < 0.0 {
+                    return args.error(String::from("absorption must not be negative"));
+                }
+                Arc::new(Dielectric {
+                    ref_idx,
+                    absorption,
+                    dispersion,
+                })
+            }
+            "frosted" => Arc::new(FrostedGlass::new(args.f64("ior")?, args.f64("fuzz")?)),
+            "conductor" => {
+                let (eta, k, spectrum) = match args.opt_str("metal") {
+                    Some(name) => match conductor_preset(name) {
+                        Some((eta, k)) => (eta, k, conductor_spectrum(name)),
+                        None => return args.error(format!("unknown metal '{}'", name)),
+                    },
+                    None => (args.vec3("eta")?, args.vec3("k")?, None),
+                };
+                let mut conductor = Conductor::new(eta, k, distribution(args)?);
+                conductor.spectrum = spectrum;
+                Arc::new(conductor)
+            }
+            "rough_dielectric" => {
+                Arc::new(RoughDielectric::new(args.f64("ior")?, distribution(args)?))
+            }
+            "principled" => {
+                let emission = match args.opt_str("emission") {
+                    Some(_) => Some(self.texture(args, "emission")?),
+                    None => None,
+                };
+                let ior = args.opt_f64("ior")?.unwrap_or(1.5);
+                if ior <= 0.0 {
+                    return args.error(String::from("ior must be positive"));
+                }
+                Arc::new(Principled {
+                    base_color: self.texture_or(args, "base_color", Color::new(0.8, 0.8, 0.8))?,
+                    metallic: self.scalar_texture(args, "metallic", 0.0)?,
+                    roughness: self.scalar_texture(args, "roughness", 0.5)?,
+                    specular: self.scalar_texture(args, "specular", 0.5)?,
+                    transmission: self.scalar_texture(args, "transmission", 0.0)?,
+                    clearcoat: self.scalar_texture(args, "clearcoat", 0.0)?,
+                    clearcoat_roughness: self.scalar_texture(args, "clearcoat_roughness", 0.1)?,
+                    sheen: self.scalar_texture(args, "sheen", 0.0)?,
+                    emission,
+                    ior,
+                })
+            }
+            "mix" => Arc::new(MixMaterial {
+                a: self.material(args, "a")?,
+                b: self.material(args, "b")?,
+                weight: self.scalar_texture(args, "weight", 0.5)?,
+            }),
+            "coated" => {
+                let ior = args.opt_f64("ior")?.unwrap_or(1.5);
+                if ior <= 0.0 {
+                    return args.error(String::from("ior must be positive"));
+                }
+                Arc::new(Coated::new(
+                    self.material(args, "base")?,
+                    ior,
+                    distribution(args)?,
+                ))
+            }
+            "henyey_greenstein" => {
+                let g = args.f64("g")?;
+                if g <= -1.0 || g >= 1.0 {
+                    return args.error(String::from("'g' must be between -1 and 1"));
+                }
+                Arc::new(HenyeyGreenstein::new(
+                    g,
+                    self.texture(args, "albedo")?,
+                    args.opt_vec3("emit")?.unwrap_or_else(Color::zero),
+                ))
+            }
+            "isotropic" => Arc::new(Isotropic {
+                albedo: self.texture(args, "albedo")?,
+            }),
+            "light" => Arc::new(DiffuseLight {
+                emit: self.texture(args, "emit")?,
+            }),
+            "blackbody" => {
+                let temperature = args.f64("temperature")?;
+                if temperature < MIN_TEMPERATURE {
+                    return args
+                        .error(format!("temperature must be at least {}K", MIN_TEMPERATURE));
+                }
+                let luminance = args.opt_f64("luminance")?.unwrap_or(1.0);
+                if luminance < 0.0 {
+                    return args.error(String::from("luminance must not be negative"));
+                }
+                Arc::new(Blackbody::new(temperature, luminance))
+            }
+            _ => return args.error(format!("unknown material kind '{}'", kind)),
+        })
+    }
+
+    fn place(object: Arc<dyn HitTable>, placement: &Option<Placement>) -> Arc<dyn HitTable> {
+        match placement {
+            Some(Placement::Fixed(to_world)) => Arc::new(Transform::new(object, *to_world)),
+            Some(Placement::Moving(start, end, time0, time1)) => {
+                Arc::new(AnimatedTransform::new(object, *start, *end, *time0, *time1))
+            }
+            None => object,
+        }
+    }
+
+    // grids are read once and shared by every volume using them
+    fn grid(&mut self, args: &mut Args, key: &str) -> Result<Option<Arc<Grid>>, SceneError> {
+        let path = match args.opt_str(key) {
+            Some(path) => self.dir.join(path),
+            None => return Ok(None),
+        };
+        if let Some(grid) = self.grids.get(&path) {
+            return Ok(Some(grid.clone()));
+        }
+        match load_grid(&path.to_string_lossy()) {
+            Ok(grid) => {
+                let grid = Arc::new(grid);
+                self.grids.insert(path, grid.clone());
+                Ok(Some(grid))
+            }
+            Err(err) => args.error(err),
+        }
+    }
+
+    // a shape with a `density` is filled with a medium that scatters with its material
+    fn add(&mut self, args: &mut Args, object: Arc<dyn HitTable>) -> Result<(), SceneError> {
+        let object = Self::place(object, &placement(args)?);
+        match args.opt_f64("density")? {
+            Some(density) if density <= 0.0 => args.error(String::from("density must be positive")),
+            Some(density) => {
+                let mat = self.material(args, "material")?;
+                self.scene
+                    .world
+                    .add(Arc::new(ConstantMedium::new(object, density, mat)));
+                Ok(())
+            }
+            None => {
+                self.scene.world.add(object);
+                Ok(())
+            }
+        }
+    }
+
+    fn line(&mut self, line: usize, words: &[&str]) -> Result<(), SceneError> {
+        let directive = words[0];
+        let named = directive == "texture" || directive == "material";
+        if named && words.len() < 3 {
+            return Err(error(
+                line,
+                format!("usage: {} <name> <kind> key=value...", directive),
+            ));
+        }
+        let mut args = Args::new(line, if named { &words[3..] } else { &words[1..] })?;
+        match directive {
+            "camera" => {
+                let camera = &mut self.scene.camera;
+                camera.lookfrom = args.opt_vec3("lookfrom")?.unwrap_or(camera.lookfrom);
+                camera.lookat = args.opt_vec3("lookat")?.unwrap_or(camera.lookat);
+                camera.vup = args.opt_vec3("vup")?.unwrap_or(camera.vup);
+                camera.vfov = args.opt_f64("vfov")?.unwrap_or(camera.vfov);
+                camera.aperture = args.opt_f64("aperture")?.unwrap_or(camera.aperture);
+                camera.focus_dist = args.opt_f64("focus_dist")?.unwrap_or(camera.focus_dist);
+                camera.time0 = args.opt_f64("time0")?.unwrap_or(camera.time0);
+                camera.time1 = args.opt_f64("time1")?.unwrap_or(camera.time1);
+                if camera.vfov <= 0.0 || camera.vfov >= 180.0 {
+                    return args.error(String::from("vfov must be between 0 and 180 degrees"));
+                }
+                if camera.aperture < 0.0 {
+                    return args.error(String::from("aperture must not be negative"));
+                }
+                if camera.focus_dist <= 0.0 {
+                    return args.error(String::from("focus_dist must be positive"));
+                }
+                if camera.time1 < camera.time0 {
+                    return args.error(String::from("the shutter must not close before it opens"));
+                }
+            }
+            "background" => self.scene.background = args.vec3("color")?,
+            "atmosphere" => {
+                let density = args.f64("density")?;
+                if density <= 0.0 {
+                    return args.error(String::from("density must be positive"));
+                }
+                let albedo = args.opt_vec3("albedo")?.unwrap_or_else(Color::ones);
+                self.scene.atmosphere = Some((density, albedo));
+            }
+            "texture" => {
+                if self.textures.contains_key(words[1]) {
+                    return args.error(format!("texture '{}' is already defined", words[1]));
+                }
+                let texture = self.define_texture(words[2], &mut args)?;
+                self.textures.insert(words[1].to_string(), texture);
+            }
+            "material" => {
+                if self.materials.contains_key(words[1]) {
+                    return args.error(format!("material '{}' is already defined", words[1]));
+                }
+                let material = self.define_material(words[2], &mut args)?;
+                self.materials.insert(words[1].to_string(), material);
+            }
+            "sphere" => {
+                let radius = args.f64("radius")?;
+                if radius <= 0.0 {
+                    return args.error(String::from("sphere radius must be positive"));
+                }
+                let center = args.vec3("center")?;
+                let object: Arc<dyn HitTable> = match args.opt_vec3("center1")? {
+                    Some(center1) => {
+                        let (time0, time1) = motion_interval(&mut args)?;
+                        let mat = self.material(&mut args, "material")?;
+                        Arc::new(MovingSphere::new(
+                            center, center1, time0, time1, radius, mat,
+                        ))
+                    }
+                    None => Arc::new(Sphere::new(
+                        center,
+                        radius,
+                        self.material(&mut args, "material")?,
+                    )),
+                };
+                self.add(&mut args, object)?;
+            }
+            "xyrect" => {
+                let (x0, x1) = side(&mut args, "x0", "x1")?;
+                let (y0, y1) = side(&mut args, "y0", "y1")?;
+                let object = XYRect::new(
+                    x0,
+                    x1,
+                    y0,
+                    y1,
+                    args.f64("k")?,
+                    self.material(&mut args, "material")?,
+                );
+                self.add(&mut args, Arc::new(object))?;
+            }
+            "xzrect" => {
+                let (x0, x1) = side(&mut args, "x0", "x1")?;
+                let (z0, z1) = side(&mut args, "z0", "z1")?;
+                let object = XZRect::new(
+                    x0,
+                    x1,
+                    z0,
+                    z1,
+                    args.f64("k")?,
+                    self.material(&mut args, "material")?,
+                );
+                self.add(&mut args, Arc::new(object))?;
+            }
+            "yzrect" => {
+                let (y0, y1) = side(&mut args, "y0", "y1")?;
+                let (z0, z1) = side(&mut args, "z0", "z1")?;
+                let object = YZRect::new(
+                    y0,
+                    y1,
+                    z0,
+                    z1,
+                    args.f64("k")?,
+                    self.material(&mut args, "material")?,
+                );
+                self.add(&mut args, Arc::new(object))?;
+            }
+            "quad" => {
+                let (u, v) = (args.vec3("u")?, args.vec3("v")?);
+                if u.cross(v).squared_length() == 0.0 {
+                    return args.error(String::from("quad edges must not be parallel"));
+                }
+                let object =
+                    Quad::new(args.vec3("q")?, u, v, self.material(&mut args, "material")?);
+                self.add(&mut args, Arc::new(object))?;
+            }
+            "box" => {
+                let (p0, p1) = (args.vec3("min")?, args.vec3("max")?);
+                if p0.x == p1.x || p0.y == p1.y || p0.z == p1.z {
+                    return args.error(String::from("box must not be flat"));
+                }
+                let object = Cuboid::new(p0, p1, self.material(&mut args, "material")?);
+                self.add(&mut args, Arc::new(object))?;
+            }
+            "disk" => {
+                let (normal, radius) = (args.vec3("normal")?, args.f64("radius")?);
+                if normal.squared_length() == 0.0 {
+                    return args.error(String::from("disk normal must not be zero"));
+                }
+                if radius <= 0.0 {
+                    return args.error(String::from("disk radius must be positive"));
+                }
+                let object = Disk::new(
+                    args.vec3("center")?,
+                    normal,
+                    radius,
+                    self.material(&mut args, "material")?,
+                );
+                self.add(&mut args, Arc::new(object))?;
+            }
+            "cylinder" | "cone" => {
+                let (a, b) = if directive == "cylinder" {
+                    (args.vec3("p0")?, args.vec3("p1")?)
+                } else {
+                    (args.vec3("base")?, args.vec3("apex")?)
+                };
+                let radius = args.f64("radius")?;
+                if a == b {
+                    return args.error(format!("{} must have a non-zero height", directive));
+                }
+                if radius <= 0.0 {
+                    return args.error(format!("{} radius must be positive", directive));
+                }
+                let mat = self.material(&mut args, "material")?;
+                let object: Arc<dyn HitTable> = if directive == "cylinder" {
+                    Arc::new(Cylinder::new(a, b, radius, mat))
+                } else {
+                    Arc::new(Cone::new(a, b, radius, mat))
+                };
+                self.add(&mut args, object)?;
+            }
+            "torus" => {
+                let axis = args.vec3("axis")?;
+                let (major, minor) = (args.f64("major")?, args.f64("minor")?);
+                if axis.squared_length() == 0.0 {
+                    return args.error(String::from("torus axis must not be zero"));
+                }
+                if minor <= 0.0 || major <= minor {
+                    return args.error(String::from("torus radii must satisfy 0 < minor < major"));
+                }
+                let object = Torus::new(
+                    args.vec3("center")?,
+                    axis,
+                    major,
+                    minor,
+                    self.material(&mut args, "material")?,
+                );
+                self.add(&mut args, Arc::new(object))?;
+            }
+            "triangle" => {
+                let object = Triangle::new(
+                    args.vec3("v0")?,
+                    args.vec3("v1")?,
+                    args.vec3("v2")?,
+                    self.material(&mut args, "material")?,
+                );
+                self.add(&mut args, Arc::new(object))?;
+            }
+            "volume" => {
+                let (p0, p1) = (args.vec3("min")?, args.vec3("max")?);
+                if p0.x >= p1.x || p0.y >= p1.y || p0.z >= p1.z {
+                    return args.error(String::from("volume 'max' must be above 'min'"));
+                }
+                let density = match self.grid(&mut args, "grid")? {
+                    Some(grid) => grid,
+                    None => return args.error(String::from("missing 'grid'")),
+                };
+                let scale = args.opt_f64("density")?.unwrap_or(1.0);
+                if scale <= 0.0 {
+                    return args.error(String::from("density must be positive"));
+                }
+                let emission = self.grid(&mut args, "emission")?;
+                let object = GridMedium::new(
+                    AABB::new(p0, p1),
+                    density,
+                    scale,
+                    emission,
+                    self.material(&mut args, "material")?,
+                );
+                let placement = placement(&mut args)?;
+                self.scene
+                    .world
+                    .add(Self::place(Arc::new(object), &placement));
+            }
+            "obj" => {
+                let path = self.dir.join(args.str("path")?);
+                // loading a file again only adds instances of the meshes read the first time
+                let meshes = match self.meshes.get(&path) {
+                    Some(meshes) => meshes.clone(),
+                    None => match load_obj(&path.to_string_lossy()) {
+                        Ok(meshes) => {
+                            self.meshes.insert(path, meshes.objects.clone());
+                            meshes.objects
+                        }
+                        Err(err) => return args.error(err),
+                    },
+                };
+                // every mesh carries its own BVH and enters the scene as a single object
+                let placement = placement(&mut args)?;
+                for mesh in meshes {
+                    self.scene.world.add(Self::place(mesh, &placement));
+                }
+            }
+            _ => return args.error(format!("unknown directive '{}'", directive)),
+        }
+        args.finish()
+    }
+}
+
+pub fn parse_scene(src: &str, dir: &Path) -> Result<Scene, SceneError> {
+    let mut parser = Parser {
+        dir: dir.to_path_buf(),
+        meshes: HashMap::new(),
+        grids: HashMap::new(),
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        scene: Scene {
+            world: HitTableList::new(),
+            camera: CameraSettings::default(),
+            background: Color::zero(),
+            atmosphere: None,
+        },
+    };
+    for (idx, line) in src.lines().enumerate() {
+        let content = match line.find('#') {
+            Some(pos) => &line[..pos],
+            None => line,
+        };
+        let words: Vec<&str> = content.split_whitespace().collect();
+        if !words.is_empty() {
+            parser.line(idx + 1, &words)?;
+        }
+    }
+    Ok(parser.scene)
+}
+
+#[allow(clippy::float_cmp)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(src: &str) -> String {
+        match parse_scene(src, Path::new(".")) {
+            Ok(_) => panic!("scene should not parse"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let scene = parse_scene(
+            "# a comment\n\
+             camera lookfrom=1,2,3 vfov=40 time1=0.5\n\
+             background color=0.5,0.5,0.5\n\
+             texture check checker odd=0,0,0 even=1,1,1\n\
+             material ground lambertian albedo=check\n\
+             texture marble marble scale=4 low=check high=0.9,0.9,0.9 seed=3\n\
+             texture wood wood scale=2 strength=0.3 low=0.3,0.2,0.1\n\
+             texture cells worley scale=5\n\
+             material veined lambertian albedo=marble\n\
+             material copper conductor metal=copper roughness=0.4 roughness_v=0.1\n\
+             material frost rough_dielectric ior=1.5 roughness=0.3\n\
+             material paint principled base_color=marble metallic=0.5 roughness=cells emission=0,0,1\n\
+             material prism dielectric ior=1.5 cauchy=0.004 absorption=0.1,0,0\n\
+             material bk7 dielectric sellmeier_b=1.04,0.23,1.01 sellmeier_c=0.006,0.02,103.6\n\
+             material rusty mix a=copper b=veined weight=cells\n\
+             material varnished coated base=veined roughness=0.1\n\
+             material lamp light emit=4,4,4 # trailing comment\n\
+             material bulb blackbody temperature=3000 luminance=2\n\
+             sphere center=0,-1000,0 radius=1000 material=ground\n\
+             sphere center=0,1,0 radius=1 material=ground\n\
+             xyrect x0=3 x1=5 y0=1 y1=3 k=-2 material=lamp\n\
+             xzrect x0=-1 x1=1 z0=-1 z1=1 k=6 material=lamp\n\
+             yzrect y0=0 y1=2 z0=-1 z1=1 k=4 material=ground\n\
+             quad q=0,0,0 u=1,0,0 v=0,1,1 material=ground\n\
+             box min=-1,0,-1 max=1,2,1 material=ground\n\
+             disk center=0,3,0 normal=0,-1,0 radius=0.5 material=lamp\n\
+             cylinder p0=2,0,0 p1=2,2,0 radius=0.5 material=ground\n\
+             cone base=-2,0,0 apex=-2,2,0 radius=0.5 material=ground\n\
+             torus center=0,1,2 axis=0,1,0 major=1 minor=0.25 material=ground\n\
+             box min=0,0,0 max=1,1,1 rotate=0,45,0 translate=2,0,2 material=ground\n\
+             box min=0,0,0 max=1,1,1 rotate_axis=1,1,0 rotate_angle=30 material=ground\n\
+             sphere center=0,1,0 center1=0,2,0 radius=1 material=ground\n\
+             box min=0,0,0 max=1,1,1 translate1=0,1,0 rotate1=0,90,0 time1=0.5 material=ground\n\
+             atmosphere density=0.01\n\
+             material smoke isotropic albedo=0.5,0.5,0.5\n\
+             sphere center=0,1,0 radius=1 density=0.5 material=smoke\n",
+            Path::new("."),
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 16);
+        assert_eq!(scene.world.lights().objects.len(), 3);
+        assert_eq!(scene.camera.lookfrom, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(scene.camera.vfov, 40.0);
+        assert_eq!((scene.camera.time0, scene.camera.time1), (0.0, 0.5));
+        assert_eq!(scene.background, Vec3::new(0.5, 0.5, 0.5));
+        assert_eq!(scene.atmosphere, Some((0.01, Color::ones())));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            parse_error("\nsphere center=0,0,0 radius=1 material=none"),
+            "line 2: undefined material 'none'"
+        );
+        assert_eq!(
+            parse_error("quad q=0,0,0 u=1,0,0 v=2,0,0 material=m"),
+            "line 1: quad edges must not be parallel"
+        );
+        assert_eq!(
+            parse_error(
+                "material m light emit=1,1,1\ncone base=0,0,0 apex=0,0,0 radius=1 material=m"
+            ),
+            "line 2: cone must have a non-zero height"
+        );
+        assert_eq!(
+            parse_error("material m light emit=1,1,1\ntorus center=0,0,0 axis=0,1,0 major=1 minor=2 material=m"),
+            "line 2: torus radii must satisfy 0 < minor < major"
+        );
+        assert_eq!(
+            parse_error(
+                "material m light emit=1,1,1\nbox min=0,0,0 max=1,1,1 scale=1,0,1 material=m"
+            ),
+            "line 2: scale factors must not be zero"
+        );
+        assert_eq!(
+            parse_error(
+                "material m light emit=1,1,1\nbox min=0,0,0 max=1,1,1 rotate_axis=0,1,0 material=m"
+            ),
+            "line 2: 'rotate_axis' needs a 'rotate_angle'"
+        );
+        assert_eq!(
+            parse_error("material m light emit=1,1,1\nsphere center=0,0,0 center1=1,0,0 radius=1 time0=1 material=m"),
+            "line 2: 'time1' must be after 'time0'"
+        );
+        assert_eq!(
+            parse_error(
+                "material m light emit=1,1,1\nsphere center=0,0,0 radius=1 time0=1 material=m"
+            ),
+            "line 2: unknown key 'time0'"
+        );
+        assert_eq!(
+            parse_error(
+                "material m light emit=1,1,1\nbox min=0,0,0 max=1,1,1 scale1=-1,1,1 material=m"
+            ),
+            "line 2: 'scale1' must be non-zero with the signs of 'scale'"
+        );
+        assert_eq!(
+            parse_error(
+                "material m light emit=1,1,1\nbox min=0,0,0 max=1,1,1 density=0 material=m"
+            ),
+            "line 2: density must be positive"
+        );
+        assert_eq!(
+            parse_error("texture t turbulence octaves=2.5"),
+            "line 1: octaves must be a whole number from 1 to 16"
+        );
+        assert_eq!(
+            parse_error("texture t noise low=nothing"),
+            "line 1: undefined texture 'nothing'"
+        );
+        assert_eq!(
+            parse_error("material m conductor metal=tin"),
+            "line 1: unknown metal 'tin'"
+        );
+        assert_eq!(
+            parse_error("material m rough_dielectric ior=1.5 roughness=2"),
+            "line 1: roughness must be between 0 and 1"
+        );
+        assert_eq!(
+            parse_error("material m principled metallic=shiny"),
+            "line 1: undefined texture 'shiny'"
+        );
+        assert_eq!(
+            parse_error("material m dielectric ior=1.5 absorption=-1,0,0"),
+            "line 1: absorption must not be negative"
+        );
+        assert_eq!(
+            parse_error("material m dielectric ior=1.5 cauchy=-0.5"),
+            "line 1: dispersion gives an invalid ior in the visible range"
+        );
+        assert_eq!(
+            parse_error("material m dielectric sellmeier_b=1,0,0 sellmeier_c=0.25,0,0"),
+            "line 1: dispersion gives an invalid ior in the visible range"
+        );
+        assert_eq!(
+            parse_error("material m dielectric sellmeier_b=1,0,1"),
+            "line 1: missing 'sellmeier_c'"
+        );
+        assert_eq!(
+            parse_error("material m blackbody temperature=0"),
+            "line 1: temperature must be at least 100K"
+        );
+        assert_eq!(
+            parse_error("material m blackbody temperature=20"),
+            "line 1: temperature must be at least 100K"
+        );
+        assert_eq!(
+            parse_error("material m blackbody temperature=3000 luminance=-1"),
+            "line 1: luminance must not be negative"
+        );
+        assert_eq!(
+            parse_error("material m coated base=wood"),
+            "line 1: undefined material 'wood'"
+        );
+        assert_eq!(
+            parse_error("material m lambertian albedo=1,1"),
+            "line 1: undefined texture '1,1'"
+        );
+        assert_eq!(
+            parse_error("material m metal albedo=1,1,1 fuz=0.1"),
+            "line 1: unknown key 'fuz'"
+        );
+        assert_eq!(
+            parse_error("material m light emit=1,1,1\nmaterial m light emit=1,1,1"),
+            "line 2: material 'm' is already defined"
+        );
+        assert_eq!(
+            parse_error("cube size=1"),
+            "line 1: unknown directive 'cube'"
+        );
+        assert_eq!(parse_error("background"), "line 1: missing 'color'");
+        assert_eq!(
+            parse_error("\ncamera vfov=180"),
+            "line 2: vfov must be between 0 and 180 degrees"
+        );
+        assert_eq!(
+            parse_error("camera vfov=0"),
+            "line 1: vfov must be between 0 and 180 degrees"
+        );
+        assert_eq!(
+            parse_error("camera aperture=-0.1"),
+            "line 1: aperture must not be negative"
+        );
+        assert_eq!(
+            parse_error("camera focus_dist=0"),
+            "line 1: focus_dist must be positive"
+        );
+        assert_eq!(
+            parse_error("xyrect x0=1 x1=1 y0=0 y1=1 k=0 material=m"),
+            "line 1: 'x1' must be greater than 'x0'"
+        );
+        assert_eq!(
+            parse_error("xyrect x0=0 x1=1 y0=1 y1=-1 k=0 material=m"),
+            "line 1: 'y1' must be greater than 'y0'"
+        );
+        assert_eq!(
+            parse_error("xzrect x0=2 x1=1 z0=0 z1=1 k=0 material=m"),
+            "line 1: 'x1' must be greater than 'x0'"
+        );
+        assert_eq!(
+            parse_error("xzrect x0=0 x1=1 z0=0 z1=0 k=0 material=m"),
+            "line 1: 'z1' must be greater than 'z0'"
+        );
+        assert_eq!(
+            parse_error("yzrect y0=0 y1=0 z0=0 z1=1 k=0 material=m"),
+            "line 1: 'y1' must be greater than 'y0'"
+        );
+        assert_eq!(
+            parse_error("yzrect y0=0 y1=1 z0=1 z1=-1 k=0 material=m"),
+            "line 1: 'z1' must be greater than 'z0'"
+        );
+        assert_eq!(
+            parse_error("texture t marble seed=-1"),
+            "line 1: 'seed' is not a whole number: '-1'"
+        );
+        assert_eq!(
+            parse_error("texture t worley seed=2.5"),
+            "line 1: 'seed' is not a whole number: '2.5'"
+        );
+    }
+
+    #[test]
+    fn test_volume() {
+        let dir = std::env::temp_dir().join(format!("raytracer-volume-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut bytes = Vec::new();
+        for x in &[1u32, 1, 2] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        for x in &[0.5f32, 1.0] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        fs::write(dir.join("cloud.vol"), &bytes).unwrap();
+        let scene = parse_scene(
+            "material fire henyey_greenstein g=0.3 albedo=0.5,0.5,0.5 emit=8,3,1\n\
+             volume grid=cloud.vol min=0,0,0 max=1,1,1 density=10 material=fire\n\
+             volume grid=cloud.vol emission=cloud.vol min=0,0,0 max=1,1,1 translate=2,0,0 material=fire\n",
+            &dir,
+        );
+        let err = parse_scene(
+            "material m isotropic albedo=1,1,1\nvolume grid=none.vol min=0,0,0 max=1,1,1 material=m",
+            &dir,
+        )
+        .err()
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(scene.unwrap().world.objects.len(), 2);
+        assert!(err.to_string().starts_with("line 2: "));
+        assert!(err.to_string().contains("none.vol"));
+        assert_eq!(
+            parse_error("material m henyey_greenstein g=1 albedo=1,1,1"),
+            "line 1: 'g' must be between -1 and 1"
+        );
+        assert_eq!(
+            parse_error(
+                "material m isotropic albedo=1,1,1\nvolume grid=a.vol min=0,0,0 max=1,0,1 material=m"
+            ),
+            "line 2: volume 'max' must be above 'min'"
+        );
+    }
+
+    #[test]
+    fn test_image_texture() {
+        let dir = std::env::temp_dir().join(format!("raytracer-image-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        image::RgbImage::new(4, 2)
+            .save(dir.join("black.png"))
+            .unwrap();
+        let scene = parse_scene(
+            "texture t image path=black.png wrap=mirror filter=nearest\n\
+             material m lambertian albedo=t\n\
+             sphere center=0,0,0 radius=1 material=m\n",
+            &dir,
+        );
+        let err = parse_scene("texture t image path=black.png wrap=tile", &dir)
+            .err()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(scene.unwrap().world.objects.len(), 1);
+        assert_eq!(err.to_string(), "line 1: unknown wrap mode 'tile'");
+    }
+}
@@ -0,0 +1,720 @@
+use crate::{
+    aabb::AABB,
+    aarect::Quad,
+    hittable::{HitRecord, HitTable},
+    hittablelist::HitTableList,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    rtweekend::{clamp, fmax, fmin},
+    sampler::Sampler,
+    triangle::closest_point_on_triangle,
+    vec3::{sample_in_unit_disk, Point3, Vec3},
+};
+use std::{f64::consts::PI, sync::Arc};
+
+// Axis-aligned box made of six quads, named so that it does not shadow `std::boxed::Box`.
+pub struct Cuboid {
+    pub box_min: Point3,
+    pub box_max: Point3,
+    sides: HitTableList,
+}
+
+impl Cuboid {
+    // `p0` and `p1` are opposite corners, the box must not be flat
+    pub fn new(p0: Point3, p1: Point3, mat: Arc<dyn Material>) -> Self {
+        let a = Point3::new(fmin(p0.x, p1.x), fmin(p0.y, p1.y), fmin(p0.z, p1.z));
+        let b = Point3::new(fmax(p0.x, p1.x), fmax(p0.y, p1.y), fmax(p0.z, p1.z));
+        let dx = Vec3::new(b.x - a.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, b.y - a.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, b.z - a.z);
+        let mut sides = HitTableList::new();
+        let quads = [
+            (Point3::new(a.x, a.y, b.z), dx, dy),
+            (Point3::new(b.x, a.y, b.z), -dz, dy),
+            (Point3::new(b.x, a.y, a.z), -dx, dy),
+            (Point3::new(a.x, a.y, a.z), dz, dy),
+            (Point3::new(a.x, b.y, b.z), dx, -dz),
+            (Point3::new(a.x, a.y, a.z), dx, dz),
+        ];
+        for (q, u, v) in quads.iter() {
+            sides.add(Arc::new(Quad::new(*q, *u, *v, mat.clone())));
+        }
+        Self {
+            box_min: a,
+            box_max: b,
+            sides,
+        }
+    }
+}
+
+impl HitTable for Cuboid {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.sides.hit(r, t_min, t_max, rec)
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        *output_box = padded_box(self.box_min, self.box_max);
+        true
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        let closest = Point3::new(
+            clamp(other_center.x, self.box_min.x, self.box_max.x),
+            clamp(other_center.y, self.box_min.y, self.box_max.y),
+            clamp(other_center.z, self.box_min.z, self.box_max.z),
+        );
+        other_center.distance(closest)
+    }
+}
+
+// Placement of a shape defined around the local z axis. The basis is orthonormal, so ray
+// parameters are the same in both spaces.
+#[derive(Clone, Copy, Debug)]
+struct Frame {
+    origin: Point3,
+    onb: Onb,
+}
+
+impl Frame {
+    fn new(origin: Point3, axis: &Vec3) -> Self {
+        Self {
+            origin,
+            onb: Onb::build_from_w(axis),
+        }
+    }
+
+    fn point_to_local(&self, p: &Point3) -> Point3 {
+        self.onb.project(&(*p - self.origin))
+    }
+
+    fn ray_to_local(&self, r: &Ray) -> Ray {
+        Ray {
+            orig: self.point_to_local(&r.orig),
+            dir: self.onb.project(&r.dir),
+            time: r.time,
+            wavelength: r.wavelength,
+        }
+    }
+}
+
+// intersection found in the local space of a shape
+struct LocalHit {
+    t: f64,
+    // outward and unit length
+    normal: Vec3,
+    u: f64,
+    v: f64,
+}
+
+fn set_local_hit(
+    rec: &mut HitRecord,
+    r: &Ray,
+    frame: &Frame,
+    hit: &LocalHit,
+    mat: &Arc<dyn Material>,
+) {
+    rec.t = hit.t;
+    rec.p = r.at(hit.t);
+    rec.u = hit.u;
+    rec.v = hit.v;
+    rec.set_face_normal(r, &frame.onb.local(&hit.normal));
+    rec.mat_ptr = mat.clone();
+}
+
+fn closer(a: Option<LocalHit>, b: Option<LocalHit>) -> Option<LocalHit> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if b.t < a.t { b } else { a }),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+// half extents of a circle of `radius` around the unit vector `axis`, along the world axes
+fn circle_extent(axis: &Vec3, radius: f64) -> Vec3 {
+    Vec3::new(
+        radius * (1.0 - axis.x * axis.x).max(0.0).sqrt(),
+        radius * (1.0 - axis.y * axis.y).max(0.0).sqrt(),
+        radius * (1.0 - axis.z * axis.z).max(0.0).sqrt(),
+    )
+}
+
+// angle around the local z axis, mapped to [0, 1)
+fn azimuth(p: &Vec3) -> f64 {
+    let phi = p.y.atan2(p.x);
+    if phi < 0.0 {
+        (phi + 2.0 * PI) / (2.0 * PI)
+    } else {
+        phi / (2.0 * PI)
+    }
+}
+
+// smallest root of a t^2 + b t + c in (t_min, t_max) for which `accept` holds
+fn quadratic_hit<F: Fn(f64) -> bool>(
+    a: f64,
+    b: f64,
+    c: f64,
+    t_min: f64,
+    t_max: f64,
+    accept: F,
+) -> Option<f64> {
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 || a == 0.0 {
+        return None;
+    }
+    // avoids the cancellation of -b + sqrt(discriminant)
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = (q / a, c / q);
+    let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+    [t0, t1]
+        .iter()
+        .cloned()
+        .find(|&t| t > t_min && t < t_max && accept(t))
+}
+
+// where a local ray crosses the disk of `radius` around the z axis at height `z`
+fn cap_hit(r: &Ray, z: f64, radius: f64, t_min: f64, t_max: f64) -> Option<(f64, Point3)> {
+    let t = (z - r.orig.z) / r.dir.z;
+    if t.is_nan() || t <= t_min || t >= t_max {
+        return None;
+    }
+    let p = r.at(t);
+    if p.x * p.x + p.y * p.y > radius * radius {
+        return None;
+    }
+    Some((t, p))
+}
+
+fn cap_local_hit(
+    r: &Ray,
+    z: f64,
+    radius: f64,
+    normal_z: f64,
+    t_min: f64,
+    t_max: f64,
+) -> Option<LocalHit> {
+    cap_hit(r, z, radius, t_min, t_max).map(|(t, p)| LocalHit {
+        t,
+        normal: Vec3::new(0.0, 0.0, normal_z),
+        u: azimuth(&p),
+        v: (p.x * p.x + p.y * p.y).sqrt() / radius,
+    })
+}
+
+fn padded_box(min: Point3, max: Point3) -> AABB {
+    let pad = Vec3::new(0.0001, 0.0001, 0.0001);
+    AABB::new(min - pad, max + pad)
+}
+
+// Flat disk facing along `normal`.
+pub struct Disk {
+    pub center: Point3,
+    pub normal: Vec3,
+    pub radius: f64,
+    pub mat_ptr: Arc<dyn Material>,
+    frame: Frame,
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: Vec3, radius: f64, m: Arc<dyn Material>) -> Self {
+        let normal = normal.unit();
+        Self {
+            center,
+            normal,
+            radius,
+            mat_ptr: m,
+            frame: Frame::new(center, &normal),
+        }
+    }
+}
+
+impl HitTable for Disk {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let local = self.frame.ray_to_local(r);
+        match cap_local_hit(&local, 0.0, self.radius, 1.0, t_min, t_max) {
+            Some(hit) => {
+                set_local_hit(rec, r, &self.frame, &hit, &self.mat_ptr);
+                true
+            }
+            None => false,
+        }
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        let e = circle_extent(&self.normal, self.radius);
+        *output_box = padded_box(self.center - e, self.center + e);
+        true
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        let p = self.frame.point_to_local(other_center);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        let dr = (rho - self.radius).max(0.0);
+        (dr * dr + p.z * p.z).sqrt()
+    }
+    fn is_light(&self) -> bool {
+        self.mat_ptr.is_emissive()
+    }
+    // uniform over the area, converted to solid angle from `o`
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        let local = self.frame.ray_to_local(&Ray {
+            orig: *o,
+            dir: *v,
+            time: 0.0,
+            wavelength: None,
+        });
+        let t = match cap_hit(&local, 0.0, self.radius, 0.001, f64::INFINITY) {
+            Some((t, _)) => t,
+            None => return 0.0,
+        };
+        let area = PI * self.radius * self.radius;
+        let distance_squared = t * t * v.squared_length();
+        let cosine = (*v * self.normal).abs() / v.length();
+        distance_squared / (cosine * area)
+    }
+    fn random(&self, o: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let d = sample_in_unit_disk(sampler.get_2d()) * self.radius;
+        self.center + self.frame.onb.local(&d) - *o
+    }
+}
+
+// Capped cylinder with axis from `p0` to `p1`.
+pub struct Cylinder {
+    pub p0: Point3,
+    pub p1: Point3,
+    pub radius: f64,
+    pub mat_ptr: Arc<dyn Material>,
+    frame: Frame,
+    height: f64,
+}
+
+impl Cylinder {
+    pub fn new(p0: Point3, p1: Point3, radius: f64, m: Arc<dyn Material>) -> Self {
+        let axis = p1 - p0;
+        Self {
+            p0,
+            p1,
+            radius,
+            mat_ptr: m,
+            frame: Frame::new(p0, &axis),
+            height: axis.length(),
+        }
+    }
+}
+
+impl HitTable for Cylinder {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let l = self.frame.ray_to_local(r);
+        let (o, d) = (l.orig, l.dir);
+        let h = self.height;
+        let side = quadratic_hit(
+            d.x * d.x + d.y * d.y,
+            2.0 * (o.x * d.x + o.y * d.y),
+            o.x * o.x + o.y * o.y - self.radius * self.radius,
+            t_min,
+            t_max,
+            |t| (0.0..=h).contains(&(o.z + t * d.z)),
+        )
+        .map(|t| {
+            let p = l.at(t);
+            LocalHit {
+                t,
+                normal: Vec3::new(p.x / self.radius, p.y / self.radius, 0.0),
+                u: azimuth(&p),
+                v: p.z / h,
+            }
+        });
+        let bottom = cap_local_hit(&l, 0.0, self.radius, -1.0, t_min, t_max);
+        let top = cap_local_hit(&l, h, self.radius, 1.0, t_min, t_max);
+        match closer(side, closer(bottom, top)) {
+            Some(hit) => {
+                set_local_hit(rec, r, &self.frame, &hit, &self.mat_ptr);
+                true
+            }
+            None => false,
+        }
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        let e = circle_extent(&self.frame.onb.w, self.radius);
+        let (p0, p1) = (self.p0, self.p1);
+        *output_box = padded_box(
+            Point3::new(fmin(p0.x, p1.x), fmin(p0.y, p1.y), fmin(p0.z, p1.z)) - e,
+            Point3::new(fmax(p0.x, p1.x), fmax(p0.y, p1.y), fmax(p0.z, p1.z)) + e,
+        );
+        true
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        let p = self.frame.point_to_local(other_center);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        let dr = (rho - self.radius).max(0.0);
+        let dz = (-p.z).max(p.z - self.height).max(0.0);
+        (dr * dr + dz * dz).sqrt()
+    }
+}
+
+// Cone from a capped circular `base` to a point `apex`.
+pub struct Cone {
+    pub base: Point3,
+    pub apex: Point3,
+    pub radius: f64,
+    pub mat_ptr: Arc<dyn Material>,
+    frame: Frame,
+    height: f64,
+}
+
+impl Cone {
+    pub fn new(base: Point3, apex: Point3, radius: f64, m: Arc<dyn Material>) -> Self {
+        let axis = apex - base;
+        Self {
+            base,
+            apex,
+            radius,
+            mat_ptr: m,
+            frame: Frame::new(base, &axis),
+            height: axis.length(),
+        }
+    }
+}
+
+impl HitTable for Cone {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let l = self.frame.ray_to_local(r);
+        let (o, d) = (l.orig, l.dir);
+        let h = self.height;
+        // the side is x^2 + y^2 = (k (h - z))^2
+        let k = self.radius / h;
+        let k2 = k * k;
+        let side = quadratic_hit(
+            d.x * d.x + d.y * d.y - k2 * d.z * d.z,
+            2.0 * (o.x * d.x + o.y * d.y + k2 * (h - o.z) * d.z),
+            o.x * o.x + o.y * o.y - k2 * (h - o.z) * (h - o.z),
+            t_min,
+            t_max,
+            |t| (0.0..=h).contains(&(o.z + t * d.z)),
+        )
+        .map(|t| {
+            let p = l.at(t);
+            let rho = (p.x * p.x + p.y * p.y).sqrt();
+            LocalHit {
+                t,
+                // the apex has no normal of its own
+                normal: if rho > 0.0 {
+                    Vec3::new(p.x, p.y, k * rho).unit()
+                } else {
+                    Vec3::new(0.0, 0.0, 1.0)
+                },
+                u: azimuth(&p),
+                v: p.z / h,
+            }
+        });
+        let bottom = cap_local_hit(&l, 0.0, self.radius, -1.0, t_min, t_max);
+        match closer(side, bottom) {
+            Some(hit) => {
+                set_local_hit(rec, r, &self.frame, &hit, &self.mat_ptr);
+                true
+            }
+            None => false,
+        }
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        let e = circle_extent(&self.frame.onb.w, self.radius);
+        let (b, a) = (self.base, self.apex);
+        *output_box = padded_box(
+            Point3::new(
+                fmin(b.x - e.x, a.x),
+                fmin(b.y - e.y, a.y),
+                fmin(b.z - e.z, a.z),
+            ),
+            Point3::new(
+                fmax(b.x + e.x, a.x),
+                fmax(b.y + e.y, a.y),
+                fmax(b.z + e.z, a.z),
+            ),
+        );
+        true
+    }
+    // the cone is symmetric around its axis, so work in the (rho, z) half plane where it is a
+    // triangle
+    fn distance(&self, other_center: &Point3) -> f64 {
+        let p = self.frame.point_to_local(other_center);
+        let q = Point3::new((p.x * p.x + p.y * p.y).sqrt(), p.z, 0.0);
+        let closest = closest_point_on_triangle(
+            &q,
+            &Point3::zero(),
+            &Point3::new(self.radius, 0.0, 0.0),
+            &Point3::new(0.0, self.height, 0.0),
+        );
+        q.distance(closest)
+    }
+}
+
+fn poly_eval(c: &[f64], t: f64) -> f64 {
+    c.iter().rev().fold(0.0, |acc, &ci| acc * t + ci)
+}
+
+// root of a polynomial that is monotonic on [a, b], if it changes sign there
+fn bisect(c: &[f64], mut a: f64, mut b: f64) -> Option<f64> {
+    let mut fa = poly_eval(c, a);
+    let fb = poly_eval(c, b);
+    if fa == 0.0 {
+        return Some(a);
+    }
+    if fb == 0.0 {
+        return Some(b);
+    }
+    if (fa < 0.0) == (fb < 0.0) {
+        return None;
+    }
+    for _ in 0..100 {
+        let m = 0.5 * (a + b);
+        if m <= a || m >= b {
+            break;
+        }
+        let fm = poly_eval(c, m);
+        if (fm < 0.0) == (fa < 0.0) {
+            a = m;
+            fa = fm;
+        } else {
+            b = m;
+        }
+    }
+    Some(0.5 * (a + b))
+}
+
+// Real roots in [lo, hi] of the polynomial sum(c[i] t^i) of degree 1 to 4, in ascending order.
+// The interval is split at the roots of the derivative so that every piece is monotonic, which
+// is robust but can miss roots where the polynomial only touches zero.
+fn poly_roots(c: &[f64], lo: f64, hi: f64, roots: &mut [f64; 4]) -> usize {
+    let degree = c.len() - 1;
+    if degree == 1 {
+        let t = -c[0] / c[1];
+        if t >= lo && t <= hi {
+            roots[0] = t;
+            return 1;
+        }
+        return 0;
+    }
+    let mut derivative = [0.0; 4];
+    for i in 1..=degree {
+        derivative[i - 1] = c[i] * i as f64;
+    }
+    let mut critical = [0.0; 4];
+    let n_critical = poly_roots(&derivative[..degree], lo, hi, &mut critical);
+    let mut count = 0;
+    let mut a = lo;
+    for &b in critical[..n_critical].iter().chain(std::iter::once(&hi)) {
+        if let Some(t) = bisect(c, a, b) {
+            if count == 0 || t > roots[count - 1] {
+                roots[count] = t;
+                count += 1;
+            }
+        }
+        a = b;
+    }
+    count
+}
+
+// Ring around `axis` through `center`, with the tube of radius `minor_radius` following a
+// circle of radius `major_radius`.
+pub struct Torus {
+    pub center: Point3,
+    pub axis: Vec3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub mat_ptr: Arc<dyn Material>,
+    frame: Frame,
+}
+
+impl Torus {
+    pub fn new(
+        center: Point3,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        m: Arc<dyn Material>,
+    ) -> Self {
+        let axis = axis.unit();
+        Self {
+            center,
+            axis,
+            major_radius,
+            minor_radius,
+            mat_ptr: m,
+            frame: Frame::new(center, &axis),
+        }
+    }
+}
+
+impl HitTable for Torus {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let l = self.frame.ray_to_local(r);
+        let (o, d) = (l.orig, l.dir);
+        let (big_r, small_r) = (self.major_radius, self.minor_radius);
+        // only search where the ray is inside the bounding sphere
+        let bound = big_r + small_r;
+        let (dd, od, oo) = (d * d, o * d, o * o);
+        let discriminant = od * od - dd * (oo - bound * bound);
+        if discriminant < 0.0 {
+            return false;
+        }
+        let root = discriminant.sqrt();
+        let lo = fmax(t_min, (-od - root) / dd);
+        let hi = fmin(t_max, (-od + root) / dd);
+        if lo >= hi {
+            return false;
+        }
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2) along the ray
+        let k = oo + big_r * big_r - small_r * small_r;
+        let four_r2 = 4.0 * big_r * big_r;
+        let c = [
+            k * k - four_r2 * (o.x * o.x + o.y * o.y),
+            4.0 * od * k - 2.0 * four_r2 * (o.x * d.x + o.y * d.y),
+            4.0 * od * od + 2.0 * dd * k - four_r2 * (d.x * d.x + d.y * d.y),
+            4.0 * dd * od,
+            dd * dd,
+        ];
+        let mut roots = [0.0; 4];
+        let count = poly_roots(&c, lo, hi, &mut roots);
+        let t = match roots[..count].iter().find(|&&t| t > t_min) {
+            Some(&t) => t,
+            None => return false,
+        };
+        let p = l.at(t);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        // away from the closest point on the circle at the heart of the tube
+        let ring = Vec3::new(p.x, p.y, 0.0) * (big_r / rho);
+        let tube = (p.z).atan2(rho - big_r);
+        let hit = LocalHit {
+            t,
+            normal: (p - ring).unit(),
+            u: azimuth(&p),
+            v: (tube + PI) / (2.0 * PI),
+        };
+        set_local_hit(rec, r, &self.frame, &hit, &self.mat_ptr);
+        true
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        let r = self.minor_radius;
+        let e = circle_extent(&self.axis, self.major_radius) + Vec3::new(r, r, r);
+        *output_box = padded_box(self.center - e, self.center + e);
+        true
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        let p = self.frame.point_to_local(other_center);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        let dr = rho - self.major_radius;
+        ((dr * dr + p.z * p.z).sqrt() - self.minor_radius).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Lambertian,
+        rtweekend::Rng,
+        vec3::{sample_unit_vector, Color},
+    };
+
+    fn mat() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::ones()))
+    }
+
+    fn first_hit(object: &dyn HitTable, orig: Point3, dir: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new(mat());
+        if object.hit(
+            &Ray {
+                orig,
+                dir,
+                time: 0.0,
+                wavelength: None,
+            },
+            0.001,
+            f64::INFINITY,
+            &mut rec,
+        ) {
+            Some(rec)
+        } else {
+            None
+        }
+    }
+
+    fn hit_t(object: &dyn HitTable, orig: Point3, dir: Vec3) -> f64 {
+        first_hit(object, orig, dir).expect("ray should hit").t
+    }
+
+    #[test]
+    fn test_known_hits() {
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let from_left = Point3::new(-5.0, 1.0, 0.0);
+        let from_top = Point3::new(0.0, 5.0, 0.0);
+        let cuboid = Cuboid::new(
+            Point3::new(1.0, 0.0, -1.0),
+            Point3::new(-1.0, 2.0, 1.0),
+            mat(),
+        );
+        assert!((hit_t(&cuboid, from_left, x) - 4.0).abs() < 1e-9);
+        assert!((hit_t(&cuboid, from_top, -y) - 3.0).abs() < 1e-9);
+        let cylinder = Cylinder::new(Point3::zero(), Point3::new(0.0, 2.0, 0.0), 1.0, mat());
+        assert!((hit_t(&cylinder, from_left, x) - 4.0).abs() < 1e-9);
+        assert!((hit_t(&cylinder, from_top, -y) - 3.0).abs() < 1e-9);
+        assert!(first_hit(&cylinder, Point3::new(-5.0, 3.0, 0.0), x).is_none());
+        let cone = Cone::new(Point3::zero(), Point3::new(0.0, 2.0, 0.0), 1.0, mat());
+        // half way up the radius is 0.5
+        assert!((hit_t(&cone, from_left, x) - 4.5).abs() < 1e-9);
+        assert!((hit_t(&cone, from_top, -y) - 3.0).abs() < 1e-9);
+        assert!((hit_t(&cone, Point3::new(0.0, -5.0, 0.0), y) - 5.0).abs() < 1e-9);
+        let disk = Disk::new(Point3::new(0.0, 1.0, 0.0), y, 1.0, mat());
+        assert!((hit_t(&disk, from_top, -y) - 4.0).abs() < 1e-9);
+        assert!(first_hit(&disk, Point3::new(1.5, 5.0, 0.0), -y).is_none());
+        let torus = Torus::new(Point3::new(0.0, 1.0, 0.0), y, 2.0, 0.5, mat());
+        assert!((hit_t(&torus, from_left, x) - 2.5).abs() < 1e-9);
+        assert!((hit_t(&torus, Point3::new(0.0, 1.0, 0.0), x) - 1.5).abs() < 1e-9);
+        assert!((hit_t(&torus, Point3::new(2.0, 5.0, 0.0), -y) - 3.5).abs() < 1e-9);
+        assert!(first_hit(&torus, from_top, -y).is_none());
+    }
+
+    // every hit lies on the surface, inside the bounding box, with a unit normal facing the ray
+    #[test]
+    fn test_random_rays() {
+        let axis = Vec3::new(0.3, 0.8, -0.5);
+        let c = Point3::new(0.5, -0.2, 0.1);
+        let shapes: Vec<Box<dyn HitTable>> = vec![
+            Box::new(Cuboid::new(c, c + Vec3::new(1.0, 2.0, 0.5), mat())),
+            Box::new(Disk::new(c, axis, 1.5, mat())),
+            Box::new(Cylinder::new(c, c + axis * 2.0, 0.7, mat())),
+            Box::new(Cone::new(c, c + axis * 2.0, 0.7, mat())),
+            Box::new(Torus::new(c, axis, 1.5, 0.4, mat())),
+        ];
+        let mut rng = Rng::new(17);
+        for shape in &shapes {
+            let mut bbox = AABB::new(Point3::zero(), Point3::zero());
+            assert!(shape.bounding_box(0.0, 1.0, &mut bbox));
+            let mut hits = 0;
+            for _ in 0..500 {
+                let u = (rng.random_double(0.0, 1.0), rng.random_double(0.0, 1.0));
+                let orig = c + sample_unit_vector(u) * 6.0;
+                let target = c + Vec3::new(
+                    rng.random_double(-1.5, 1.5),
+                    rng.random_double(-1.5, 1.5),
+                    rng.random_double(-1.5, 1.5),
+                );
+                let dir = target - orig;
+                if let Some(rec) = first_hit(shape.as_ref(), orig, dir) {
+                    hits += 1;
+                    assert!(shape.distance(&rec.p) < 1e-6);
+                    assert!((rec.normal.length() - 1.0).abs() < 1e-9);
+                    assert!(rec.normal * dir <= 0.0);
+                    for i in 0..3 {
+                        assert!(
+                            rec.p[i] >= bbox._min[i] && rec.p[i] <= bbox._max[i],
+                            "{:?} {:?} {:?}",
+                            rec.p,
+                            bbox._min,
+                            bbox._max
+                        );
+                    }
+                    assert!((0.0..=1.0).contains(&rec.u) && (0.0..=1.0).contains(&rec.v));
+                }
+            }
+            assert!(hits > 50);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use crate::vec3::{Color, Vec3};
+
+// visible wavelengths in nanometers
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+// the sodium D line, where an index of refraction is usually quoted
+const LAMBDA_D: f64 = 589.3;
+
+// reciprocal of the mean of the clipped sRGB of the visible wavelengths, per channel
+const RGB_NORM: (f64, f64, f64) = (1.929_846, 2.946_889, 3.110_814);
+
+// the same without clipping, which balances the film so that a flat spectrum is white
+const FILM_NORM: (f64, f64, f64) = (2.648_785, 3.348_498, 3.503_315);
+
+// piecewise Gaussian with different widths on each side of the mean
+fn lobe(lambda: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let sigma = if lambda < mu { sigma_low } else { sigma_high };
+    let t = (lambda - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 color matching functions (Wyman, Sloan and Shirley 2013, "Simple Analytic
+// Approximations to the CIE XYZ Color Matching Functions")
+pub fn cie_xyz(lambda: f64) -> Color {
+    Color::new(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+// linear sRGB of a CIE XYZ color
+pub fn xyz_to_rgb(xyz: &Color) -> Color {
+    Color::new(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    )
+}
+
+// uniform over the visible range
+pub fn sample_wavelength(u: f64) -> f64 {
+    LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+// weight turning light of RGB color c into light of a single `sample_wavelength`, so that c times
+// the weight averages to c over all wavelengths. Colors outside sRGB are clipped.
+pub fn wavelength_to_rgb(lambda: f64) -> Color {
+    let rgb = xyz_to_rgb(&cie_xyz(lambda));
+    Color::new(
+        rgb.x.max(0.0) * RGB_NORM.0,
+        rgb.y.max(0.0) * RGB_NORM.1,
+        rgb.z.max(0.0) * RGB_NORM.2,
+    )
+}
+
+// The spectral mode traces a hero wavelength together with the two that divide the visible range
+// into thirds with it, one per channel of a `Color`.
+pub fn hero_wavelengths(hero: f64) -> Vec3 {
+    let range = LAMBDA_MAX - LAMBDA_MIN;
+    let rotate = |k: f64| LAMBDA_MIN + (hero - LAMBDA_MIN + k * range / 3.0) % range;
+    Vec3::new(hero, rotate(1.0), rotate(2.0))
+}
+
+// smooth red, green and blue bumps which add up to one at every wavelength, with edges placed
+// so that upsampling a color and viewing it on the film gives back about the same color
+fn basis(lambda: f64) -> Color {
+    let sigmoid = |x: f64| 1.0 / (1.0 + (-x).exp());
+    let red = sigmoid((lambda - 590.0) / 8.0);
+    let blue = 1.0 - sigmoid((lambda - 485.0) / 8.0);
+    Color::new(red, 1.0 - red - blue, blue)
+}
+
+// a smooth spectrum for the RGB color `c`, at the wavelengths `lambda`. It is linear in `c`, keeps
+// white flat and never leaves the range of the channels, so albedos stay below one.
+pub fn rgb_to_spectrum(c: &Color, lambda: &Vec3) -> Vec3 {
+    Vec3::new(
+        *c * basis(lambda.x),
+        *c * basis(lambda.y),
+        *c * basis(lambda.z),
+    )
+}
+
+// estimate of the CIE XYZ color of a spectrum from its `values` at the uniformly sampled
+// wavelengths `lambda`
+pub fn spectrum_to_xyz(values: &Vec3, lambda: &Vec3) -> Color {
+    (cie_xyz(lambda.x) * values.x + cie_xyz(lambda.y) * values.y + cie_xyz(lambda.z) * values.z)
+        / 3.0
+}
+
+// linear sRGB the film records for `xyz` from `spectrum_to_xyz`
+pub fn xyz_to_film(xyz: &Color) -> Color {
+    let rgb = xyz_to_rgb(xyz);
+    Color::new(
+        rgb.x * FILM_NORM.0,
+        rgb.y * FILM_NORM.1,
+        rgb.z * FILM_NORM.2,
+    )
+}
+
+// spectral radiance of a black body at `temperature` kelvin, at `lambda` nanometers
+pub fn planck(lambda: f64, temperature: f64) -> f64 {
+    const C: f64 = 299_792_458.0;
+    const H: f64 = 6.626_070_15e-34;
+    const KB: f64 = 1.380_649e-23;
+    let l = lambda * 1e-9;
+    2.0 * H * C * C / (l.powi(5) * ((H * C / (l * KB * temperature)).exp() - 1.0))
+}
+
+// film color of a spectrum, integrated over the visible range in 1nm steps
+pub fn film_color<F: Fn(f64) -> f64>(spectrum: F) -> Color {
+    let n = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+    let mut xyz = Color::zero();
+    for i in 0..n {
+        let lambda = LAMBDA_MIN + i as f64 + 0.5;
+        xyz += cie_xyz(lambda) * (spectrum(lambda) / n as f64);
+    }
+    xyz_to_film(&xyz)
+}
+
+// approximate complex indices of refraction (wavelength, eta, k) of the `conductor_preset`
+// metals, after Johnson and Christy (1972) and Rakic (1995)
+pub fn conductor_spectrum(name: &str) -> Option<&'static [(f64, f64, f64)]> {
+    const GOLD: [(f64, f64, f64); 7] = [
+        (400.0, 1.66, 1.96),
+        (450.0, 1.50, 1.88),
+        (500.0, 0.97, 1.87),
+        (550.0, 0.43, 2.46),
+        (600.0, 0.22, 3.05),
+        (650.0, 0.17, 3.25),
+        (700.0, 0.13, 3.84),
+    ];
+    const COPPER: [(f64, f64, f64); 7] = [
+        (400.0, 1.18, 2.21),
+        (450.0, 1.24, 2.40),
+        (500.0, 1.13, 2.56),
+        (550.0, 1.02, 2.58),
+        (600.0, 0.27, 3.41),
+        (650.0, 0.21, 3.67),
+        (700.0, 0.21, 4.21),
+    ];
+    const ALUMINIUM: [(f64, f64, f64); 7] = [
+        (400.0, 0.49, 4.86),
+        (450.0, 0.62, 5.47),
+        (500.0, 0.77, 6.08),
+        (550.0, 0.96, 6.69),
+        (600.0, 1.20, 7.26),
+        (650.0, 1.47, 7.79),
+        (700.0, 1.83, 8.31),
+    ];
+    const SILVER: [(f64, f64, f64); 7] = [
+        (400.0, 0.05, 2.10),
+        (450.0, 0.04, 2.65),
+        (500.0, 0.05, 3.09),
+        (550.0, 0.06, 3.59),
+        (600.0, 0.06, 4.01),
+        (650.0, 0.05, 4.42),
+        (700.0, 0.04, 4.84),
+    ];
+    match name {
+        "gold" => Some(&GOLD),
+        "copper" => Some(&COPPER),
+        "aluminium" => Some(&ALUMINIUM),
+        "silver" => Some(&SILVER),
+        _ => None,
+    }
+}
+
+// (eta, k) at `lambda`, linear between the entries of `table` and constant beyond them
+pub fn interpolate_ior(table: &[(f64, f64, f64)], lambda: f64) -> (f64, f64) {
+    let first = table[0];
+    if lambda <= first.0 {
+        return (first.1, first.2);
+    }
+    for w in table.windows(2) {
+        let (a, b) = (w[0], w[1]);
+        if lambda <= b.0 {
+            let t = (lambda - a.0) / (b.0 - a.0);
+            return (a.1 + t * (b.1 - a.1), a.2 + t * (b.2 - a.2));
+        }
+    }
+    let last = table[table.len() - 1];
+    (last.1, last.2)
+}
+
+// how the index of refraction of a material changes with wavelength
+#[derive(Clone, Copy, Debug)]
+pub enum Dispersion {
+    // n = ior + b (1 / lambda^2 - 1 / lambda_d^2), with lambda in micrometers, so `ior` is kept
+    // at the D line
+    Cauchy { ior: f64, b: f64 },
+    // n^2 = 1 + sum of b lambda^2 / (lambda^2 - c), with lambda in micrometers
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let l2 = (lambda / 1000.0) * (lambda / 1000.0);
+        match self {
+            Dispersion::Cauchy { ior, b } => {
+                let d2 = (LAMBDA_D / 1000.0) * (LAMBDA_D / 1000.0);
+                ior + b * (1.0 / l2 - 1.0 / d2)
+            }
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    // index at the D line, for rays that carry no single wavelength
+    pub fn ior_d(&self) -> f64 {
+        self.ior(LAMBDA_D)
+    }
+
+    // whether the index is finite and positive over the whole visible range
+    pub fn is_physical(&self) -> bool {
+        if let Dispersion::Sellmeier { c, .. } = self {
+            // poles where lambda^2 = c, in square micrometers
+            let (l0, l1) = (LAMBDA_MIN / 1000.0, LAMBDA_MAX / 1000.0);
+            if c.iter().any(|c| (l0 * l0..=l1 * l1).contains(c)) {
+                return false;
+            }
+        }
+        (LAMBDA_MIN as usize..=LAMBDA_MAX as usize).all(|lambda| {
+            let ior = self.ior(lambda as f64);
+            ior.is_finite() && ior > 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wavelength_to_rgb() {
+        let n = 10000;
+        let mut mean = Color::zero();
+        for i in 0..n {
+            let lambda = sample_wavelength((i as f64 + 0.5) / n as f64);
+            let rgb = wavelength_to_rgb(lambda);
+            assert!(rgb.x >= 0.0 && rgb.y >= 0.0 && rgb.z >= 0.0);
+            mean += rgb / n as f64;
+        }
+        assert!((mean - Color::ones()).length() < 1e-3);
+        // red, green and blue ends of the spectrum
+        let (red, green, blue) = (
+            wavelength_to_rgb(650.0),
+            wavelength_to_rgb(530.0),
+            wavelength_to_rgb(450.0),
+        );
+        assert!(red.x > red.y && red.x > red.z);
+        assert!(green.y > green.x && green.y > green.z);
+        assert!(blue.z > blue.x && blue.z > blue.y);
+    }
+
+    #[test]
+    fn test_upsampling() {
+        let mut lambda = hero_wavelengths(LAMBDA_MAX - 1.0);
+        assert!((lambda.y - (LAMBDA_MIN - 1.0 + (LAMBDA_MAX - LAMBDA_MIN) / 3.0)).abs() < 1e-9);
+        for &c in &[
+            Color::ones(),
+            Color::new(0.8, 0.1, 0.1),
+            Color::new(0.1, 0.8, 0.1),
+            Color::new(0.1, 0.1, 0.8),
+            Color::new(0.3, 0.5, 0.7),
+        ] {
+            // round trip through the film
+            let n = 3000;
+            let mut xyz = Color::zero();
+            for i in 0..n {
+                lambda = hero_wavelengths(sample_wavelength((i as f64 + 0.5) / n as f64));
+                let values = rgb_to_spectrum(&c, &lambda);
+                assert!(values.x >= 0.0 && values.x <= 1.0);
+                xyz += spectrum_to_xyz(&values, &lambda) / n as f64;
+            }
+            assert!((xyz_to_film(&xyz) - c).length() < 0.05);
+        }
+        let flat = film_color(|_| 1.0);
+        assert!((flat - Color::ones()).length() < 1e-3);
+    }
+
+    #[test]
+    fn test_planck() {
+        // Wien's displacement law
+        let peak = (300..1500)
+            .map(|l| l as f64)
+            .max_by(|a, b| planck(*a, 5000.0).partial_cmp(&planck(*b, 5000.0)).unwrap())
+            .unwrap();
+        assert!((peak - 2.897_771_955e6 / 5000.0).abs() < 1.0);
+        // low temperatures glow red, high ones blue
+        let warm = film_color(|l| planck(l, 2000.0));
+        let cold = film_color(|l| planck(l, 12000.0));
+        assert!(warm.x > warm.z && cold.z > cold.x);
+    }
+
+    #[test]
+    fn test_conductor_spectrum() {
+        let gold = conductor_spectrum("gold").unwrap();
+        assert_eq!(interpolate_ior(gold, 300.0), (1.66, 1.96));
+        assert_eq!(interpolate_ior(gold, 800.0), (0.13, 3.84));
+        let (eta, k) = interpolate_ior(gold, 425.0);
+        assert!((eta - 1.58).abs() < 1e-12 && (k - 1.92).abs() < 1e-12);
+        assert!(conductor_spectrum("tin").is_none());
+    }
+
+    #[test]
+    fn test_dispersion() {
+        let cauchy = Dispersion::Cauchy {
+            ior: 1.5,
+            b: 0.0042,
+        };
+        assert!((cauchy.ior_d() - 1.5).abs() < 1e-12);
+        // BK7 glass
+        let sellmeier = Dispersion::Sellmeier {
+            b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        };
+        assert!((sellmeier.ior_d() - 1.5168).abs() < 1e-3);
+        for d in &[cauchy, sellmeier] {
+            // normal dispersion, blue bends more than red
+            assert!(d.ior(450.0) > d.ior_d() && d.ior_d() > d.ior(650.0));
+            assert!(d.is_physical());
+        }
+        // an index that turns negative in the blue, and a pole in the visible range
+        assert!(!Dispersion::Cauchy { ior: 1.5, b: -0.5 }.is_physical());
+        assert!(!Dispersion::Sellmeier {
+            b: [1.0, 0.0, 0.0],
+            c: [0.25, 0.0, 0.0],
+        }
+        .is_physical());
+    }
+}
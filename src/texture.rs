@@ -1,4 +1,9 @@
-use crate::vec3::{Color, Vec3};
+use crate::{
+    noise::{Perlin, Worley},
+    rtweekend::clamp,
+    vec3::{Color, Vec3},
+};
+use image::GenericImageView;
 use std::sync::Arc;
 
 pub trait Texture: Send + Sync {
@@ -38,3 +43,327 @@ impl Texture for CheckerTexture {
         self.even.value(u, v, p)
     }
 }
+
+// Procedural patterns below pick a weight in [0, 1] and blend `low` into `high` by it, so any
+// other texture can be laid out by them.
+fn blend(
+    low: &Arc<dyn Texture>,
+    high: &Arc<dyn Texture>,
+    t: f64,
+    u: f64,
+    v: f64,
+    p: &Vec3,
+) -> Color {
+    low.value(u, v, p) * (1.0 - t) + high.value(u, v, p) * t
+}
+
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    // frequency of the pattern in world space
+    pub scale: f64,
+    pub low: Arc<dyn Texture>,
+    pub high: Arc<dyn Texture>,
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let t = 0.5 * (1.0 + self.noise.noise(&(*p * self.scale)));
+        blend(&self.low, &self.high, clamp(t, 0.0, 1.0), u, v, p)
+    }
+}
+
+// fractal Brownian motion, for clouds and rough surfaces
+pub struct TurbulenceTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+    pub octaves: u32,
+    pub low: Arc<dyn Texture>,
+    pub high: Arc<dyn Texture>,
+}
+
+impl Texture for TurbulenceTexture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let t = self.noise.turb(&(*p * self.scale), self.octaves);
+        blend(&self.low, &self.high, clamp(t, 0.0, 1.0), u, v, p)
+    }
+}
+
+// stripes along z bent by turbulence
+pub struct MarbleTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+    pub octaves: u32,
+    // how far the turbulence pushes the stripes
+    pub strength: f64,
+    pub low: Arc<dyn Texture>,
+    pub high: Arc<dyn Texture>,
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let q = *p * self.scale;
+        let phase = q.z + self.strength * self.noise.turb(&q, self.octaves);
+        blend(&self.low, &self.high, 0.5 * (1.0 + phase.sin()), u, v, p)
+    }
+}
+
+// rings around the y axis, one per unit of scaled distance, warped by noise
+pub struct WoodTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+    pub strength: f64,
+    pub low: Arc<dyn Texture>,
+    pub high: Arc<dyn Texture>,
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let q = *p * self.scale;
+        let r = (q.x * q.x + q.z * q.z).sqrt() + self.strength * self.noise.noise(&q);
+        // sharp at the start of a ring, fading out towards the next
+        let t = (r - r.floor()).powi(2);
+        blend(&self.low, &self.high, t, u, v, p)
+    }
+}
+
+// cells, `low` at their centers and `high` towards their borders
+pub struct WorleyTexture {
+    pub noise: Worley,
+    pub scale: f64,
+    pub low: Arc<dyn Texture>,
+    pub high: Arc<dyn Texture>,
+}
+
+impl Texture for WorleyTexture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let t = self.noise.distance(&(*p * self.scale));
+        blend(&self.low, &self.high, clamp(t, 0.0, 1.0), u, v, p)
+    }
+}
+
+// how texel coordinates outside the image are brought back into it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+// sRGB transfer function, decoded so that textures are filtered and shaded in linear light
+pub fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Image mapped onto `u` and `v` in [0, 1], with `v` running from the bottom row to the top one.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    // linear colors, row by row from the top
+    data: Vec<Color>,
+    pub wrap: Wrap,
+    pub filter: Filter,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, data: Vec<Color>, wrap: Wrap, filter: Filter) -> Self {
+        assert_eq!(
+            data.len(),
+            width * height,
+            "image size does not match its data"
+        );
+        Self {
+            width,
+            height,
+            data,
+            wrap,
+            filter,
+        }
+    }
+
+    // any format the `image` crate reads, assumed to be sRGB encoded
+    pub fn load(path: &str, wrap: Wrap, filter: Filter) -> Result<Self, String> {
+        let img = match image::open(path) {
+            Ok(img) => img,
+            Err(err) => return Err(format!("{}: {}", path, err)),
+        };
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        if width == 0 || height == 0 {
+            return Err(format!("{}: empty image", path));
+        }
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = img.get_pixel(x as u32, y as u32);
+                let channel = |i: usize| srgb_to_linear(pixel[i] as f64 / 255.0);
+                data.push(Color::new(channel(0), channel(1), channel(2)));
+            }
+        }
+        Ok(Self::new(width, height, data, wrap, filter))
+    }
+
+    // index of texel `i` along an axis with `n` texels
+    fn address(&self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let i = match self.wrap {
+            Wrap::Repeat => i.rem_euclid(n),
+            Wrap::Clamp => i.max(0).min(n - 1),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * n);
+                if i < n {
+                    i
+                } else {
+                    2 * n - 1 - i
+                }
+            }
+        };
+        i as usize
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        self.data[self.address(y, self.height) * self.width + self.address(x, self.width)]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Vec3) -> Color {
+        let x = u * self.width as f64;
+        let y = (1.0 - v) * self.height as f64;
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                // texel centers sit at half-integer coordinates
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = self.texel(x0, y0) * (1.0 - fx) + self.texel(x0 + 1, y0) * fx;
+                let bottom = self.texel(x0, y0 + 1) * (1.0 - fx) + self.texel(x0 + 1, y0 + 1) * fx;
+                top * (1.0 - fy) + bottom * fy
+            }
+        }
+    }
+}
+
+#[allow(clippy::float_cmp)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2x2 image with distinct gray levels
+    fn texture(wrap: Wrap, filter: Filter) -> ImageTexture {
+        let data = [0.0, 1.0, 2.0, 3.0]
+            .iter()
+            .map(|&x| Color::new(x, x, x))
+            .collect();
+        ImageTexture::new(2, 2, data, wrap, filter)
+    }
+
+    #[test]
+    fn test_srgb_to_linear() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-12);
+        assert!((srgb_to_linear(0.5) - 0.214041).abs() < 1e-6);
+        // continuous where the two pieces meet
+        assert!((srgb_to_linear(0.04045) - srgb_to_linear(0.04045 + 1e-9)).abs() < 1e-8);
+    }
+
+    #[test]
+    fn test_addressing() {
+        let p = Vec3::zero();
+        let nearest = texture(Wrap::Repeat, Filter::Nearest);
+        // top left is texel 0, bottom right texel 3
+        assert_eq!(nearest.value(0.25, 0.75, &p).x, 0.0);
+        assert_eq!(nearest.value(0.75, 0.25, &p).x, 3.0);
+        assert_eq!(nearest.value(1.25, 0.75, &p).x, 0.0);
+        assert_eq!(nearest.value(-0.25, 0.75, &p).x, 1.0);
+        let clamp = texture(Wrap::Clamp, Filter::Nearest);
+        assert_eq!(clamp.value(1.75, 0.75, &p).x, 1.0);
+        assert_eq!(clamp.value(-3.0, -3.0, &p).x, 2.0);
+        let mirror = texture(Wrap::Mirror, Filter::Nearest);
+        assert_eq!(mirror.value(1.25, 0.75, &p).x, 1.0);
+        assert_eq!(mirror.value(1.75, 0.75, &p).x, 0.0);
+        assert_eq!(mirror.value(-0.25, 0.75, &p).x, 0.0);
+    }
+
+    #[test]
+    fn test_bilinear() {
+        let p = Vec3::zero();
+        let clamp = texture(Wrap::Clamp, Filter::Bilinear);
+        assert_eq!(clamp.value(0.25, 0.75, &p).x, 0.0);
+        assert_eq!(clamp.value(0.5, 0.5, &p).x, 1.5);
+        assert_eq!(clamp.value(0.5, 0.75, &p).x, 0.5);
+        assert_eq!(clamp.value(0.0, 0.75, &p).x, 0.0);
+        // repeating blends the edge with the opposite side
+        let repeat = texture(Wrap::Repeat, Filter::Bilinear);
+        assert_eq!(repeat.value(0.0, 0.75, &p).x, 0.5);
+    }
+
+    #[test]
+    fn test_procedural() {
+        let solid = |x: f64| -> Arc<dyn Texture> {
+            Arc::new(ConstTexture {
+                color_value: Color::new(x, x, x),
+            })
+        };
+        let textures: Vec<Box<dyn Texture>> = vec![
+            Box::new(NoiseTexture {
+                noise: Perlin::new(1),
+                scale: 4.0,
+                low: solid(0.2),
+                high: solid(0.8),
+            }),
+            Box::new(TurbulenceTexture {
+                noise: Perlin::new(1),
+                scale: 4.0,
+                octaves: 7,
+                low: solid(0.2),
+                high: solid(0.8),
+            }),
+            Box::new(MarbleTexture {
+                noise: Perlin::new(1),
+                scale: 4.0,
+                octaves: 7,
+                strength: 10.0,
+                low: solid(0.2),
+                high: solid(0.8),
+            }),
+            Box::new(WoodTexture {
+                noise: Perlin::new(1),
+                scale: 4.0,
+                strength: 0.5,
+                low: solid(0.2),
+                high: solid(0.8),
+            }),
+            Box::new(WorleyTexture {
+                noise: Worley::new(1),
+                scale: 4.0,
+                low: solid(0.2),
+                high: solid(0.8),
+            }),
+        ];
+        for texture in &textures {
+            let (mut min, mut max) = (1.0_f64, 0.0_f64);
+            for i in 0..500 {
+                let p = Vec3::new(i as f64 * 0.013, (i % 17) as f64 * 0.07, i as f64 * -0.029);
+                let c = texture.value(0.0, 0.0, &p);
+                // a blend of the two grays
+                assert!(c.x == c.y && c.y == c.z);
+                assert!(c.x >= 0.2 - 1e-12 && c.x <= 0.8 + 1e-12);
+                min = min.min(c.x);
+                max = max.max(c.x);
+            }
+            // and not a constant one
+            assert!(max - min > 0.2);
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, HitTable},
+    matrix::{Mat3, Mat4, Quat},
+    ray::Ray,
+    rtweekend::{fmax, fmin},
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
+use std::sync::Arc;
+
+// Places an object in the world with an affine transform. The object is only referenced, so one
+// mesh can be instanced many times without copying its triangles.
+pub struct Transform {
+    pub object: Arc<dyn HitTable>,
+    pub to_world: Mat4,
+    to_object: Mat4,
+    // inverse transpose of the linear part, which keeps normals perpendicular to the surface
+    normal_matrix: Mat3,
+    // cube root of the volume scale, exact for rotations and uniform scales
+    scale: f64,
+    // whether angles are preserved, which light sampling relies on
+    conformal: bool,
+}
+
+impl Transform {
+    // `to_world` must be invertible
+    pub fn new(object: Arc<dyn HitTable>, to_world: Mat4) -> Self {
+        let to_object = to_world.inverse().expect("transform must be invertible");
+        let linear = to_world.linear();
+        let scale = linear.determinant().abs().cbrt();
+        // angles are kept when the columns are perpendicular and equally long
+        let gram = linear.transpose() * linear;
+        let mut conformal = true;
+        for i in 0..3 {
+            for j in 0..3 {
+                let expected = if i == j { scale * scale } else { 0.0 };
+                conformal &= (gram.m[i][j] - expected).abs() < 1e-9 * scale * scale;
+            }
+        }
+        Self {
+            object,
+            to_world,
+            normal_matrix: to_object.linear().transpose(),
+            to_object,
+            scale,
+            conformal,
+        }
+    }
+
+    fn ray_to_object(&self, r: &Ray) -> Ray {
+        Ray {
+            orig: self.to_object.transform_point(&r.orig),
+            dir: self.to_object.transform_vector(&r.dir),
+            time: r.time,
+            wavelength: r.wavelength,
+        }
+    }
+}
+
+impl HitTable for Transform {
+    // the direction is not renormalized, so `t` is the same in both spaces
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.object.hit(&self.ray_to_object(r), t_min, t_max, rec) {
+            return false;
+        }
+        rec.p = self.to_world.transform_point(&rec.p);
+        // the normal keeps facing the ray, so `front_face` stays valid
+        rec.normal = (self.normal_matrix * rec.normal).unit();
+        true
+    }
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut AABB) -> bool {
+        let mut object_box = AABB::new(Point3::zero(), Point3::zero());
+        if !self.object.bounding_box(t0, t1, &mut object_box) {
+            return false;
+        }
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = -min;
+        for corner in 0..8 {
+            let pick = |axis: usize| {
+                if corner & (1 << axis) == 0 {
+                    object_box._min[axis]
+                } else {
+                    object_box._max[axis]
+                }
+            };
+            let p = self
+                .to_world
+                .transform_point(&Point3::new(pick(0), pick(1), pick(2)));
+            min = Point3::new(fmin(min.x, p.x), fmin(min.y, p.y), fmin(min.z, p.z));
+            max = Point3::new(fmax(max.x, p.x), fmax(max.y, p.y), fmax(max.z, p.z));
+        }
+        *output_box = AABB::new(min, max);
+        true
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        self.object
+            .distance(&self.to_object.transform_point(other_center))
+            * self.scale
+    }
+    // solid angles only carry over when the transform preserves angles, other emitters are left
+    // to BSDF sampling
+    fn is_light(&self) -> bool {
+        self.conformal && self.object.is_light()
+    }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        self.object.pdf_value(
+            &self.to_object.transform_point(o),
+            &self.to_object.transform_vector(v),
+        )
+    }
+    fn random(&self, o: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let dir = self
+            .object
+            .random(&self.to_object.transform_point(o), sampler);
+        self.to_world.transform_vector(&dir)
+    }
+}
+
+// Placement of an animated object at one moment: scaled, then rotated, then translated.
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    pub fn identity() -> Self {
+        Self {
+            translation: Vec3::zero(),
+            rotation: Quat::identity(),
+            scale: Vec3::ones(),
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::translate(&self.translation)
+            * Mat4::from_parts(&self.rotation.to_mat3(), &Vec3::zero())
+            * Mat4::scale(&self.scale)
+    }
+
+    // the parts are interpolated separately so that rotations stay rigid
+    pub fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            translation: self.translation + (other.translation - self.translation) * t,
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+}
+
+// Object moving from the `start` placement at `time0` to `end` at `time1`, and resting outside
+// that interval. Scales must not pass through zero. Moving emitters are not sampled as lights.
+pub struct AnimatedTransform {
+    pub object: Arc<dyn HitTable>,
+    pub start: Keyframe,
+    pub end: Keyframe,
+    pub time0: f64,
+    pub time1: f64,
+}
+
+impl AnimatedTransform {
+    // steps the motion is split into when bounding it
+    const BOX_STEPS: usize = 32;
+
+    pub fn new(
+        object: Arc<dyn HitTable>,
+        start: Keyframe,
+        end: Keyframe,
+        time0: f64,
+        time1: f64,
+    ) -> Self {
+        Self {
+            object,
+            start,
+            end,
+            time0,
+            time1,
+        }
+    }
+
+    pub fn at(&self, time: f64) -> Keyframe {
+        if time <= self.time0 {
+            return self.start;
+        }
+        if time >= self.time1 {
+            return self.end;
+        }
+        self.start
+            .lerp(&self.end, (time - self.time0) / (self.time1 - self.time0))
+    }
+}
+
+impl HitTable for AnimatedTransform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let to_world = self.at(r.time).matrix();
+        let to_object = match to_world.inverse() {
+            Some(m) => m,
+            None => return false,
+        };
+        let local = Ray {
+            orig: to_object.transform_point(&r.orig),
+            dir: to_object.transform_vector(&r.dir),
+            time: r.time,
+            wavelength: r.wavelength,
+        };
+        if !self.object.hit(&local, t_min, t_max, rec) {
+            return false;
+        }
+        rec.p = to_world.transform_point(&rec.p);
+        rec.normal = (to_object.linear().transpose() * rec.normal).unit();
+        true
+    }
+    // Union of the boxes at evenly spaced times. Between two of them a corner moves along an arc
+    // no longer than about the chord, so padding by the longest chord covers the whole motion.
+    fn bounding_box(&self, t0: f64, t1: f64, output_box: &mut AABB) -> bool {
+        let mut object_box = AABB::new(Point3::zero(), Point3::zero());
+        if !self.object.bounding_box(t0, t1, &mut object_box) {
+            return false;
+        }
+        let corners: Vec<Point3> = (0..8)
+            .map(|corner| {
+                let pick = |axis: usize| {
+                    if corner & (1 << axis) == 0 {
+                        object_box._min[axis]
+                    } else {
+                        object_box._max[axis]
+                    }
+                };
+                Point3::new(pick(0), pick(1), pick(2))
+            })
+            .collect();
+        let (t0, t1) = (
+            fmin(fmax(t0, self.time0), self.time1),
+            fmin(fmax(t1, self.time0), self.time1),
+        );
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = -min;
+        let mut pad: f64 = 0.0;
+        let mut previous: Option<Vec<Point3>> = None;
+        for step in 0..=Self::BOX_STEPS {
+            let time = t0 + (t1 - t0) * step as f64 / Self::BOX_STEPS as f64;
+            let to_world = self.at(time).matrix();
+            let moved: Vec<Point3> = corners
+                .iter()
+                .map(|c| to_world.transform_point(c))
+                .collect();
+            for p in &moved {
+                min = Point3::new(fmin(min.x, p.x), fmin(min.y, p.y), fmin(min.z, p.z));
+                max = Point3::new(fmax(max.x, p.x), fmax(max.y, p.y), fmax(max.z, p.z));
+            }
+            if let Some(previous) = &previous {
+                for (a, b) in previous.iter().zip(&moved) {
+                    pad = pad.max(a.distance(*b));
+                }
+            }
+            previous = Some(moved);
+        }
+        let pad = Vec3::new(pad, pad, pad);
+        *output_box = AABB::new(min - pad, max + pad);
+        true
+    }
+    // measured at the start of the motion
+    fn distance(&self, other_center: &Point3) -> f64 {
+        let to_world = self.start.matrix();
+        let to_object = match to_world.inverse() {
+            Some(m) => m,
+            None => return f64::INFINITY,
+        };
+        let s = self.start.scale;
+        self.object
+            .distance(&to_object.transform_point(other_center))
+            * (s.x * s.y * s.z).abs().cbrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::Sphere,
+        material::{DiffuseLight, Lambertian},
+        matrix::Quat,
+        sampler::IndependentSampler,
+        vec3::Color,
+    };
+
+    #[test]
+    fn test_transformed_sphere() {
+        let mat = Arc::new(Lambertian::new(Color::ones()));
+        let sphere: Arc<dyn HitTable> = Arc::new(Sphere::new(Point3::zero(), 1.0, mat.clone()));
+        let moved = Transform::new(
+            sphere.clone(),
+            Mat4::translate(&Vec3::new(0.0, 0.0, -5.0)) * Mat4::scale(&Vec3::new(2.0, 1.0, 1.0)),
+        );
+        let mut rec = HitRecord::new(mat);
+        let r = Ray {
+            orig: Point3::new(-10.0, 0.0, -5.0),
+            dir: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        assert!(moved.hit(&r, 0.001, f64::INFINITY, &mut rec));
+        assert!((rec.t - 8.0).abs() < 1e-9);
+        assert!((rec.p - Point3::new(-2.0, 0.0, -5.0)).length() < 1e-9);
+        assert!((rec.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-9);
+        assert!(rec.front_face);
+        // the normal of the stretched sphere is not the stretched normal
+        let r = Ray {
+            orig: Point3::new(2.0 * 0.7_f64.cos(), 5.0, -5.0),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+            wavelength: None,
+        };
+        assert!(moved.hit(&r, 0.001, f64::INFINITY, &mut rec));
+        assert!((rec.p.y - 0.7_f64.sin()).abs() < 1e-9);
+        let expected = Vec3::new(rec.p.x / 4.0, rec.p.y, 0.0).unit();
+        assert!((rec.normal - expected).length() < 1e-9);
+
+        let mut bbox = AABB::new(Point3::zero(), Point3::zero());
+        assert!(moved.bounding_box(0.0, 1.0, &mut bbox));
+        assert!((bbox._min - Point3::new(-2.0, -1.0, -6.0)).length() < 1e-9);
+        assert!((bbox._max - Point3::new(2.0, 1.0, -4.0)).length() < 1e-9);
+        assert!(!moved.is_light());
+    }
+
+    #[test]
+    fn test_transformed_light() {
+        let lamp = Arc::new(DiffuseLight::new(Color::ones()));
+        let sphere = Arc::new(Sphere::new(Point3::zero(), 1.0, lamp));
+        let to_world = Mat4::translate(&Vec3::new(3.0, 0.0, 0.0))
+            * Mat4::from_parts(
+                &Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 40.0).to_mat3(),
+                &Vec3::zero(),
+            )
+            * Mat4::scale(&Vec3::new(2.0, 2.0, 2.0));
+        let moved = Transform::new(sphere, to_world);
+        let reference = Sphere::new(
+            Point3::new(3.0, 0.0, 0.0),
+            2.0,
+            Arc::new(DiffuseLight::new(Color::ones())),
+        );
+        assert!(moved.is_light());
+        let o = Point3::new(-4.0, 1.0, 0.5);
+        let mut sampler = IndependentSampler::new(5);
+        for i in 0..20 {
+            sampler.start_sample(0, 0, i);
+            let v = moved.random(&o, &mut sampler);
+            let expected = reference.pdf_value(&o, &v);
+            assert!(expected > 0.0);
+            assert!((moved.pdf_value(&o, &v) - expected).abs() < 1e-6 * expected);
+        }
+        assert!((moved.distance(&Point3::new(8.0, 0.0, 0.0)) - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_animated_transform() {
+        let mat = Arc::new(Lambertian::new(Color::ones()));
+        let sphere = Arc::new(Sphere::new(Point3::new(1.0, 0.0, 0.0), 0.5, mat.clone()));
+        let start = Keyframe::identity();
+        let end = Keyframe {
+            translation: Vec3::new(0.0, 0.0, -4.0),
+            rotation: Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 90.0),
+            scale: Vec3::ones(),
+        };
+        let moving = AnimatedTransform::new(sphere, start, end, 0.0, 1.0);
+        let mut rec = HitRecord::new(mat);
+        // at the end the sphere has turned to x = 0, z = -1 and moved to z = -5
+        let r = Ray {
+            orig: Point3::new(0.0, 5.0, -5.0),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time: 1.0,
+            wavelength: None,
+        };
+        assert!(moving.hit(&r, 0.001, f64::INFINITY, &mut rec));
+        assert!((rec.p - Point3::new(0.0, 0.5, -5.0)).length() < 1e-9);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+        let r = Ray { time: 0.0, ..r };
+        assert!(!moving.hit(&r, 0.001, f64::INFINITY, &mut rec));
+        // half way the center is at 45 degrees, two units along
+        let c = Point3::new(0.5_f64.sqrt(), 0.0, -2.0 - 0.5_f64.sqrt());
+        let r = Ray {
+            orig: c + Vec3::new(0.0, 5.0, 0.0),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.5,
+            wavelength: None,
+        };
+        assert!(moving.hit(&r, 0.001, f64::INFINITY, &mut rec));
+        assert!((rec.p - (c + Vec3::new(0.0, 0.5, 0.0))).length() < 1e-9);
+
+        // every moment of the motion lies inside the box
+        let mut bbox = AABB::new(Point3::zero(), Point3::zero());
+        assert!(moving.bounding_box(0.0, 1.0, &mut bbox));
+        for step in 0..=100 {
+            let time = step as f64 / 100.0;
+            let c = moving
+                .at(time)
+                .matrix()
+                .transform_point(&Point3::new(1.0, 0.0, 0.0));
+            for i in 0..3 {
+                assert!(c[i] - 0.5 >= bbox._min[i] && c[i] + 0.5 <= bbox._max[i]);
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, HitTable},
+    material::Material,
+    ray::Ray,
+    rtweekend::{fmax, fmin},
+    vec3::{Point3, Vec3},
+};
+use std::sync::Arc;
+
+pub struct Triangle {
+    pub v: [Point3; 3],
+    // per-vertex shading normals, the geometric normal is used without them
+    pub n: Option<[Vec3; 3]>,
+    // per-vertex texture coordinates, the barycentric coordinates are used without them
+    pub uv: Option<[(f64, f64); 3]>,
+    pub mat_ptr: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, m: Arc<dyn Material>) -> Self {
+        Self {
+            v: [v0, v1, v2],
+            n: None,
+            uv: None,
+            mat_ptr: m,
+        }
+    }
+}
+
+// Moller-Trumbore intersection, returns t and the barycentric coordinates of v1 and v2
+pub fn hit_triangle(
+    v0: &Point3,
+    v1: &Point3,
+    v2: &Point3,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let e1 = *v1 - *v0;
+    let e2 = *v2 - *v0;
+    let pvec = r.dir.cross(e2);
+    let det = e1 * pvec;
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = r.orig - *v0;
+    let b1 = (tvec * pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = tvec.cross(e1);
+    let b2 = (r.dir * qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = (e2 * qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+// fill in a hit record from the barycentric coordinates of a triangle hit
+#[allow(clippy::too_many_arguments)]
+pub fn set_triangle_hit(
+    rec: &mut HitRecord,
+    r: &Ray,
+    v: [&Point3; 3],
+    n: Option<[&Vec3; 3]>,
+    uv: Option<[&(f64, f64); 3]>,
+    t: f64,
+    b1: f64,
+    b2: f64,
+) {
+    let b0 = 1.0 - b1 - b2;
+    rec.t = t;
+    rec.p = r.at(t);
+    let geometric = (*v[1] - *v[0]).cross(*v[2] - *v[0]).unit();
+    rec.front_face = r.dir * geometric < 0.0;
+    let shading = match n {
+        Some(n) => {
+            let interpolated = *n[0] * b0 + *n[1] * b1 + *n[2] * b2;
+            if interpolated.squared_length() > 0.0 {
+                interpolated.unit()
+            } else {
+                geometric
+            }
+        }
+        None => geometric,
+    };
+    // orient the shading normal like the geometric one, then flip both towards the ray
+    let shading = if shading * geometric < 0.0 {
+        -shading
+    } else {
+        shading
+    };
+    rec.normal = if rec.front_face { shading } else { -shading };
+    match uv {
+        Some(uv) => {
+            rec.u = uv[0].0 * b0 + uv[1].0 * b1 + uv[2].0 * b2;
+            rec.v = uv[0].1 * b0 + uv[1].1 * b1 + uv[2].1 * b2;
+        }
+        None => {
+            rec.u = b1;
+            rec.v = b2;
+        }
+    }
+}
+
+pub fn triangle_box(v0: &Point3, v1: &Point3, v2: &Point3) -> AABB {
+    // pad flat boxes, so that axis-aligned triangles still have a volume
+    let pad = 0.0001;
+    AABB::new(
+        Point3::new(
+            fmin(v0.x, fmin(v1.x, v2.x)) - pad,
+            fmin(v0.y, fmin(v1.y, v2.y)) - pad,
+            fmin(v0.z, fmin(v1.z, v2.z)) - pad,
+        ),
+        Point3::new(
+            fmax(v0.x, fmax(v1.x, v2.x)) + pad,
+            fmax(v0.y, fmax(v1.y, v2.y)) + pad,
+            fmax(v0.z, fmax(v1.z, v2.z)) + pad,
+        ),
+    )
+}
+
+// closest point on a triangle, from Real-Time Collision Detection 5.1.5
+pub fn closest_point_on_triangle(p: &Point3, a: &Point3, b: &Point3, c: &Point3) -> Point3 {
+    let ab = *b - *a;
+    let ac = *c - *a;
+    let ap = *p - *a;
+    let d1 = ab * ap;
+    let d2 = ac * ap;
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return *a;
+    }
+    let bp = *p - *b;
+    let d3 = ab * bp;
+    let d4 = ac * bp;
+    if d3 >= 0.0 && d4 <= d3 {
+        return *b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return *a + ab * (d1 / (d1 - d3));
+    }
+    let cp = *p - *c;
+    let d5 = ab * cp;
+    let d6 = ac * cp;
+    if d6 >= 0.0 && d5 <= d6 {
+        return *c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return *a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return *b + (*c - *b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = 1.0 / (va + vb + vc);
+    *a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+impl HitTable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        match hit_triangle(&self.v[0], &self.v[1], &self.v[2], r, t_min, t_max) {
+            Some((t, b1, b2)) => {
+                let n = self.n.as_ref().map(|n| [&n[0], &n[1], &n[2]]);
+                let uv = self.uv.as_ref().map(|uv| [&uv[0], &uv[1], &uv[2]]);
+                let v = [&self.v[0], &self.v[1], &self.v[2]];
+                set_triangle_hit(rec, r, v, n, uv, t, b1, b2);
+                rec.mat_ptr = self.mat_ptr.clone();
+                true
+            }
+            None => false,
+        }
+    }
+    fn bounding_box(&self, _t0: f64, _t1: f64, output_box: &mut AABB) -> bool {
+        *output_box = triangle_box(&self.v[0], &self.v[1], &self.v[2]);
+        true
+    }
+    fn distance(&self, other_center: &Point3) -> f64 {
+        other_center.distance(closest_point_on_triangle(
+            other_center,
+            &self.v[0],
+            &self.v[1],
+            &self.v[2],
+        ))
+    }
+}
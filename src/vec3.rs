@@ -1,4 +1,4 @@
-use crate::rtweekend::random_double;
+use crate::rtweekend::Rng;
 use std::{
     f64::consts::PI,
     ops::{Add, AddAssign, Div, Index, Mul, MulAssign, Neg, Sub, SubAssign},
@@ -70,32 +70,19 @@ impl Vec3 {
     }
 }
 
-pub fn randomvec() -> Vec3 {
+pub fn randomvec(rng: &mut Rng) -> Vec3 {
     Vec3::new(
-        random_double(0.0, 1.0),
-        random_double(0.0, 1.0),
-        random_double(0.0, 1.0),
+        rng.random_double(0.0, 1.0),
+        rng.random_double(0.0, 1.0),
+        rng.random_double(0.0, 1.0),
     )
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let p = Vec3 {
-            x: random_double(-1.0, 1.0),
-            y: random_double(-1.0, 1.0),
-            z: random_double(-1.0, 1.0),
-        };
-        if p.squared_length() >= 1.0 {
-            continue;
-        }
-        return p;
-    }
-}
-
-pub fn random_unit_vector() -> Vec3 {
-    let a = random_double(0.0, 2.0 * PI);
-    let zz = random_double(-1.0, 1.0);
-    let r = (1.0 - zz * zz).sqrt();
+// uniform direction from a 2D sample
+pub fn sample_unit_vector(u: (f64, f64)) -> Vec3 {
+    let a = 2.0 * PI * u.0;
+    let zz = 1.0 - 2.0 * u.1;
+    let r = (1.0 - zz * zz).max(0.0).sqrt();
     Vec3 {
         x: r * a.cos(),
         y: r * a.sin(),
@@ -103,14 +90,31 @@ pub fn random_unit_vector() -> Vec3 {
     }
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(random_double(-1.0, 1.0), random_double(-1.0, 1.0), 0.0);
-        if p.squared_length() >= 1.0 {
-            continue;
-        }
-        return p;
-    }
+// uniform point in the unit ball, from a direction sample and a radius sample
+pub fn sample_in_unit_sphere(u: (f64, f64), w: f64) -> Vec3 {
+    sample_unit_vector(u) * w.cbrt()
+}
+
+// uniform point in the unit disk, with the concentric mapping so that strata stay compact
+pub fn sample_in_unit_disk(u: (f64, f64)) -> Vec3 {
+    let a = 2.0 * u.0 - 1.0;
+    let b = 2.0 * u.1 - 1.0;
+    if a == 0.0 && b == 0.0 {
+        return Vec3::zero();
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+// cosine-weighted direction around +z, by lifting a uniform disk sample onto the hemisphere
+pub fn sample_cosine_direction(u: (f64, f64)) -> Vec3 {
+    let d = sample_in_unit_disk(u);
+    let z = (1.0 - d.x * d.x - d.y * d.y).max(0.0).sqrt();
+    Vec3::new(d.x, d.y, z)
 }
 
 pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {